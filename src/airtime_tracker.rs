@@ -37,11 +37,6 @@ pub async fn track_airtime(
         priority: (-1).into(),
     };
 
-    let mut stream = tuner_manager.send(StartStreamingMessage {
-        channel: channel.clone(),
-        user
-    }).await??;
-
     let template = mustache::compile_str(command)?;
     let data = mustache::MapBuilder::new()
         .insert("sid", &program.quad.sid())?
@@ -49,6 +44,12 @@ pub async fn track_airtime(
         .build();
     let cmd = template.render_data_to_string(&data)?;
 
+    let mut stream = tuner_manager.send(StartStreamingMessage {
+        channel: channel.clone(),
+        user,
+        filters: vec![cmd.clone()],
+    }).await??;
+
     let mut pipeline = command_util::spawn_pipeline(
         vec![cmd], stream.id())?;
 
@@ -1,6 +1,8 @@
 use std::fmt;
 use std::io;
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::time::{Duration, Instant};
 
 use actix::prelude::*;
@@ -18,13 +20,62 @@ use crate::tuner::TunerSubscriptionId as SubscriberId;
 struct Subscriber {
     id: SubscriberId,
     sender: mpsc::Sender<Bytes>,
+    // Shared with the tuner manager for the Web API.
+    bytes_sent: Arc<AtomicU64>,
+}
+
+// Statistics shared with the tuner manager for the Web API.
+pub struct BroadcasterStats {
+    bytes_per_second: AtomicU64,
+    dropped_chunks: AtomicU64,
+    last_received: Mutex<Instant>,
+    timed_out: AtomicBool,
+}
+
+impl BroadcasterStats {
+    pub fn new() -> Self {
+        BroadcasterStats {
+            bytes_per_second: AtomicU64::new(0),
+            dropped_chunks: AtomicU64::new(0),
+            last_received: Mutex::new(Instant::now()),
+            timed_out: AtomicBool::new(false),
+        }
+    }
+
+    pub fn bytes_per_second(&self) -> u64 {
+        self.bytes_per_second.load(Ordering::Relaxed)
+    }
+
+    // The total number of chunks dropped for all subscribers.
+    pub fn dropped_chunks(&self) -> u64 {
+        self.dropped_chunks.load(Ordering::Relaxed)
+    }
+
+    pub fn time_since_last_packet(&self) -> Duration {
+        self.last_received.lock().unwrap().elapsed()
+    }
+
+    // Returns true if the broadcaster stopped because no packet came from the
+    // tuner within the time limit.
+    pub fn is_timed_out(&self) -> bool {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for BroadcasterStats {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 pub struct Broadcaster {
     id: BroadcasterId,
     subscribers: Vec<Subscriber>,
     time_limit: Duration,
-    last_received: Instant,
+    stats: Arc<BroadcasterStats>,
+    // For measuring the bitrate.
+    measured_at: Instant,
+    num_bytes: u64,
 }
 
 impl Broadcaster {
@@ -34,10 +85,13 @@ impl Broadcaster {
     // 32 KiB, large enough for 10 ms buffering.
     const CHUNK_SIZE: usize = 4096 * 8;
 
+    const MEASUREMENT_PERIOD: Duration = Duration::from_secs(1);
+
     pub fn new<R>(
         id: BroadcasterId,
         source: R,
         time_limit: u64,
+        stats: Arc<BroadcasterStats>,
         ctx: &mut Context<Self>,
     ) -> Self
     where
@@ -49,13 +103,19 @@ impl Broadcaster {
             id,
             subscribers: Vec::new(),
             time_limit: Duration::from_millis(time_limit),
-            last_received: Instant::now(),
+            stats,
+            measured_at: Instant::now(),
+            num_bytes: 0,
         }
     }
 
-    fn subscribe(&mut self, id: SubscriberId) -> BroadcasterStream {
+    fn subscribe(
+        &mut self,
+        id: SubscriberId,
+        bytes_sent: Arc<AtomicU64>,
+    ) -> BroadcasterStream {
         let (sender, receiver) = mpsc::channel(Self::MAX_CHUNKS);
-        self.subscribers.push(Subscriber { id, sender });
+        self.subscribers.push(Subscriber { id, sender, bytes_sent });
         BroadcasterStream::new(receiver)
     }
 
@@ -69,12 +129,15 @@ impl Broadcaster {
             let chunk_size = chunk.len();
             match subscriber.sender.try_send(chunk.clone()) {
                 Ok(_) => {
+                    subscriber.bytes_sent.fetch_add(
+                        chunk_size as u64, Ordering::Relaxed);
                     log::trace!("{}: Sent a chunk of {} bytes to {}",
                                 self.id, chunk_size, subscriber.id);
                 },
                 Err(mpsc::error::TrySendError::Full(_)) => {
                     log::warn!("{}: No space for {}, drop the chunk",
                                self.id, subscriber.id);
+                    self.stats.dropped_chunks.fetch_add(1, Ordering::Relaxed);
                 }
                 Err(mpsc::error::TrySendError::Closed(_)) => {
                     log::debug!("{}: Closed by {}, wait for unsubscribe",
@@ -83,14 +146,30 @@ impl Broadcaster {
             }
         }
 
-        self.last_received = Instant::now();
+        self.measure(chunk.len());
+    }
+
+    fn measure(&mut self, chunk_size: usize) {
+        let now = Instant::now();
+        *self.stats.last_received.lock().unwrap() = now;
+
+        self.num_bytes += chunk_size as u64;
+        let elapsed = now - self.measured_at;
+        if elapsed >= Self::MEASUREMENT_PERIOD {
+            let bps = self.num_bytes * 1000 / elapsed.as_millis() as u64;
+            self.stats.bytes_per_second.store(bps, Ordering::Relaxed);
+            self.measured_at = now;
+            self.num_bytes = 0;
+        }
     }
 
     fn check_timeout(&mut self, ctx: &mut Context<Self>) {
-        let elapsed = self.last_received.elapsed();
+        let elapsed = self.stats.time_since_last_packet();
         if  elapsed > self.time_limit {
             log::error!("{}: No packet from the tuner for {}, stop",
                         self.id, humantime::format_duration(elapsed));
+            self.stats.timed_out.store(true, Ordering::Relaxed);
+            self.stats.bytes_per_second.store(0, Ordering::Relaxed);
             ctx.stop();
         }
     }
@@ -112,7 +191,8 @@ impl Actor for Broadcaster {
 // subscribe
 
 pub struct SubscribeMessage {
-    pub id: SubscriberId
+    pub id: SubscriberId,
+    pub bytes_sent: Arc<AtomicU64>,
 }
 
 impl fmt::Display for SubscribeMessage {
@@ -146,7 +226,7 @@ impl Handler<SubscribeMessage> for Broadcaster {
         _: &mut Self::Context
     ) -> Self::Result {
         log::debug!("{}", msg);
-        self.subscribe(msg.id)
+        self.subscribe(msg.id, msg.bytes_sent)
     }
 }
 
@@ -244,21 +324,27 @@ mod tests {
         let (mut tx, rx) = mpsc::channel(1);
 
         let broadcaster = Broadcaster::create(|ctx| {
-            Broadcaster::new(Default::default(), DataSource(rx), 1000, ctx)
+            Broadcaster::new(
+                Default::default(), DataSource(rx), 1000, Default::default(),
+                ctx)
         });
 
+        let bytes_sent = Arc::new(AtomicU64::new(0));
         let mut stream1 = broadcaster.send(SubscribeMessage {
-            id: SubscriberId::new(Default::default(), 1)
+            id: SubscriberId::new(Default::default(), 1),
+            bytes_sent: bytes_sent.clone(),
         }).await.unwrap();
 
         let mut stream2 = broadcaster.send(SubscribeMessage {
-            id: SubscriberId::new(Default::default(), 2)
+            id: SubscriberId::new(Default::default(), 2),
+            bytes_sent: Default::default(),
         }).await.unwrap();
 
         let _ = tx.send(Bytes::from("hello")).await;
 
         let chunk = stream1.next().await;
         assert!(chunk.is_some());
+        assert_eq!(bytes_sent.load(Ordering::Relaxed), 5);
 
         let chunk = stream2.next().await;
         assert!(chunk.is_some());
@@ -269,15 +355,19 @@ mod tests {
         let (mut tx, rx) = mpsc::channel(1);
 
         let broadcaster = Broadcaster::create(|ctx| {
-            Broadcaster::new(Default::default(), DataSource(rx), 1000, ctx)
+            Broadcaster::new(
+                Default::default(), DataSource(rx), 1000, Default::default(),
+                ctx)
         });
 
         let mut stream1 = broadcaster.send(SubscribeMessage {
-            id: SubscriberId::new(Default::default(), 1)
+            id: SubscriberId::new(Default::default(), 1),
+            bytes_sent: Default::default(),
         }).await.unwrap();
 
         let mut stream2 = broadcaster.send(SubscribeMessage {
-            id: SubscriberId::new(Default::default(), 2)
+            id: SubscriberId::new(Default::default(), 2),
+            bytes_sent: Default::default(),
         }).await.unwrap();
 
         broadcaster.send(UnsubscribeMessage {
@@ -297,24 +387,31 @@ mod tests {
     async fn test_timeout() {
         let (mut tx, rx) = mpsc::channel(1);
 
-        let broadcaster = Broadcaster::create(|ctx| {
-            Broadcaster::new(Default::default(), DataSource(rx), 50, ctx)
+        let stats = Arc::new(BroadcasterStats::new());
+        let broadcaster = Broadcaster::create({
+            let stats = stats.clone();
+            move |ctx| Broadcaster::new(
+                Default::default(), DataSource(rx), 50, stats, ctx)
         });
 
         let mut stream1 = broadcaster.send(SubscribeMessage {
-            id: SubscriberId::new(Default::default(), 1)
+            id: SubscriberId::new(Default::default(), 1),
+            bytes_sent: Default::default(),
         }).await.unwrap();
 
         let _ = tx.send(Bytes::from("hello")).await;
 
         let chunk = stream1.next().await;
         assert!(chunk.is_some());
+        assert!(!stats.is_timed_out());
 
         while broadcaster.connected() {
             // Yield in order to process messages on the Broadcaster.
             tokio::task::yield_now().await;
         }
 
+        assert!(stats.is_timed_out());
+
         let _ = tx.send(Bytes::from("hello")).await;
 
         let chunk = stream1.next().await;
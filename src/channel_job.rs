@@ -0,0 +1,359 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use actix::prelude::*;
+use failure::Error;
+use humantime;
+use log;
+
+use crate::command_util::{self, CommandPipelineOutput};
+use crate::config::{CustomJobConfig, JobConfig};
+use crate::epg::EpgChannel;
+use crate::models::*;
+use crate::mpeg_ts_stream::MpegTsStreamId;
+use crate::tuner::*;
+
+// A channel job is a job which performs the same process for each channel:
+//
+//   1. Open a stream for the channel with a Job user
+//   2. Render the command template with `sids` and `xsids`
+//   3. Spawn the command and pipe the stream into it
+//   4. Process the output of the command in a job-specific handler
+//
+// `ChannelJobRunner` implements the steps 1..3 and calls a handler for the
+// step 4.  The handler receives the output-side endpoint of the command
+// pipeline, and returns a job-specific result for the channel.
+
+pub type ChannelJobOutput = CommandPipelineOutput<MpegTsStreamId>;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChannelJobOptions {
+    // The number of retries for each channel.
+    pub retries: usize,
+    // A time limit for processing each channel, including retries.
+    pub timeout: Option<Duration>,
+}
+
+impl From<&JobConfig> for ChannelJobOptions {
+    fn from(config: &JobConfig) -> Self {
+        ChannelJobOptions {
+            retries: config.retries,
+            timeout: config.channel_timeout.map(Duration::from_millis),
+        }
+    }
+}
+
+impl From<&CustomJobConfig> for ChannelJobOptions {
+    fn from(config: &CustomJobConfig) -> Self {
+        ChannelJobOptions {
+            retries: config.retries,
+            timeout: config.channel_timeout.map(Duration::from_millis),
+        }
+    }
+}
+
+pub struct ChannelJobResult<T> {
+    pub channel: EpgChannel,
+    pub result: Result<T, Error>,
+    pub attempts: usize,
+}
+
+impl<T> ChannelJobResult<T> {
+    pub fn ok(self) -> (EpgChannel, Option<T>) {
+        (self.channel, self.result.ok())
+    }
+}
+
+pub struct ChannelJobRunner {
+    label: String,
+    command: String,
+    channels: Vec<EpgChannel>,
+    stream_manager: Recipient<StartStreamingMessage>,
+    options: ChannelJobOptions,
+}
+
+impl ChannelJobRunner {
+    pub fn new(
+        label: &str,
+        command: String,
+        channels: Vec<EpgChannel>,
+        stream_manager: Recipient<StartStreamingMessage>,
+        options: ChannelJobOptions,
+    ) -> Self {
+        ChannelJobRunner {
+            label: label.to_string(),
+            command,
+            channels,
+            stream_manager,
+            options,
+        }
+    }
+
+    pub async fn run<T, F, Fut>(self, mut handler: F) -> Vec<ChannelJobResult<T>>
+    where
+        F: FnMut(EpgChannel, ChannelJobOutput) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut results = Vec::with_capacity(self.channels.len());
+
+        for channel in self.channels.iter() {
+            let now = Instant::now();
+            let mut attempts = 0;
+            let fut = self.run_in_channel(channel, &mut handler, &mut attempts);
+            let result = match self.options.timeout {
+                // The pipeline is dropped together with the future when timed
+                // out.  The tuner will be released when the streaming task
+                // detects the broken pipe.
+                Some(timeout) => match tokio::time::timeout(timeout, fut).await {
+                    Ok(result) => result,
+                    Err(_) => Err(failure::format_err!(
+                        "Timed out, {} elapsed",
+                        humantime::format_duration(timeout))),
+                }
+                None => fut.await,
+            };
+            let elapsed = now.elapsed();
+
+            match result {
+                Ok(_) => log::debug!("{}: Done in {}, {} elapsed",
+                                     self.label, channel.name,
+                                     humantime::format_duration(elapsed)),
+                Err(ref err) => log::warn!("{}: Failed in {}: {}",
+                                           self.label, channel.name, err),
+            }
+
+            results.push(ChannelJobResult {
+                channel: channel.clone(),
+                result,
+                attempts,
+            });
+        }
+
+        results
+    }
+
+    // `attempts` is updated in place so that the number of attempts can be
+    // reported even when the future is dropped by the timeout.
+    async fn run_in_channel<T, F, Fut>(
+        &self,
+        channel: &EpgChannel,
+        handler: &mut F,
+        attempts: &mut usize,
+    ) -> Result<T, Error>
+    where
+        F: FnMut(EpgChannel, ChannelJobOutput) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        loop {
+            *attempts += 1;
+            match self.do_run_in_channel(channel, handler).await {
+                Err(err) if *attempts <= self.options.retries => {
+                    log::warn!("{}: Failed in {}, retry: {}",
+                               self.label, channel.name, err);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn do_run_in_channel<T, F, Fut>(
+        &self,
+        channel: &EpgChannel,
+        handler: &mut F,
+    ) -> Result<T, Error>
+    where
+        F: FnMut(EpgChannel, ChannelJobOutput) -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        log::debug!("{}: Processing {}...", self.label, channel.name);
+
+        let user = TunerUser {
+            info: TunerUserInfo::Job { name: self.label.clone() },
+            priority: (-1).into(),
+        };
+
+        let cmd = render_template(&self.command, channel)?;
+
+        let stream = self.stream_manager.send(StartStreamingMessage {
+            channel: channel.clone(),
+            user,
+            filters: vec![cmd.clone()],
+        }).await??;
+
+        let mut pipeline = command_util::spawn_pipeline(
+            vec![cmd], stream.id())?;
+
+        let (input, output) = pipeline.take_endpoints()?;
+
+        let handle = tokio::spawn(stream.pipe(input));
+
+        let result = handler(channel.clone(), output).await;
+
+        // Explicitly dropping the output of the pipeline is needed.  The output
+        // holds the child processes and it kills them when dropped.
+        drop(pipeline);
+
+        // Wait for the task so that the tuner is released before a request for
+        // streaming in the next iteration.
+        let _ = handle.await;
+
+        result
+    }
+}
+
+// Renders a template string with the following variables:
+//
+//   channel_name, channel_type, channel, sids, xsids
+pub fn render_template(
+    template: &str,
+    channel: &EpgChannel,
+) -> Result<String, Error> {
+    let template = mustache::compile_str(template)?;
+    let data = mustache::MapBuilder::new()
+        .insert_str("channel_name", channel.name.clone())
+        .insert_str("channel_type", channel.channel_type.to_string())
+        .insert_str("channel", channel.channel.clone())
+        .insert("sids", &channel.services)?
+        .insert("xsids", &channel.excluded_services)?
+        .build();
+    Ok(template.render_data_to_string(&data)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+    use tokio::io::AsyncReadExt;
+    use super::test_util::*;
+
+    #[actix_rt::test]
+    async fn test_run() {
+        let mock = create_tuner_manager_mock();
+
+        let runner = ChannelJobRunner::new(
+            "test", "echo hello".to_string(), create_channels(),
+            mock.clone().recipient(), Default::default());
+        let results = runner.run(|_, mut output| async move {
+            let mut buf = String::new();
+            output.read_to_string(&mut buf).await?;
+            Ok(buf)
+        }).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].attempts, 1);
+        assert_matches!(&results[0].result, Ok(v) => {
+            assert_eq!(v, "hello\n");
+        });
+    }
+
+    #[actix_rt::test]
+    async fn test_run_with_retries() {
+        let mock = create_tuner_manager_mock();
+
+        let options = ChannelJobOptions { retries: 2, timeout: None };
+        let runner = ChannelJobRunner::new(
+            "test", "false".to_string(), create_channels(),
+            mock.clone().recipient(), options);
+        let results = runner.run(|_, _| async move {
+            Err::<(), _>(failure::err_msg("error"))
+        }).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].attempts, 3);
+        assert_matches!(&results[0].result, Err(_));
+    }
+
+    #[actix_rt::test]
+    async fn test_run_with_timeout() {
+        let mock = create_tuner_manager_mock();
+
+        let options = ChannelJobOptions {
+            retries: 0,
+            timeout: Some(Duration::from_millis(10)),
+        };
+        let runner = ChannelJobRunner::new(
+            "test", "sleep 10".to_string(), create_channels(),
+            mock.clone().recipient(), options);
+        let results = runner.run(|_, mut output| async move {
+            let mut buf = Vec::new();
+            output.read_to_end(&mut buf).await?;
+            Ok(buf)
+        }).await;
+        assert_eq!(results.len(), 1);
+        assert_matches!(&results[0].result, Err(_));
+    }
+
+    #[actix_rt::test]
+    async fn test_run_with_retries_and_timeout() {
+        let mock = create_tuner_manager_mock();
+
+        // The time limit covers all the attempts.
+        let options = ChannelJobOptions {
+            retries: 2,
+            timeout: Some(Duration::from_millis(100)),
+        };
+        let runner = ChannelJobRunner::new(
+            "test", "sleep 1".to_string(), create_channels(),
+            mock.clone().recipient(), options);
+        let results = runner.run(|_, mut output| async move {
+            let mut buf = Vec::new();
+            output.read_to_end(&mut buf).await?;
+            Err::<(), _>(failure::err_msg("error"))
+        }).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].attempts, 1);
+        assert_matches!(&results[0].result, Err(_));
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut channel = create_channels().remove(0);
+        channel.services = vec![1.into(), 2.into()];
+        channel.excluded_services = vec![3.into()];
+        let cmd = render_template(
+            "cmd{{#sids}} -s {{{.}}}{{/sids}}{{#xsids}} -x {{{.}}}{{/xsids}}",
+            &channel).unwrap();
+        assert_eq!(cmd, "cmd -s 1 -s 2 -x 3");
+
+        let path = render_template(
+            "/{{{channel_type}}}/{{{channel}}}/{{{channel_name}}}.json",
+            &channel).unwrap();
+        assert_eq!(path, "/GR/0/channel.json");
+    }
+}
+
+// Helpers for tests of channel jobs.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
+    use crate::broadcaster::BroadcasterStream;
+    use crate::error::Error as MirakcError;
+    use crate::mpeg_ts_stream::MpegTsStream;
+
+    pub type TunerManagerMock = actix::actors::mocker::Mocker<TunerManager>;
+
+    // Returns a mock which provides an empty stream for each request.
+    pub fn create_tuner_manager_mock() -> Addr<TunerManagerMock> {
+        TunerManagerMock::mock(Box::new(|msg, ctx| {
+            if let Some(_) = msg.downcast_ref::<StartStreamingMessage>() {
+                let (_, stream) = BroadcasterStream::new_for_test();
+                let result: Result<_, MirakcError> = Ok(MpegTsStream::new(
+                    Default::default(), stream, ctx.address().recipient()));
+                Box::new(Some(result))
+            } else if let Some(_) = msg.downcast_ref::<StopStreamingMessage>() {
+                Box::new(Some(()))
+            } else {
+                unimplemented!();
+            }
+        })).start()
+    }
+
+    pub fn create_channels() -> Vec<EpgChannel> {
+        vec![EpgChannel {
+            name: "channel".to_string(),
+            channel_type: ChannelType::GR,
+            channel: "0".to_string(),
+            extra_args: "".to_string(),
+            services: vec![],
+            excluded_services: vec![],
+        }]
+    }
+}
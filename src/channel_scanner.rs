@@ -0,0 +1,266 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use actix::prelude::*;
+use log;
+use tokio::sync::mpsc;
+
+use crate::channel_job::ChannelJobOptions;
+use crate::config::ChannelConfig;
+use crate::epg::EpgChannel;
+use crate::error::Error;
+use crate::models::*;
+use crate::service_scanner::ServiceScanner;
+use crate::tuner::StartStreamingMessage;
+
+// Only a single scan can run at a time because it may occupy tuners for a long
+// time.
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Released when the scan ends.
+pub struct ChannelScanLock(());
+
+impl ChannelScanLock {
+    pub fn acquire() -> Result<Self, Error> {
+        if RUNNING.compare_exchange(
+            false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return Err(Error::ChannelScanAlreadyRunning);
+        }
+        Ok(ChannelScanLock(()))
+    }
+}
+
+impl Drop for ChannelScanLock {
+    fn drop(&mut self) {
+        RUNNING.store(false, Ordering::SeqCst);
+    }
+}
+
+// Discovers channels by scanning services in each candidate channel.
+pub struct ChannelScanner {
+    command: String,
+    channel_type: ChannelType,
+    candidates: Vec<String>,
+    stream_manager: Recipient<StartStreamingMessage>,
+    options: ChannelJobOptions,
+}
+
+impl ChannelScanner {
+    pub fn new(
+        command: String,
+        channel_type: ChannelType,
+        candidates: Vec<String>,
+        stream_manager: Recipient<StartStreamingMessage>,
+        options: ChannelJobOptions,
+    ) -> Self {
+        ChannelScanner {
+            command, channel_type, candidates, stream_manager, options,
+        }
+    }
+
+    // Returns channel names in the same form as Mirakurun.
+    //
+    // Sub-channels of BS are always scanned.  SKY is not supported.
+    pub fn candidates(
+        channel_type: ChannelType,
+        min_ch: Option<u32>,
+        max_ch: Option<u32>,
+    ) -> Result<Vec<String>, Error> {
+        let (min, max) = match channel_type {
+            ChannelType::GR => (13, 62),
+            ChannelType::BS => (1, 23),
+            ChannelType::CS => (2, 24),
+            ChannelType::SKY => return Err(Error::InvalidConfig(
+                "SKY is not supported".to_string())),
+        };
+        let min = min_ch.unwrap_or(min);
+        let max = max_ch.unwrap_or(max);
+        if min > max {
+            return Err(Error::InvalidConfig(
+                format!("Invalid channel range: {}..{}", min, max)));
+        }
+        let candidates = match channel_type {
+            ChannelType::GR => (min..=max)
+                .map(|ch| ch.to_string())
+                .collect(),
+            ChannelType::BS => (min..=max)
+                .flat_map(|ch| (0..3).map(move |sub| {
+                    format!("BS{:02}_{}", ch, sub)
+                }))
+                .collect(),
+            ChannelType::CS => (min..=max)
+                .map(|ch| format!("CS{}", ch))
+                .collect(),
+            ChannelType::SKY => unreachable!(),
+        };
+        Ok(candidates)
+    }
+
+    // Progress messages are sent to `progress` line by line.
+    //
+    // Returns `None` when the receiver of `progress` has been closed, which
+    // means that the client has gone away.
+    pub async fn scan(
+        self,
+        mut progress: mpsc::Sender<String>,
+    ) -> Option<Vec<ChannelConfig>> {
+        log::info!("Scanning {} channels...", self.channel_type);
+
+        let total = self.candidates.len();
+        let mut channels = Vec::new();
+        for (i, ch) in self.candidates.into_iter().enumerate() {
+            progress.send(format!("{}/{} ({}/{}): ",
+                                  self.channel_type, ch, i + 1, total))
+                .await.ok()?;
+
+            let channel = EpgChannel {
+                name: ch.clone(),
+                channel_type: self.channel_type,
+                channel: ch.clone(),
+                extra_args: "".to_string(),
+                services: vec![],
+                excluded_services: vec![],
+            };
+            let scanner = ServiceScanner::new(
+                self.command.clone(), vec![channel],
+                self.stream_manager.clone(), self.options.clone());
+            let result = scanner.scan_services_with_errors().await
+                .into_iter()
+                .next()
+                .map(|result| result.result);
+            let services = match result {
+                Some(Ok(services)) => services,
+                Some(Err(err)) => {
+                    progress.send(format!("failed: {}\n", err))
+                        .await.ok()?;
+                    continue;
+                }
+                None => vec![],
+            };
+
+            if services.is_empty() {
+                progress.send("no service\n".to_string()).await.ok()?;
+                continue;
+            }
+
+            let names: Vec<&str> = services.iter()
+                .map(|sv| sv.name.as_str())
+                .collect();
+            progress.send(format!("{} services found: {}\n",
+                                  names.len(), names.join(", ")))
+                .await.ok()?;
+
+            channels.push(ChannelConfig {
+                name: names[0].to_string(),
+                channel_type: self.channel_type,
+                channel: ch,
+                extra_args: "".to_string(),
+                services: vec![],
+                excluded_services: vec![],
+                disabled: false,
+            });
+        }
+
+        log::info!("Found {} {} channels", channels.len(), self.channel_type);
+        Some(channels)
+    }
+}
+
+// Merges scanned channels into channels in the config.
+//
+// Channels of the scanned type are replaced if `refresh` is true.  Otherwise,
+// scanned channels are added only if they are not in the config.
+pub fn merge_channels(
+    channels: &[ChannelConfig],
+    channel_type: ChannelType,
+    scanned: Vec<ChannelConfig>,
+    refresh: bool,
+) -> Vec<ChannelConfig> {
+    let mut merged: Vec<ChannelConfig> = channels.iter()
+        .filter(|ch| !refresh || ch.channel_type != channel_type)
+        .cloned()
+        .collect();
+    for channel in scanned.into_iter() {
+        let exists = merged.iter().any(|ch| {
+            ch.channel_type == channel.channel_type &&
+                ch.channel == channel.channel
+        });
+        if !exists {
+            merged.push(channel);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+
+    #[test]
+    fn test_candidates() {
+        let candidates =
+            ChannelScanner::candidates(ChannelType::GR, None, None).unwrap();
+        assert_eq!(candidates.len(), 50);
+        assert_eq!(candidates[0], "13");
+        assert_eq!(candidates[49], "62");
+
+        let candidates =
+            ChannelScanner::candidates(ChannelType::BS, Some(1), Some(2))
+            .unwrap();
+        assert_eq!(candidates, vec![
+            "BS01_0", "BS01_1", "BS01_2", "BS02_0", "BS02_1", "BS02_2",
+        ]);
+
+        let candidates =
+            ChannelScanner::candidates(ChannelType::CS, Some(2), Some(4))
+            .unwrap();
+        assert_eq!(candidates, vec!["CS2", "CS3", "CS4"]);
+
+        assert_matches!(
+            ChannelScanner::candidates(ChannelType::GR, Some(30), Some(20)),
+            Err(Error::InvalidConfig(_)));
+        assert_matches!(
+            ChannelScanner::candidates(ChannelType::SKY, None, None),
+            Err(Error::InvalidConfig(_)));
+    }
+
+    #[test]
+    fn test_merge_channels() {
+        let channels = vec![
+            channel(ChannelType::GR, "27"),
+            channel(ChannelType::BS, "BS01_0"),
+        ];
+        let scanned = vec![
+            channel(ChannelType::GR, "26"),
+            channel(ChannelType::GR, "27"),
+        ];
+
+        let merged = merge_channels(
+            &channels, ChannelType::GR, scanned.clone(), false);
+        assert_eq!(merged, vec![
+            channel(ChannelType::GR, "27"),
+            channel(ChannelType::BS, "BS01_0"),
+            channel(ChannelType::GR, "26"),
+        ]);
+
+        let merged = merge_channels(
+            &channels, ChannelType::GR, scanned.clone(), true);
+        assert_eq!(merged, vec![
+            channel(ChannelType::BS, "BS01_0"),
+            channel(ChannelType::GR, "26"),
+            channel(ChannelType::GR, "27"),
+        ]);
+    }
+
+    fn channel(channel_type: ChannelType, ch: &str) -> ChannelConfig {
+        ChannelConfig {
+            name: ch.to_string(),
+            channel_type,
+            channel: ch.to_string(),
+            extra_args: "".to_string(),
+            services: vec![],
+            excluded_services: vec![],
+            disabled: false,
+        }
+    }
+}
@@ -10,29 +10,28 @@ use tokio::io::AsyncReadExt;
 #[cfg(test)]
 use serde::Serialize;
 
-use crate::command_util;
+use crate::channel_job::*;
 use crate::epg::*;
 use crate::models::*;
 use crate::tuner::*;
 
 pub struct ClockSynchronizer {
-    command: String,
-    channels: Vec<EpgChannel>,
-    stream_manager: Recipient<StartStreamingMessage>,
+    runner: ChannelJobRunner,
 }
 
-// TODO: The following implementation has code clones similar to
-//       EitCollector and ServiceScanner.
-
 impl ClockSynchronizer {
-    const LABEL: &'static str = "clock-synchronizer";
+    pub const LABEL: &'static str = "clock-synchronizer";
 
     pub fn new(
         command: String,
         channels: Vec<EpgChannel>,
         stream_manager: Recipient<StartStreamingMessage>,
+        options: ChannelJobOptions,
     ) -> Self {
-        ClockSynchronizer { command, channels, stream_manager }
+        ClockSynchronizer {
+            runner: ChannelJobRunner::new(
+                Self::LABEL, command, channels, stream_manager, options),
+        }
     }
 
     pub async fn sync_clocks(
@@ -40,75 +39,36 @@ impl ClockSynchronizer {
     ) -> Vec<(EpgChannel, Option<HashMap<ServiceTriple, Clock>>)> {
         log::debug!("Synchronizing clocks...");
 
-        let mut results = Vec::new();
+        let results = self.runner.run(Self::sync_clocks_in_channel).await;
 
-        for channel in self.channels.iter() {
-            let result = match Self::sync_clocks_in_channel(
-                &channel, &self.command, &self.stream_manager).await {
-                Ok(clocks) => {
+        log::debug!("Synchronized {} channels", results.len());
+
+        results
+            .into_iter()
+            .map(|result| {
+                let (channel, clocks) = result.ok();
+                let clocks = clocks.map(|clocks| {
                     let mut map = HashMap::new();
                     for clock in clocks.into_iter() {
                         let triple = (clock.nid, clock.tsid, clock.sid).into();
-                        map.insert(triple, clock.clock.clone());
+                        map.insert(triple, clock.clock);
                     }
-                    Some(map)
-                }
-                Err(err) => {
-                    log::warn!("Failed to synchronize clocks in {}: {}",
-                               channel.name, err);
-                    None
-                }
-            };
-            results.push((channel.clone(), result));
-        }
-
-        log::debug!("Synchronized {} channels", self.channels.len());
-
-        results
+                    map
+                });
+                (channel, clocks)
+            })
+            .collect()
     }
 
     async fn sync_clocks_in_channel(
-        channel: &EpgChannel,
-        command: &str,
-        stream_manager: &Recipient<StartStreamingMessage>,
+        channel: EpgChannel,
+        mut output: ChannelJobOutput,
     ) -> Result<Vec<SyncClock>, Error> {
         log::debug!("Synchronizing clocks in {}...", channel.name);
 
-        let user = TunerUser {
-            info: TunerUserInfo::Job { name: Self::LABEL.to_string() },
-            priority: (-1).into(),
-        };
-
-        let stream = stream_manager.send(StartStreamingMessage {
-            channel: channel.clone(),
-            user
-        }).await??;
-
-        let template = mustache::compile_str(command)?;
-        let data = mustache::MapBuilder::new()
-            .insert("sids", &channel.services)?
-            .insert("xsids", &channel.excluded_services)?
-            .build();
-        let cmd = template.render_data_to_string(&data)?;
-
-        let mut pipeline = command_util::spawn_pipeline(
-            vec![cmd], stream.id())?;
-
-        let (input, mut output) = pipeline.take_endpoints().unwrap();
-
-        let handle = tokio::spawn(stream.pipe(input));
-
         let mut buf = Vec::new();
         output.read_to_end(&mut buf).await?;
 
-        // Explicitly dropping the output of the pipeline is needed.  The output
-        // holds the child processes and it kills them when dropped.
-        drop(pipeline);
-
-        // Wait for the task so that the tuner is released before a request for
-        // streaming in the next iteration.
-        let _ = handle.await;
-
         failure::ensure!(!buf.is_empty(), "No clock, maybe out of service");
 
         let clocks: Vec<SyncClock> = serde_json::from_slice(&buf)?;
@@ -172,7 +132,8 @@ mod tests {
         let cmd = format!(
             "echo '{}'", serde_json::to_string(&expected).unwrap());
         let sync = ClockSynchronizer::new(
-            cmd, channels.clone(), mock.clone().recipient());
+            cmd, channels.clone(), mock.clone().recipient(),
+            Default::default());
         let results = sync.sync_clocks().await;
         assert_eq!(results.len(), 1);
         assert_matches!(&results[0], (_, Some(v)) => {
@@ -189,7 +150,8 @@ mod tests {
         // Emulate out of services by using `false`
         let cmd = "false".to_string();
         let sync = ClockSynchronizer::new(
-            cmd, channels.clone(), mock.clone().recipient());
+            cmd, channels.clone(), mock.clone().recipient(),
+            Default::default());
         let results = sync.sync_clocks().await;
         assert_eq!(results.len(), 1);
         assert_matches!(&results[0], (_, None));
@@ -1,13 +1,20 @@
 use std::collections::HashMap;
 use std::fs::File;
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::SystemTime;
 
+use chrono::FixedOffset;
+use indexmap::IndexMap;
 use num_cpus;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_yaml;
 
-use crate::models::{ChannelType, ServiceId};
+use crate::datetime_ext;
+use crate::error::Error;
+use crate::fs_util;
+use crate::ip_net::IpNet;
+use crate::models::{ChannelType, NetworkId, ServiceId};
 
 pub fn load(config_path: &str) -> Arc<Config> {
     const SERVER_STREAM_TIME_LIMIT_MIN: u64 = 15_000;
@@ -25,23 +32,144 @@ pub fn load(config_path: &str) -> Arc<Config> {
                     reset it to {0}", SERVER_STREAM_TIME_LIMIT_MIN);
         config.server.stream_time_limit = SERVER_STREAM_TIME_LIMIT_MIN;
     }
+    if let Err(msg) = validate(&config) {
+        panic!("Failed to parse {}: {}", config_path, msg);
+    }
 
+    config.path = Some(config_path.to_string());
     config.last_modified = std::fs::metadata(config_path)
         .map(|metadata| metadata.modified().ok()).ok().flatten();
     Arc::new(config)
 }
 
+// Updates a property specified by `keys` in the config file.
+//
+// The updated config is validated before it's written to the file.  Comments
+// in the file are not preserved.
+pub fn update<T>(
+    config_path: &str,
+    keys: &[&str],
+    value: &T,
+) -> Result<(), Error>
+where
+    T: Serialize,
+{
+    let mut root: serde_yaml::Value =
+        serde_yaml::from_reader(File::open(config_path)?)?;
+
+    let mut node = &mut root;
+    for key in keys.iter() {
+        if node.is_null() {
+            *node = serde_yaml::Value::Mapping(Default::default());
+        }
+        let mapping = node.as_mapping_mut()
+            .ok_or_else(|| Error::InvalidConfig(
+                format!("{} is not a mapping", keys.join("."))))?;
+        let key = serde_yaml::Value::from(*key);
+        if !mapping.contains_key(&key) {
+            mapping.insert(key.clone(), serde_yaml::Value::Null);
+        }
+        node = mapping.get_mut(&key).unwrap();
+    }
+    *node = serde_yaml::to_value(value)?;
+
+    let config: Config = serde_yaml::from_value(root.clone())
+        .map_err(|err| Error::InvalidConfig(err.to_string()))?;
+    validate(&config).map_err(Error::InvalidConfig)?;
+
+    fs_util::write_atomically(config_path, |writer| {
+        serde_yaml::to_writer(writer, &root).map_err(Error::from)
+    })?;
+    log::info!("Updated {} in {}", keys.join("."), config_path);
+    Ok(())
+}
+
+fn validate(config: &Config) -> Result<(), String> {
+    for (name, job) in config.jobs.custom.iter() {
+        if JobsConfig::BUILTIN_JOBS.contains(&name.as_str()) {
+            return Err(format!(
+                "jobs.custom.{} conflicts with a built-in job", name));
+        }
+        if let CustomJobOutput::Post(ref url) = job.output {
+            if !is_local_url(url, &config.server.access_control) {
+                return Err(format!(
+                    "jobs.custom.{}.output.post: {} is not a local endpoint",
+                    name, url));
+            }
+        }
+    }
+    for (i, channel) in config.channels.iter().enumerate() {
+        if channel.name.is_empty() || channel.channel.is_empty() {
+            return Err(format!(
+                "channels[{}]: name and channel must not be empty", i));
+        }
+    }
+    for (i, tuner) in config.tuners.iter().enumerate() {
+        if tuner.name.is_empty() || tuner.command.is_empty() {
+            return Err(format!(
+                "tuners[{}]: name and command must not be empty", i));
+        }
+    }
+    let cors = &config.server.cors;
+    if cors.allow_credentials &&
+        cors.allowed_origins.iter().any(|origin| origin == "*")
+    {
+        return Err(
+            "server.cors: '*' cannot be used with allow-credentials"
+                .to_string());
+    }
+    Ok(())
+}
+
+// Returns true if the host of `url` is `localhost`, a loopback address or an
+// address allowed in `server.access-control.allow`.
+//
+// `url` is a Mustache template, but the host must be a literal.
+fn is_local_url(url: &str, access_control: &AccessControlConfig) -> bool {
+    let rest = match url.find("://") {
+        Some(pos) if url[..pos].eq_ignore_ascii_case("http") ||
+            url[..pos].eq_ignore_ascii_case("https") => &url[pos + 3..],
+        _ => return false,
+    };
+    let authority = rest.split(|c| c == '/' || c == '?' || c == '#')
+        .next()
+        .unwrap_or_default();
+    if authority.contains('@') {
+        return false;
+    }
+    let host = if authority.starts_with('[') {
+        match authority.find(']') {
+            Some(pos) => &authority[1..pos],
+            None => return false,
+        }
+    } else {
+        authority.split(':').next().unwrap_or_default()
+    };
+    if host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match host.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback() ||
+            access_control.allow.iter().any(|net| net.contains(ip)),
+        Err(_) => false,
+    }
+}
+
 // result
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
 pub struct Config {
+    #[serde(skip)]
+    pub path: Option<String>,
     #[serde(skip)]
     pub last_modified: Option<SystemTime>,
     #[serde(default)]
     pub epg: EpgConfig,
     #[serde(default)]
+    pub time_zone: TimeZoneConfig,
+    #[serde(default)]
     pub server: ServerConfig,
     #[serde(default)]
     pub channels: Vec<ChannelConfig>,
@@ -63,12 +191,107 @@ pub struct Config {
     pub mirakurun: MirakurunConfig,
 }
 
-#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
 pub struct EpgConfig {
     #[serde(default)]
     pub cache_dir: Option<String>,
+    #[serde(default = "EpgConfig::default_max_cache_age")]
+    pub max_cache_age: u64,
+    #[serde(default)]
+    pub storage: EpgStorageType,
+    #[serde(default)]
+    pub ended_programs_retention: u64,
+}
+
+impl EpgConfig {
+    fn default_max_cache_age() -> u64 {
+        24 * 60 * 60 * 1000  // 24h
+    }
+}
+
+impl Default for EpgConfig {
+    fn default() -> Self {
+        EpgConfig {
+            cache_dir: None,
+            max_cache_age: Self::default_max_cache_age(),
+            storage: Default::default(),
+            ended_programs_retention: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EpgStorageType {
+    Json,
+    Sqlite,
+}
+
+impl Default for EpgStorageType {
+    fn default() -> Self {
+        EpgStorageType::Json
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct TimeZoneConfig {
+    #[serde(default = "TimeZoneConfig::default_offset")]
+    #[serde(deserialize_with = "deserialize_offset")]
+    pub offset: FixedOffset,
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_network_offsets")]
+    pub networks: HashMap<NetworkId, FixedOffset>,
+}
+
+impl TimeZoneConfig {
+    fn default_offset() -> FixedOffset {
+        FixedOffset::east(datetime_ext::Jst::OFFSET)
+    }
+
+    // Returns the UTC offset used for computing day boundaries of EPG data
+    // in the network.
+    pub fn offset_for(&self, nid: NetworkId) -> FixedOffset {
+        self.networks.get(&nid).cloned().unwrap_or(self.offset)
+    }
+}
+
+impl Default for TimeZoneConfig {
+    fn default() -> Self {
+        TimeZoneConfig {
+            offset: Self::default_offset(),
+            networks: HashMap::new(),
+        }
+    }
+}
+
+fn parse_offset<E: serde::de::Error>(s: &str) -> Result<FixedOffset, E> {
+    datetime_ext::parse_offset(s).ok_or_else(|| {
+        E::custom(format!("invalid UTC offset: {}", s))
+    })
+}
+
+fn deserialize_offset<'de, D>(deserializer: D) -> Result<FixedOffset, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_offset(&s)
+}
+
+fn deserialize_network_offsets<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<NetworkId, FixedOffset>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    HashMap::<u16, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(nid, s)| Ok((nid.into(), parse_offset(&s)?)))
+        .collect()
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -85,12 +308,26 @@ pub struct ServerConfig {
     pub stream_chunk_size: usize,
     #[serde(default = "ServerConfig::default_stream_time_limit")]
     pub stream_time_limit: u64,
+    #[serde(default)]
+    pub access_control: AccessControlConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    #[serde(default)]
+    pub quotas: QuotasConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum ServerAddr {
     Http(String),
+    Https {
+        addr: String,
+        // Paths to PEM files.
+        cert: String,
+        key: String,
+    },
     Unix(String),
 }
 
@@ -126,13 +363,162 @@ impl Default for ServerConfig {
             stream_max_chunks: Self::default_stream_max_chunks(),
             stream_chunk_size: Self::default_stream_chunk_size(),
             stream_time_limit: Self::default_stream_time_limit(),
+            access_control: Default::default(),
+            auth: Default::default(),
+            quotas: Default::default(),
+            cors: Default::default(),
         }
     }
 }
 
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct AccessControlConfig {
+    // Loopback, private and link-local addresses are allowed if empty.
+    #[serde(default)]
+    pub allow: Vec<IpNet>,
+    #[serde(default)]
+    pub deny: Vec<IpNet>,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+// Authentication is disabled if no token and no user are defined.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<AuthTokenConfig>,
+    #[serde(default)]
+    pub users: Vec<AuthUserConfig>,
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.users.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct AuthTokenConfig {
+    pub token: String,
+    #[serde(default = "AuthScope::default_scopes")]
+    pub scopes: Vec<AuthScope>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct AuthUserConfig {
+    pub name: String,
+    pub password: String,
+    #[serde(default = "AuthScope::default_scopes")]
+    pub scopes: Vec<AuthScope>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthScope {
+    // Read-only access to EPG data and IPTV resources.
+    Read,
+    // Streaming.
+    Stream,
+    // Everything including jobs.
+    Admin,
+}
+
+impl AuthScope {
+    fn default_scopes() -> Vec<AuthScope> {
+        vec![AuthScope::Read, AuthScope::Stream]
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct QuotasConfig {
+    // Limits for all clients in total.
+    #[serde(default)]
+    pub global: QuotaConfig,
+    // Limits for each client identified by a token or a remote address.
+    #[serde(default)]
+    pub per_client: QuotaConfig,
+}
+
+// No limit if `None`.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct QuotaConfig {
+    #[serde(default)]
+    pub max_concurrent_streams: Option<usize>,
+    #[serde(default)]
+    pub max_daily_streams: Option<usize>,
+    // In milliseconds.
+    #[serde(default)]
+    pub max_daily_streaming_time: Option<u64>,
+}
+
+// CORS is disabled if `allowed_origins` is empty.
 #[derive(Clone, Debug, Deserialize, PartialEq)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
+pub struct CorsConfig {
+    // "*" allows any origin.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "CorsConfig::default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "CorsConfig::default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    #[serde(default)]
+    pub allow_credentials: bool,
+    // In seconds.
+    #[serde(default = "CorsConfig::default_max_age")]
+    pub max_age: u64,
+}
+
+impl CorsConfig {
+    fn default_allowed_methods() -> Vec<String> {
+        vec!["GET".to_string(), "HEAD".to_string()]
+    }
+
+    fn default_allowed_headers() -> Vec<String> {
+        vec![
+            "authorization".to_string(),
+            "content-type".to_string(),
+            "x-mirakurun-priority".to_string(),
+        ]
+    }
+
+    fn default_max_age() -> u64 {
+        60 * 60  // 1h
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: Self::default_allowed_methods(),
+            allowed_headers: Self::default_allowed_headers(),
+            allow_credentials: false,
+            max_age: Self::default_max_age(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
 pub struct ChannelConfig {
     pub name: String,
     #[serde(rename = "type")]
@@ -148,7 +534,7 @@ pub struct ChannelConfig {
     pub disabled: bool,
 }
 
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(rename_all = "kebab-case")]
 #[serde(deny_unknown_fields)]
 pub struct TunerConfig {
@@ -163,7 +549,7 @@ pub struct TunerConfig {
 }
 
 impl TunerConfig {
-    fn default_time_limit() -> u64 {
+    pub fn default_time_limit() -> u64 {
         30 * 1000  // 30s
     }
 }
@@ -247,15 +633,25 @@ pub struct JobsConfig {
     pub sync_clocks: JobConfig,
     #[serde(default = "JobsConfig::default_update_schedules")]
     pub update_schedules: JobConfig,
+    #[serde(default = "JobsConfig::default_collect_logos")]
+    pub collect_logos: JobConfig,
+    #[serde(default)]
+    pub custom: IndexMap<String, CustomJobConfig>,
 }
 
 impl JobsConfig {
+    const BUILTIN_JOBS: [&'static str; 4] = [
+        "scan-services", "sync-clocks", "update-schedules", "collect-logos",
+    ];
+
     fn default_scan_services() -> JobConfig {
         JobConfig {
             command: "mirakc-arib scan-services\
                       {{#sids}} --sids={{{.}}}{{/sids}}\
                       {{#xsids}} --xsids={{{.}}}{{/xsids}}".to_string(),
             schedule: "0 31 5 * * * *".to_string(),
+            retries: 0,
+            channel_timeout: None,
         }
     }
 
@@ -265,6 +661,8 @@ impl JobsConfig {
                       {{#sids}} --sids={{{.}}}{{/sids}}\
                       {{#xsids}} --xsids={{{.}}}{{/xsids}}".to_string(),
             schedule: "0 3 12 * * * *".to_string(),
+            retries: 0,
+            channel_timeout: None,
         }
     }
 
@@ -274,6 +672,19 @@ impl JobsConfig {
                       {{#sids}} --sids={{{.}}}{{/sids}}\
                       {{#xsids}} --xsids={{{.}}}{{/xsids}}".to_string(),
             schedule: "0 7,37 * * * * *".to_string(),
+            retries: 0,
+            channel_timeout: None,
+        }
+    }
+
+    fn default_collect_logos() -> JobConfig {
+        JobConfig {
+            command: "mirakc-arib collect-logos\
+                      {{#sids}} --sids={{{.}}}{{/sids}}\
+                      {{#xsids}} --xsids={{{.}}}{{/xsids}}".to_string(),
+            schedule: "0 13 3 * * Sun *".to_string(),
+            retries: 0,
+            channel_timeout: None,
         }
     }
 }
@@ -284,6 +695,8 @@ impl Default for JobsConfig {
             scan_services: Self::default_scan_services(),
             sync_clocks: Self::default_sync_clocks(),
             update_schedules: Self::default_update_schedules(),
+            collect_logos: Self::default_collect_logos(),
+            custom: Default::default(),
         }
     }
 }
@@ -294,6 +707,41 @@ impl Default for JobsConfig {
 pub struct JobConfig {
     pub command: String,
     pub schedule: String,
+    #[serde(default)]
+    pub retries: usize,
+    #[serde(default)]
+    pub channel_timeout: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+#[serde(deny_unknown_fields)]
+pub struct CustomJobConfig {
+    pub command: String,
+    pub schedule: String,
+    // Names of target channels.  All enabled channels are targeted if empty.
+    #[serde(default)]
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub output: CustomJobOutput,
+    #[serde(default)]
+    pub retries: usize,
+    #[serde(default)]
+    pub channel_timeout: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CustomJobOutput {
+    Ignore,
+    File(String),
+    Post(String),
+}
+
+impl Default for CustomJobOutput {
+    fn default() -> Self {
+        CustomJobOutput::Ignore
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq)]
@@ -340,6 +788,9 @@ impl Default for ResourceConfig {
 pub struct MirakurunConfig {
     #[serde(default = "MirakurunConfig::default_openapi_json")]
     pub openapi_json: String,
+    // Enables PUT /api/config/* endpoints.
+    #[serde(default)]
+    pub writable_config: bool,
 }
 
 impl MirakurunConfig {
@@ -352,6 +803,7 @@ impl Default for MirakurunConfig {
     fn default() -> Self {
         MirakurunConfig {
             openapi_json: Self::default_openapi_json(),
+            writable_config: false,
         }
     }
 }
@@ -359,6 +811,7 @@ impl Default for MirakurunConfig {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::fs_util::test_util::TempDir;
 
     #[test]
     fn test_config() {
@@ -385,8 +838,49 @@ mod tests {
             "#).unwrap(),
             EpgConfig {
                 cache_dir: Some("/path/to/epg".to_string()),
+                max_cache_age: EpgConfig::default_max_cache_age(),
+                storage: EpgStorageType::Json,
+                ended_programs_retention: 0,
             });
 
+        assert_eq!(
+            serde_yaml::from_str::<EpgConfig>(r#"
+                max-cache-age: 3600000
+            "#).unwrap(),
+            EpgConfig {
+                cache_dir: None,
+                max_cache_age: 3600000,
+                storage: EpgStorageType::Json,
+                ended_programs_retention: 0,
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<EpgConfig>(r#"
+                storage: sqlite
+            "#).unwrap(),
+            EpgConfig {
+                cache_dir: None,
+                max_cache_age: EpgConfig::default_max_cache_age(),
+                storage: EpgStorageType::Sqlite,
+                ended_programs_retention: 0,
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<EpgConfig>(r#"
+                ended-programs-retention: 604800000
+            "#).unwrap(),
+            EpgConfig {
+                cache_dir: None,
+                max_cache_age: EpgConfig::default_max_cache_age(),
+                storage: EpgStorageType::Json,
+                ended_programs_retention: 604800000,
+            });
+
+        let result = serde_yaml::from_str::<EpgConfig>(r#"
+            storage: xml
+        "#);
+        assert!(result.is_err());
+
         let result = serde_yaml::from_str::<EpgConfig>(r#"
             unknown:
               property: value
@@ -394,6 +888,54 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_time_zone_config() {
+        assert_eq!(
+            serde_yaml::from_str::<TimeZoneConfig>("{}").unwrap(),
+            Default::default());
+
+        assert_eq!(
+            serde_yaml::from_str::<TimeZoneConfig>(r#"
+                offset: '-03:00'
+            "#).unwrap(),
+            TimeZoneConfig {
+                offset: FixedOffset::west(3 * 3600),
+                networks: HashMap::new(),
+            });
+
+        let config = serde_yaml::from_str::<TimeZoneConfig>(r#"
+            networks:
+              1: '+08:00'
+        "#).unwrap();
+        assert_eq!(
+            config,
+            TimeZoneConfig {
+                offset: TimeZoneConfig::default_offset(),
+                networks: vec![
+                    (1.into(), FixedOffset::east(8 * 3600)),
+                ].into_iter().collect(),
+            });
+        assert_eq!(config.offset_for(1.into()), FixedOffset::east(8 * 3600));
+        assert_eq!(config.offset_for(2.into()), FixedOffset::east(9 * 3600));
+
+        let result = serde_yaml::from_str::<TimeZoneConfig>(r#"
+            offset: JST
+        "#);
+        assert!(result.is_err());
+
+        let result = serde_yaml::from_str::<TimeZoneConfig>(r#"
+            networks:
+              1: '+25:00'
+        "#);
+        assert!(result.is_err());
+
+        let result = serde_yaml::from_str::<TimeZoneConfig>(r#"
+            unknown:
+              property: value
+        "#);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_server_config() {
         assert_eq!(
@@ -413,6 +955,10 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
         assert_eq!(
@@ -428,6 +974,10 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
         assert_eq!(
@@ -445,8 +995,45 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<ServerConfig>(r#"
+                addrs:
+                  - https:
+                      addr: '0.0.0.0:40773'
+                      cert: /path/to/cert.pem
+                      key: /path/to/key.pem
+            "#).unwrap(),
+            ServerConfig {
+                addrs: vec![
+                    ServerAddr::Https {
+                        addr: "0.0.0.0:40773".to_string(),
+                        cert: "/path/to/cert.pem".to_string(),
+                        key: "/path/to/key.pem".to_string(),
+                    },
+                ],
+                workers: ServerConfig::default_workers(),
+                stream_max_chunks: ServerConfig::default_stream_max_chunks(),
+                stream_chunk_size: ServerConfig::default_stream_chunk_size(),
+                stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
+        let result = serde_yaml::from_str::<ServerConfig>(r#"
+            addrs:
+              - https:
+                  addr: '0.0.0.0:40773'
+        "#);
+        assert!(result.is_err());
+
         assert_eq!(
             serde_yaml::from_str::<ServerConfig>(r#"
                 workers: 2
@@ -457,6 +1044,10 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
         assert_eq!(
@@ -469,6 +1060,10 @@ mod tests {
                 stream_max_chunks: 1000,
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
         assert_eq!(
@@ -481,6 +1076,10 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: 10000,
                 stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
         assert_eq!(
@@ -493,8 +1092,158 @@ mod tests {
                 stream_max_chunks: ServerConfig::default_stream_max_chunks(),
                 stream_chunk_size: ServerConfig::default_stream_chunk_size(),
                 stream_time_limit: 10000,
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
             });
 
+        assert_eq!(
+            serde_yaml::from_str::<ServerConfig>(r#"
+                access-control:
+                  allow:
+                    - 192.168.0.0/16
+                    - 'fd00::/8'
+                  deny:
+                    - 192.168.1.1
+                  trusted-proxies:
+                    - 127.0.0.1
+            "#).unwrap(),
+            ServerConfig {
+                addrs: ServerConfig::default_addrs(),
+                workers: ServerConfig::default_workers(),
+                stream_max_chunks: ServerConfig::default_stream_max_chunks(),
+                stream_chunk_size: ServerConfig::default_stream_chunk_size(),
+                stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: AccessControlConfig {
+                    allow: vec![
+                        "192.168.0.0/16".parse().unwrap(),
+                        "fd00::/8".parse().unwrap(),
+                    ],
+                    deny: vec!["192.168.1.1".parse().unwrap()],
+                    trusted_proxies: vec!["127.0.0.1".parse().unwrap()],
+                },
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<ServerConfig>(r#"
+                auth:
+                  tokens:
+                    - token: secret
+                    - token: admin-secret
+                      scopes: [admin]
+                  users:
+                    - name: user
+                      password: pass
+                      scopes: [read]
+            "#).unwrap(),
+            ServerConfig {
+                addrs: ServerConfig::default_addrs(),
+                workers: ServerConfig::default_workers(),
+                stream_max_chunks: ServerConfig::default_stream_max_chunks(),
+                stream_chunk_size: ServerConfig::default_stream_chunk_size(),
+                stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: AuthConfig {
+                    tokens: vec![
+                        AuthTokenConfig {
+                            token: "secret".to_string(),
+                            scopes: vec![AuthScope::Read, AuthScope::Stream],
+                        },
+                        AuthTokenConfig {
+                            token: "admin-secret".to_string(),
+                            scopes: vec![AuthScope::Admin],
+                        },
+                    ],
+                    users: vec![
+                        AuthUserConfig {
+                            name: "user".to_string(),
+                            password: "pass".to_string(),
+                            scopes: vec![AuthScope::Read],
+                        },
+                    ],
+                },
+                quotas: Default::default(),
+                cors: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<ServerConfig>(r#"
+                quotas:
+                  global:
+                    max-concurrent-streams: 4
+                  per-client:
+                    max-concurrent-streams: 1
+                    max-daily-streams: 10
+                    max-daily-streaming-time: 7200000
+            "#).unwrap(),
+            ServerConfig {
+                addrs: ServerConfig::default_addrs(),
+                workers: ServerConfig::default_workers(),
+                stream_max_chunks: ServerConfig::default_stream_max_chunks(),
+                stream_chunk_size: ServerConfig::default_stream_chunk_size(),
+                stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: QuotasConfig {
+                    global: QuotaConfig {
+                        max_concurrent_streams: Some(4),
+                        max_daily_streams: None,
+                        max_daily_streaming_time: None,
+                    },
+                    per_client: QuotaConfig {
+                        max_concurrent_streams: Some(1),
+                        max_daily_streams: Some(10),
+                        max_daily_streaming_time: Some(7200000),
+                    },
+                },
+                cors: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<ServerConfig>(r#"
+                cors:
+                  allowed-origins:
+                    - http://localhost:8080
+                  allowed-methods: [GET, POST]
+                  allow-credentials: true
+            "#).unwrap(),
+            ServerConfig {
+                addrs: ServerConfig::default_addrs(),
+                workers: ServerConfig::default_workers(),
+                stream_max_chunks: ServerConfig::default_stream_max_chunks(),
+                stream_chunk_size: ServerConfig::default_stream_chunk_size(),
+                stream_time_limit: ServerConfig::default_stream_time_limit(),
+                access_control: Default::default(),
+                auth: Default::default(),
+                quotas: Default::default(),
+                cors: CorsConfig {
+                    allowed_origins: vec!["http://localhost:8080".to_string()],
+                    allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+                    allowed_headers: CorsConfig::default_allowed_headers(),
+                    allow_credentials: true,
+                    max_age: CorsConfig::default_max_age(),
+                },
+            });
+
+        let result = serde_yaml::from_str::<ServerConfig>(r#"
+            auth:
+              tokens:
+                - token: secret
+                  scopes: [write]
+        "#);
+        assert!(result.is_err());
+
+        let result = serde_yaml::from_str::<ServerConfig>(r#"
+            access-control:
+              allow:
+                - 192.168.0.0/33
+        "#);
+        assert!(result.is_err());
+
         let result = serde_yaml::from_str::<ServerConfig>(r#"
             unknown:
               property: value
@@ -788,9 +1537,13 @@ mod tests {
                 scan_services: JobConfig {
                     command: "job".to_string(),
                     schedule: "*".to_string(),
+                    retries: 0,
+                    channel_timeout: None,
                 },
                 sync_clocks: JobsConfig::default_sync_clocks(),
                 update_schedules: JobsConfig::default_update_schedules(),
+                collect_logos: JobsConfig::default_collect_logos(),
+                custom: Default::default(),
             });
 
         assert_eq!(
@@ -804,8 +1557,12 @@ mod tests {
                 sync_clocks: JobConfig {
                     command: "job".to_string(),
                     schedule: "*".to_string(),
+                    retries: 0,
+                    channel_timeout: None,
                 },
                 update_schedules: JobsConfig::default_update_schedules(),
+                collect_logos: JobsConfig::default_collect_logos(),
+                custom: Default::default(),
             });
 
         assert_eq!(
@@ -820,6 +1577,53 @@ mod tests {
                 update_schedules: JobConfig {
                     command: "job".to_string(),
                     schedule: "*".to_string(),
+                    retries: 0,
+                    channel_timeout: None,
+                },
+                collect_logos: JobsConfig::default_collect_logos(),
+                custom: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<JobsConfig>(r#"
+                collect-logos:
+                  command: job
+                  schedule: '*'
+            "#).unwrap(),
+            JobsConfig {
+                scan_services: JobsConfig::default_scan_services(),
+                sync_clocks: JobsConfig::default_sync_clocks(),
+                update_schedules: JobsConfig::default_update_schedules(),
+                collect_logos: JobConfig {
+                    command: "job".to_string(),
+                    schedule: "*".to_string(),
+                    retries: 0,
+                    channel_timeout: None,
+                },
+                custom: Default::default(),
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<JobsConfig>(r#"
+                custom:
+                  probe:
+                    command: job
+                    schedule: '*'
+            "#).unwrap(),
+            JobsConfig {
+                scan_services: JobsConfig::default_scan_services(),
+                sync_clocks: JobsConfig::default_sync_clocks(),
+                update_schedules: JobsConfig::default_update_schedules(),
+                collect_logos: JobsConfig::default_collect_logos(),
+                custom: indexmap::indexmap!{
+                    "probe".to_string() => CustomJobConfig {
+                        command: "job".to_string(),
+                        schedule: "*".to_string(),
+                        channels: vec![],
+                        output: CustomJobOutput::Ignore,
+                        retries: 0,
+                        channel_timeout: None,
+                    },
                 },
             });
 
@@ -830,6 +1634,53 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_custom_job_config() {
+        assert!(serde_yaml::from_str::<CustomJobConfig>("{}").is_err());
+
+        assert_eq!(
+            serde_yaml::from_str::<CustomJobConfig>(r#"
+                command: job
+                schedule: '*'
+                channels: [ch1, ch2]
+                output:
+                  file: /path/to/{{{channel_name}}}.json
+            "#).unwrap(),
+            CustomJobConfig {
+                command: "job".to_string(),
+                schedule: "*".to_string(),
+                channels: vec!["ch1".to_string(), "ch2".to_string()],
+                output: CustomJobOutput::File(
+                    "/path/to/{{{channel_name}}}.json".to_string()),
+                retries: 0,
+                channel_timeout: None,
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<CustomJobConfig>(r#"
+                command: job
+                schedule: '*'
+                output:
+                  post: http://localhost:8080/probe
+            "#).unwrap().output,
+            CustomJobOutput::Post("http://localhost:8080/probe".to_string()));
+
+        assert_eq!(
+            serde_yaml::from_str::<CustomJobConfig>(r#"
+                command: job
+                schedule: '*'
+                output: ignore
+            "#).unwrap().output,
+            CustomJobOutput::Ignore);
+
+        assert!(serde_yaml::from_str::<CustomJobConfig>(r#"
+            command: job
+            schedule: '*'
+            output:
+              unknown: value
+        "#).is_err());
+    }
+
     #[test]
     fn test_job_config() {
         assert!(serde_yaml::from_str::<JobConfig>("{}").is_err());
@@ -838,6 +1689,20 @@ mod tests {
         assert!(
             serde_yaml::from_str::<JobConfig>(r#"{"schedule":""}"#).is_err());
 
+        assert_eq!(
+            serde_yaml::from_str::<JobConfig>(r#"
+                command: job
+                schedule: '*'
+                retries: 2
+                channel-timeout: 60000
+            "#).unwrap(),
+            JobConfig {
+                command: "job".to_string(),
+                schedule: "*".to_string(),
+                retries: 2,
+                channel_timeout: Some(60000),
+            });
+
         let result = serde_yaml::from_str::<JobConfig>(r#"
             unknown:
               property: value
@@ -866,6 +1731,42 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_update() {
+        let dir = TempDir::new("config");
+        let path = dir.path().join("config.yml");
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "server:\n  workers: 2\n").unwrap();
+
+        let channels = vec![ChannelConfig {
+            name: "ch".to_string(),
+            channel_type: ChannelType::GR,
+            channel: "27".to_string(),
+            extra_args: "".to_string(),
+            services: vec![],
+            excluded_services: vec![],
+            disabled: false,
+        }];
+        assert!(update(path, &["channels"], &channels).is_ok());
+
+        let config = load(path);
+        assert_eq!(config.server.workers, 2);
+        assert_eq!(config.channels, channels);
+
+        let mut invalid = channels.clone();
+        invalid[0].name = "".to_string();
+        assert!(update(path, &["channels"], &invalid).is_err());
+        assert_eq!(load(path).channels, channels);
+
+        assert!(update(path, &["unknown"], &1).is_err());
+
+        let addrs = vec![ServerAddr::Unix("/path/to/sock".to_string())];
+        assert!(update(path, &["server", "addrs"], &addrs).is_ok());
+        let config = load(path);
+        assert_eq!(config.server.workers, 2);
+        assert_eq!(config.server.addrs, addrs);
+    }
+
     #[test]
     fn test_mirakurun_config() {
         assert_eq!(
@@ -878,6 +1779,16 @@ mod tests {
             "#).unwrap(),
             MirakurunConfig {
                 openapi_json: "/path/to/json".to_string(),
+                writable_config: false,
+            });
+
+        assert_eq!(
+            serde_yaml::from_str::<MirakurunConfig>(r#"
+                writable-config: true
+            "#).unwrap(),
+            MirakurunConfig {
+                openapi_json: MirakurunConfig::default_openapi_json(),
+                writable_config: true,
             });
 
         let result = serde_yaml::from_str::<MirakurunConfig>(r#"
@@ -886,4 +1797,54 @@ mod tests {
         "#);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_is_local_url() {
+        let mut access_control = AccessControlConfig::default();
+        assert!(is_local_url("http://localhost/logos", &access_control));
+        assert!(is_local_url(
+            "http://127.0.0.1:8080/{{{channel}}}", &access_control));
+        assert!(is_local_url("http://[::1]:8080/", &access_control));
+        assert!(is_local_url("https://LOCALHOST:8443", &access_control));
+        assert!(!is_local_url("http://192.168.0.1/", &access_control));
+        assert!(!is_local_url("http://example.com/", &access_control));
+        assert!(!is_local_url(
+            "http://localhost@example.com/", &access_control));
+        assert!(!is_local_url("http://{{{host}}}/", &access_control));
+        assert!(!is_local_url("ftp://localhost/", &access_control));
+        assert!(!is_local_url("localhost:8080", &access_control));
+
+        access_control.allow = vec!["192.168.0.0/24".parse().unwrap()];
+        assert!(is_local_url("http://192.168.0.1/", &access_control));
+        assert!(!is_local_url("http://192.168.1.1/", &access_control));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&Default::default()).is_ok());
+
+        let mut config = Config::default();
+        config.server.cors.allowed_origins = vec!["*".to_string()];
+        assert!(validate(&config).is_ok());
+        config.server.cors.allow_credentials = true;
+        assert!(validate(&config).is_err());
+        config.server.cors.allowed_origins =
+            vec!["http://localhost:8080".to_string()];
+        assert!(validate(&config).is_ok());
+
+        let mut config = Config::default();
+        config.jobs.custom.insert("post".to_string(), CustomJobConfig {
+            command: "true".to_string(),
+            schedule: "0 0 0 * * * *".to_string(),
+            channels: vec![],
+            output: CustomJobOutput::Post(
+                "http://localhost:8080/{{{channel}}}".to_string()),
+            retries: 0,
+            channel_timeout: None,
+        });
+        assert!(validate(&config).is_ok());
+        config.jobs.custom.get_mut("post").unwrap().output =
+            CustomJobOutput::Post("http://example.com/".to_string());
+        assert!(validate(&config).is_err());
+    }
 }
@@ -0,0 +1,182 @@
+use actix::prelude::*;
+use actix_web::client::Client;
+use failure::Error;
+use log;
+use tokio::io::AsyncReadExt;
+
+use crate::channel_job::*;
+use crate::config::{CustomJobConfig, CustomJobOutput};
+use crate::epg::EpgChannel;
+use crate::tuner::*;
+
+pub struct CustomJob {
+    name: String,
+    runner: ChannelJobRunner,
+    output: CustomJobOutput,
+}
+
+impl CustomJob {
+    pub fn new(
+        name: &str,
+        config: &CustomJobConfig,
+        channels: Vec<EpgChannel>,
+        stream_manager: Recipient<StartStreamingMessage>,
+    ) -> Self {
+        CustomJob {
+            name: name.to_string(),
+            runner: ChannelJobRunner::new(
+                name, config.command.clone(), channels, stream_manager,
+                ChannelJobOptions::from(config)),
+            output: config.output.clone(),
+        }
+    }
+
+    // Returns the number of bytes output from the command for each channel.
+    pub async fn run(self) -> Vec<ChannelJobResult<usize>> {
+        log::info!("{}: Running...", self.name);
+        let output_mode = self.output;
+        let results = self.runner.run(|channel, output| {
+            Self::handle_output(output_mode.clone(), channel, output)
+        }).await;
+        let num_failed = results.iter()
+            .filter(|result| result.result.is_err())
+            .count();
+        log::info!("{}: Done in {} channels, {} failed",
+                   self.name, results.len(), num_failed);
+        results
+    }
+
+    async fn handle_output(
+        output_mode: CustomJobOutput,
+        channel: EpgChannel,
+        mut output: ChannelJobOutput,
+    ) -> Result<usize, Error> {
+        match output_mode {
+            CustomJobOutput::Ignore => {
+                let n = tokio::io::copy(
+                    &mut output, &mut tokio::io::sink()).await?;
+                Ok(n as usize)
+            }
+            CustomJobOutput::File(path) => {
+                let path = render_template(&path, &channel)?;
+                let mut file = tokio::fs::File::create(&path).await?;
+                let n = tokio::io::copy(&mut output, &mut file).await?;
+                file.sync_all().await?;
+                log::debug!("Saved {} bytes to {}", n, path);
+                Ok(n as usize)
+            }
+            CustomJobOutput::Post(url) => {
+                let url = render_template(&url, &channel)?;
+                let mut buf = Vec::new();
+                output.read_to_end(&mut buf).await?;
+                let n = buf.len();
+                let res = Client::default()
+                    .post(&url)
+                    .header("content-type", "application/octet-stream")
+                    .send_body(buf)
+                    .await
+                    .map_err(|err| failure::format_err!(
+                        "Failed to post to {}: {}", url, err))?;
+                if !res.status().is_success() {
+                    failure::bail!("Failed to post to {}: {}",
+                                   url, res.status());
+                }
+                log::debug!("Posted {} bytes to {}", n, url);
+                Ok(n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use actix_web::{web, App, HttpResponse};
+    use assert_matches::*;
+    use bytes::Bytes;
+    use crate::channel_job::test_util::*;
+    use crate::fs_util::test_util::TempDir;
+
+    #[actix_rt::test]
+    async fn test_run_with_ignore() {
+        let config = create_config("echo hello", CustomJobOutput::Ignore);
+        let job = CustomJob::new(
+            "test", &config, create_channels(),
+            create_tuner_manager_mock().recipient());
+        let results = job.run().await;
+        assert_eq!(results.len(), 1);
+        assert_matches!(results[0].result, Ok(6));
+    }
+
+    #[actix_rt::test]
+    async fn test_run_with_file() {
+        let dir = TempDir::new("custom-job");
+        let path = format!("{}/{{{{{{channel_name}}}}}}.out",
+                           dir.path().to_str().unwrap());
+
+        let config = create_config("echo hello", CustomJobOutput::File(path));
+        let job = CustomJob::new(
+            "test", &config, create_channels(),
+            create_tuner_manager_mock().recipient());
+        let results = job.run().await;
+        assert_eq!(results.len(), 1);
+        assert_matches!(results[0].result, Ok(6));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("channel.out")).unwrap(),
+            "hello\n");
+    }
+
+    #[actix_rt::test]
+    async fn test_run_with_post() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let srv = {
+            let received = received.clone();
+            actix_web::test::start(move || {
+                let received = received.clone();
+                App::new()
+                    .route("/ok/{name}", web::post().to(
+                        move |path: web::Path<String>, body: Bytes| {
+                            received.lock().unwrap()
+                                .push((path.into_inner(), body));
+                            HttpResponse::Ok().finish()
+                        }))
+                    .route("/error/{name}", web::post().to(|| {
+                        HttpResponse::InternalServerError().finish()
+                    }))
+            })
+        };
+
+        let url = srv.url("/ok/{{{channel_name}}}");
+        let config = create_config("echo hello", CustomJobOutput::Post(url));
+        let job = CustomJob::new(
+            "test", &config, create_channels(),
+            create_tuner_manager_mock().recipient());
+        let results = job.run().await;
+        assert_eq!(results.len(), 1);
+        assert_matches!(results[0].result, Ok(6));
+        assert_eq!(*received.lock().unwrap(), vec![
+            ("channel".to_string(), Bytes::from("hello\n")),
+        ]);
+
+        let url = srv.url("/error/{{{channel_name}}}");
+        let config = create_config("echo hello", CustomJobOutput::Post(url));
+        let job = CustomJob::new(
+            "test", &config, create_channels(),
+            create_tuner_manager_mock().recipient());
+        let results = job.run().await;
+        assert_eq!(results.len(), 1);
+        assert_matches!(results[0].result, Err(_));
+    }
+
+    fn create_config(command: &str, output: CustomJobOutput) -> CustomJobConfig {
+        CustomJobConfig {
+            command: command.to_string(),
+            schedule: "*".to_string(),
+            channels: vec![],
+            output,
+            retries: 0,
+            channel_timeout: None,
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::fmt;
 
 use chrono::{
-    Date, DateTime, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset,
+    DateTime, FixedOffset, LocalResult, NaiveDate, NaiveDateTime, Offset,
     TimeZone, Utc,
 };
 
@@ -9,21 +9,54 @@ use chrono::{
 //
 // See https://github.com/chronotope/chrono/blob/master/src/offset/utc.rs for
 // details.
+//
+// The UTC offset of `Jst` is always UTC+9.  Computations depending on the time
+// zone of broadcasts take a `FixedOffset` in `time-zone.offset` explicitly.
 
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Jst;
 
-impl Jst {
-    pub fn now() -> DateTime<Jst> {
-        Utc::now().with_timezone(&Jst)
+// Parses a UTC offset in the form of "+HH:MM" or "-HH:MM".
+pub fn parse_offset(s: &str) -> Option<FixedOffset> {
+    let bytes = s.as_bytes();
+    if bytes.len() != 6 || bytes[3] != b':' {
+        return None;
     }
-
-    pub fn today() -> Date<Jst> {
-        Jst::now().date()
+    let sign = match bytes[0] {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digits = [bytes[1], bytes[2], bytes[4], bytes[5]];
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
     }
+    let hours = ((digits[0] - b'0') * 10 + (digits[1] - b'0')) as i32;
+    let minutes = ((digits[2] - b'0') * 10 + (digits[3] - b'0')) as i32;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 60 * 60 + minutes * 60))
+}
+
+// Returns the start of the day including `datetime` in the time zone of
+// `offset`.
+pub fn midnight_in(
+    offset: &FixedOffset,
+    datetime: DateTime<Jst>,
+) -> DateTime<Jst> {
+    datetime
+        .with_timezone(offset)
+        .date()
+        .and_hms(0, 0, 0)
+        .with_timezone(&Jst)
+}
+
+impl Jst {
+    pub const OFFSET: i32 = 9 * 60 * 60;
 
-    pub fn midnight() -> DateTime<Jst> {
-        Jst::today().and_hms(0, 0, 0)
+    pub fn now() -> DateTime<Jst> {
+        Utc::now().with_timezone(&Jst)
     }
 }
 
@@ -47,7 +80,9 @@ impl TimeZone for Jst {
 }
 
 impl Offset for Jst {
-    fn fix(&self) -> FixedOffset { FixedOffset::east(9 * 60 * 60) }
+    fn fix(&self) -> FixedOffset {
+        FixedOffset::east(Self::OFFSET)
+    }
 }
 
 impl fmt::Display for Jst {
@@ -88,6 +123,35 @@ mod tests {
                            .with_timezone(&Jst);
         assert_eq!(jst.timestamp(), UNIX_TIME);
     }
+
+    #[test]
+    fn test_parse_offset() {
+        assert_eq!(parse_offset("+09:00"), Some(FixedOffset::east(9 * 3600)));
+        assert_eq!(parse_offset("-03:00"), Some(FixedOffset::west(3 * 3600)));
+        assert_eq!(parse_offset("+05:30"),
+                   Some(FixedOffset::east(5 * 3600 + 30 * 60)));
+        assert_eq!(parse_offset("+8"), None);
+        assert_eq!(parse_offset("+9:00"), None);
+        assert_eq!(parse_offset("+0900"), None);
+        assert_eq!(parse_offset("+０９:00"), None);
+        assert_eq!(parse_offset("09:00"), None);
+        assert_eq!(parse_offset("+24:00"), None);
+        assert_eq!(parse_offset("+09:60"), None);
+        assert_eq!(parse_offset(""), None);
+    }
+
+    #[test]
+    fn test_midnight_in() {
+        // 2019-10-13T10:00:00+09:00 == 2019-10-12T22:00:00-03:00
+        let datetime = Jst.ymd(2019, 10, 13).and_hms(10, 0, 0);
+        assert_eq!(
+            midnight_in(&FixedOffset::west(3 * 3600), datetime),
+            FixedOffset::west(3 * 3600)
+                .ymd(2019, 10, 12).and_hms(0, 0, 0).with_timezone(&Jst));
+        assert_eq!(
+            midnight_in(&FixedOffset::east(9 * 3600), datetime),
+            Jst.ymd(2019, 10, 13).and_hms(0, 0, 0));
+    }
 }
 
 // The `serde_jst` module provides serde implementaion for
@@ -4,46 +4,49 @@ use std::sync::Arc;
 
 use actix::prelude::*;
 use chrono::{DateTime, Duration};
+use futures::future::{AbortHandle, Abortable};
 use log;
 use serde::{Deserialize, Serialize};
 use serde_json;
 use tokio::prelude::*;
 use tokio::io::BufReader;
 
+use crate::channel_job::*;
 use crate::config::Config;
 use crate::datetime_ext::*;
 use crate::error::Error;
 use crate::epg::*;
 use crate::models::*;
 use crate::tuner::*;
-use crate::command_util;
 
 pub fn start(
     config: Arc<Config>,
     tuner_manager: Addr<TunerManager>,
     epg: Addr<Epg>,
 ) -> Addr<EitFeeder> {
-    EitFeeder::new(config, tuner_manager, epg).start()
+    EitFeeder::new(config, tuner_manager.recipient(), epg).start()
 }
 
 pub struct EitFeeder {
     config: Arc<Config>,
-    tuner_manager: Addr<TunerManager>,
+    stream_manager: Recipient<StartStreamingMessage>,
     epg: Addr<Epg>,
+    abort_handle: Option<AbortHandle>,
 }
 
 impl EitFeeder {
     pub fn new(
         config: Arc<Config>,
-        tuner_manager: Addr<TunerManager>,
+        stream_manager: Recipient<StartStreamingMessage>,
         epg: Addr<Epg>,
     ) -> Self {
-        EitFeeder { config, tuner_manager, epg }
+        EitFeeder { config, stream_manager, epg, abort_handle: None }
     }
 
     async fn feed_eit_sections(
         command: String,
-        tuner_manager: Addr<TunerManager>,
+        options: ChannelJobOptions,
+        stream_manager: Recipient<StartStreamingMessage>,
         epg: Addr<Epg>,
     ) -> Result<(), Error> {
         let services = epg.send(QueryServicesMessage).await??;
@@ -63,7 +66,7 @@ impl EitFeeder {
         }
         let channels = map.values().cloned().collect();
 
-        EitCollector::new(command, channels, tuner_manager, epg)
+        EitCollector::new(command, channels, stream_manager, epg, options)
             .collect_schedules().await
     }
 }
@@ -103,114 +106,118 @@ impl Handler<FeedEitSectionsMessage> for EitFeeder {
         _: &mut Self::Context,
     ) -> Self::Result {
         log::debug!("{}", msg);
-        let fut = Box::pin(Self::feed_eit_sections(
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        self.abort_handle = Some(abort_handle);
+        let fut = Abortable::new(Self::feed_eit_sections(
             self.config.jobs.update_schedules.command.clone(),
-            self.tuner_manager.clone(), self.epg.clone()));
-        Response::fut(fut)
+            ChannelJobOptions::from(&self.config.jobs.update_schedules),
+            self.stream_manager.clone(), self.epg.clone()), abort_registration);
+        let epg = self.epg.clone();
+        Response::fut(Box::pin(async move {
+            match fut.await {
+                Ok(result) => result,
+                Err(_) => {
+                    log::warn!("Canceled");
+                    // EIT sections collected before the cancellation have
+                    // already been sent to `Epg` when the collection future
+                    // was dropped.  Save them as well as the job does.
+                    epg.do_send(SaveSchedulesMessage);
+                    Ok(())
+                }
+            }
+        }))
+    }
+}
+
+// cancel feed eit sections
+
+pub struct CancelFeedEitSectionsMessage;
+
+impl fmt::Display for CancelFeedEitSectionsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CancelFeedEitSections")
+    }
+}
+
+impl Message for CancelFeedEitSectionsMessage {
+    type Result = ();
+}
+
+impl Handler<CancelFeedEitSectionsMessage> for EitFeeder {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: CancelFeedEitSectionsMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        if let Some(abort_handle) = self.abort_handle.take() {
+            abort_handle.abort();
+        }
     }
 }
 
 // collector
 
 pub struct EitCollector {
-    command: String,
-    channels: Vec<EpgChannel>,
-    tuner_manager: Addr<TunerManager>,
+    runner: ChannelJobRunner,
     epg: Addr<Epg>,
 }
 
-// TODO: The following implementation has code clones similar to
-//       ClockSynchronizer and ServiceScanner.
-
 impl EitCollector {
-    const LABEL: &'static str = "eit-collector";
+    pub const LABEL: &'static str = "eit-collector";
     const UPDATE_CHUNK_SIZE: usize = 32;
 
     pub fn new(
         command: String,
         channels: Vec<EpgChannel>,
-        tuner_manager: Addr<TunerManager>,
+        stream_manager: Recipient<StartStreamingMessage>,
         epg: Addr<Epg>,
+        options: ChannelJobOptions,
     ) -> Self {
-        EitCollector { command, channels, tuner_manager, epg }
+        EitCollector {
+            runner: ChannelJobRunner::new(
+                Self::LABEL, command, channels, stream_manager,
+                options),
+            epg,
+        }
     }
 
     pub async fn collect_schedules(
         self
     ) -> Result<(), Error> {
         log::info!("Collecting EIT sections...");
-        let mut num_sections = 0;
-        for channel in self.channels.iter() {
-            num_sections += Self::collect_eits_in_channel(
-                &channel, &self.command, &self.tuner_manager, &self.epg).await?;
-        }
+        let epg = self.epg;
+        let results = self.runner.run(|channel, output| {
+            Self::collect_eits_in_channel(channel, output, epg.clone())
+        }).await;
+        let num_sections: usize = results
+            .into_iter()
+            .filter_map(|result| result.result.ok())
+            .sum();
         log::info!("Collected {} EIT sections", num_sections);
         Ok(())
     }
 
     async fn collect_eits_in_channel(
-        channel: &EpgChannel,
-        command: &str,
-        tuner_manager: &Addr<TunerManager>,
-        epg: &Addr<Epg>,
-    ) -> Result<usize, Error> {
+        channel: EpgChannel,
+        output: ChannelJobOutput,
+        epg: Addr<Epg>,
+    ) -> Result<usize, failure::Error> {
         log::debug!("Collecting EIT sections in {}...", channel.name);
 
-        let user = TunerUser {
-            info: TunerUserInfo::Job { name: Self::LABEL.to_string() },
-            priority: (-1).into(),
-        };
-
-        let stream = tuner_manager.send(StartStreamingMessage {
-            channel: channel.clone(),
-            user
-        }).await??;
-
-        let template = mustache::compile_str(command)?;
-        let data = mustache::MapBuilder::new()
-            .insert("sids", &channel.services)?
-            .insert("xsids", &channel.excluded_services)?
-            .build();
-        let cmd = template.render_data_to_string(&data)?;
-
-        let mut pipeline = command_util::spawn_pipeline(
-            vec![cmd], stream.id())?;
-
-        let (input, output) = pipeline.take_endpoints().unwrap();
-
-        let handle = tokio::spawn(stream.pipe(input));
-
         let mut reader = BufReader::new(output);
         let mut json = String::new();
         let mut num_sections = 0;
-        let mut triples = HashSet::new();
-        let mut sections = Vec::with_capacity(Self::UPDATE_CHUNK_SIZE);
+        let mut sender = EitSectionSender::new(epg);
         while reader.read_line(&mut json).await? > 0 {
             let eit = serde_json::from_str::<EitSection>(&json)?;
-            triples.insert(eit.service_triple());
-            sections.push(eit);
-            if sections.len() == Self::UPDATE_CHUNK_SIZE {
-                epg.do_send(UpdateSchedulesMessage { sections });
-                sections = Vec::with_capacity(32);
-            }
+            sender.push(eit);
             json.clear();
             num_sections += 1;
         }
-        if !sections.is_empty() {
-            epg.do_send(UpdateSchedulesMessage { sections });
-        }
-
-        // Explicitly dropping the output of the pipeline is needed.  The output
-        // holds the child processes and it kills them when dropped.
-        drop(pipeline);
-
-        // Wait for the task so that the tuner is released before a request for
-        // streaming in the next iteration.
-        let _ = handle.await;
-
-        epg.do_send(FlushSchedulesMessage {
-            triples: triples.into_iter().collect(),
-        });
+        drop(sender);
 
         log::debug!("Collected {} EIT sections in {}",
                     num_sections, channel.name);
@@ -219,6 +226,53 @@ impl EitCollector {
     }
 }
 
+// Sends EIT sections to `Epg` in chunks.
+//
+// Remaining sections are sent and collected schedules are flushed when dropped.
+// So, sections collected before the collection is aborted due to a timeout or
+// an error are also flushed.
+struct EitSectionSender {
+    epg: Addr<Epg>,
+    triples: HashSet<ServiceTriple>,
+    sections: Vec<EitSection>,
+}
+
+impl EitSectionSender {
+    fn new(epg: Addr<Epg>) -> Self {
+        EitSectionSender {
+            epg,
+            triples: HashSet::new(),
+            sections: Vec::with_capacity(EitCollector::UPDATE_CHUNK_SIZE),
+        }
+    }
+
+    fn push(&mut self, eit: EitSection) {
+        self.triples.insert(eit.service_triple());
+        self.sections.push(eit);
+        if self.sections.len() == EitCollector::UPDATE_CHUNK_SIZE {
+            self.send();
+        }
+    }
+
+    fn send(&mut self) {
+        let sections = std::mem::replace(
+            &mut self.sections,
+            Vec::with_capacity(EitCollector::UPDATE_CHUNK_SIZE));
+        self.epg.do_send(UpdateSchedulesMessage { sections });
+    }
+}
+
+impl Drop for EitSectionSender {
+    fn drop(&mut self) {
+        if !self.sections.is_empty() {
+            self.send();
+        }
+        self.epg.do_send(FlushSchedulesMessage {
+            triples: self.triples.drain().collect(),
+        });
+    }
+}
+
 #[derive(Clone)]
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -281,7 +335,7 @@ impl EitEvent {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug)]
 #[derive(Deserialize, Serialize)]
 #[serde(tag = "$type")]
 pub enum EitDescriptor {
@@ -298,7 +352,16 @@ pub enum EitDescriptor {
     #[serde(rename_all = "camelCase")]
     AudioComponent {
         component_type: u8,
+        #[serde(default)]
         sampling_rate: u8,
+        #[serde(default)]
+        component_tag: u8,
+        #[serde(default)]
+        main_component_flag: bool,
+        // ISO 639 language codes.  Two codes are specified for a bilingual
+        // stream.
+        #[serde(default)]
+        langs: Vec<String>,
     },
     #[serde(rename_all = "camelCase")]
     Content {
@@ -308,4 +371,75 @@ pub enum EitDescriptor {
     ExtendedEvent {
         items: Vec<(String, String)>,
     },
+    #[serde(rename_all = "camelCase")]
+    Series {
+        series_id: u16,
+        #[serde(default)]
+        repeat_label: u8,
+        #[serde(default)]
+        program_pattern: u8,
+        // UNIX time in milliseconds.
+        #[serde(default)]
+        expire_date: Option<i64>,
+        #[serde(default)]
+        episode_number: u16,
+        #[serde(default)]
+        last_episode_number: u16,
+        #[serde(default)]
+        series_name: Option<String>,
+    },
+    #[serde(rename_all = "camelCase")]
+    DataContent {
+        #[serde(default)]
+        data_component_id: u16,
+    },
+    #[serde(rename_all = "camelCase")]
+    EventGroup {
+        group_type: u8,
+        events: Vec<EitEventGroupItem>,
+    },
+}
+
+// An event in an event group descriptor.  The original network ID and the
+// transport stream ID are specified only for events in other networks.
+#[derive(Clone, Debug)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EitEventGroupItem {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original_network_id: Option<NetworkId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transport_stream_id: Option<TransportStreamId>,
+    pub service_id: ServiceId,
+    pub event_id: EventId,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+
+    #[test]
+    fn test_eit_descriptor_defaults() {
+        let desc = serde_json::from_str::<EitDescriptor>(r#"{
+            "$type": "Series",
+            "seriesId": 1
+        }"#).unwrap();
+        assert_matches!(desc, EitDescriptor::Series {
+            series_id: 1,
+            repeat_label: 0,
+            program_pattern: 0,
+            expire_date: None,
+            episode_number: 0,
+            last_episode_number: 0,
+            series_name: None,
+        });
+
+        let desc = serde_json::from_str::<EitDescriptor>(r#"{
+            "$type": "DataContent"
+        }"#).unwrap();
+        assert_matches!(desc, EitDescriptor::DataContent {
+            data_component_id: 0,
+        });
+    }
 }
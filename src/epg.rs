@@ -1,9 +1,7 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
 use std::iter::FromIterator;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use actix::prelude::*;
@@ -15,8 +13,10 @@ use serde::{Deserialize, Serialize};
 use crate::config::{Config, ChannelConfig};
 use crate::datetime_ext::*;
 use crate::eit_feeder::*;
+use crate::epg_storage::{self, EpgStorage};
 use crate::error::Error;
 use crate::models::*;
+use crate::search_index::ProgramSearchIndex;
 
 pub fn start(config: Arc<Config>) -> Addr<Epg> {
     // Start on a new Arbiter instead of the system Arbiter.
@@ -35,6 +35,14 @@ pub struct Epg {
     clocks: HashMap<ServiceTriple, Clock>,
     schedules: HashMap<ServiceTriple, EpgSchedule>,
     airtimes: HashMap<EventQuad, Airtime>,
+    logos: HashSet<(NetworkId, i16)>,
+    search_index: ProgramSearchIndex,
+    storage: Option<Box<dyn EpgStorage>>,
+    // Schedules which have been changed since the last save.
+    updated_schedules: HashSet<ServiceTriple>,
+    services_updated_at: Option<DateTime<Jst>>,
+    clocks_updated_at: Option<DateTime<Jst>>,
+    schedules_updated_at: Option<DateTime<Jst>>,
 }
 
 pub struct Airtime {
@@ -50,6 +58,13 @@ impl Epg {
             clocks: HashMap::new(),
             schedules: HashMap::new(),
             airtimes: HashMap::new(),
+            logos: HashSet::new(),
+            search_index: Default::default(),
+            storage: None,
+            updated_schedules: HashSet::new(),
+            services_updated_at: None,
+            clocks_updated_at: None,
+            schedules_updated_at: None,
         }
     }
 
@@ -58,11 +73,13 @@ impl Epg {
         results: Vec<(EpgChannel, Option<IndexMap<ServiceTriple, EpgService>>)>,
     ) {
         let mut services = IndexMap::new();
+        let mut updated = false;
 
         for (channel, result) in results.into_iter() {
             match result {
                 Some(new_services) => {
                     services.extend(new_services);
+                    updated = true;
                 }
                 None => {
                     // Failed to scan services for some reason.  Reuse old
@@ -77,6 +94,10 @@ impl Epg {
         }
 
         self.services = services;
+        if updated {
+            self.services_updated_at = Some(Jst::now());
+        }
+        self.update_has_logo_data();
 
         match self.save_services() {
             Ok(_) => (),
@@ -89,11 +110,13 @@ impl Epg {
         results: Vec<(EpgChannel, Option<HashMap<ServiceTriple, Clock>>)>,
     ) {
         let mut clocks = HashMap::new();
+        let mut updated = false;
 
         for (channel, result) in results.into_iter() {
             match result {
                 Some(new_clocks) => {
-                    clocks.extend(new_clocks)
+                    clocks.extend(new_clocks);
+                    updated = true;
                 }
                 None => {
                     // Failed to synchronize clocks for some reason.  Reuse old
@@ -113,6 +136,9 @@ impl Epg {
         }
 
         self.clocks = clocks;
+        if updated {
+            self.clocks_updated_at = Some(Jst::now());
+        }
 
         match self.save_clocks() {
             Ok(_) => (),
@@ -121,23 +147,42 @@ impl Epg {
     }
 
     fn update_schedules(&mut self, sections: Vec<EitSection>) {
-        self.prepare_schedules(Jst::now());
+        let now = Jst::now();
+        let retention = self.ended_programs_retention();
+        self.prepare_schedules(now);
         for section in sections.into_iter() {
             let triple = section.service_triple();
-            self.schedules.entry(triple).and_modify(move |sched| {
-                sched.update(section);
-            });
+            if let Some(sched) = self.schedules.get_mut(&triple) {
+                // Events in replaced sections are lost.  Save ones which have
+                // already ended.
+                let events = sched.update(section);
+                sched.save_ended_events(events, now, retention);
+                self.updated_schedules.insert(triple);
+            }
         }
     }
 
     fn flush_schedules(&mut self, triples: Vec<ServiceTriple>) {
+        let now = Jst::now();
+        let retention = self.ended_programs_retention();
+        if !triples.is_empty() {
+            self.schedules_updated_at = Some(now);
+        }
         for triple in triples.iter() {
             let num_programs = match self.schedules.get_mut(triple) {
                 Some(schedule) => {
+                    if schedule.expire_ended_events(now, retention) > 0 {
+                        self.updated_schedules.insert(*triple);
+                    }
                     schedule.collect_programs();
+                    self.search_index.update_service(
+                        *triple, schedule.programs.values());
                     schedule.programs.len()
                 }
-                None => 0,
+                None => {
+                    self.search_index.update_service(*triple, vec![]);
+                    0
+                }
             };
             if num_programs > 0 {
                 let service = self.services.get(triple)
@@ -152,31 +197,65 @@ impl Epg {
         let mut unused_ids: HashSet<_> =
             HashSet::from_iter(self.schedules.keys().cloned());
 
-        let midnight = timestamp.date().and_hms(0, 0, 0);
+        let retention = self.ended_programs_retention();
 
         for service in self.services.values() {
             let triple = service.triple();
+            let midnight = Self::midnight_in_network(
+                &self.config, triple.nid(), timestamp);
+            let updated_schedules = &mut self.updated_schedules;
             self.schedules
                 .entry(triple)
                 .and_modify(|sched| {
                     if sched.updated_at < midnight {
                         // Save overnight events.  The overnight events will be
-                        // lost in `update_tables()`.
+                        // lost in `update_tables()`.  Previous overnight events
+                        // which have ended are saved as ended events.
+                        let events =
+                            std::mem::take(&mut sched.overnight_events);
+                        sched.save_ended_events(events, timestamp, retention);
                         sched.save_overnight_events(midnight);
+                        updated_schedules.insert(triple);
                     }
                     sched.updated_at = timestamp;
                 })
-                .or_insert(EpgSchedule::new(triple));
+                .or_insert_with(|| {
+                    updated_schedules.insert(triple);
+                    EpgSchedule::new(triple)
+                });
             unused_ids.remove(&triple);
         }
 
         // Removing "garbage" schedules.
         for id in unused_ids.iter() {
             self.schedules.remove(&id);
+            self.updated_schedules.remove(&id);
+            self.search_index.update_service(*id, vec![]);
             log::debug!("Removed schedule#{}", id);
         }
     }
 
+    fn ended_programs_retention(&self) -> Duration {
+        Duration::milliseconds(self.config.epg.ended_programs_retention as i64)
+    }
+
+    // Day boundaries of EPG data depend on the time zone of each network.
+    fn midnight_in_network(
+        config: &Config,
+        nid: NetworkId,
+        timestamp: DateTime<Jst>,
+    ) -> DateTime<Jst> {
+        midnight_in(&config.time_zone.offset_for(nid), timestamp)
+    }
+
+    fn open_storage(&mut self) -> Result<(), Error> {
+        self.storage = epg_storage::open(&self.config.epg)?;
+        if self.storage.is_none() {
+            log::warn!("No epg.cache-dir specified, EPG data won't be saved");
+        }
+        Ok(())
+    }
+
     // Must be called before other load functions.
     fn load_services(&mut self) -> Result<(), Error> {
         let channels: Vec<EpgChannel> = self.config.channels.iter()
@@ -185,153 +264,296 @@ impl Epg {
             .map(EpgChannel::from)
             .collect();
 
-        match self.config.epg.cache_dir {
-            Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("services.json");
-                log::debug!("Loading schedules from {}...",
-                            json_path.display());
-                let reader = BufReader::new(File::open(&json_path)?);
-                let services: IndexMap<ServiceTriple, EpgService> =
-                    serde_json::from_reader(reader)?;
-                // Drop a service if the channel of the service has been
-                // changed.
-                self.services = services
-                    .into_iter()
-                    .filter(|(_, sv)| {
-                        let not_changed = channels
-                            .iter()
-                            .any(|ch| ch == &sv.channel);
-                        if !not_changed {  // if changed
-                            log::debug!("Drop service#{} ({}) due to changes \
-                                         of the channel config",
-                                        sv.triple(), sv.name);
-                        }
-                        not_changed
-                    })
-                    .collect();
-                log::info!("Loaded {} services", self.services.len());
-            }
-            None => {
-                log::warn!("No epg.cache-dir specified, skip to load services");
-            }
+        if let Some(ref storage) = self.storage {
+            log::debug!("Loading services...");
+            let stored = storage.load_services()?;
+            // Drop a service if the channel of the service has been changed.
+            self.services = stored.data
+                .into_iter()
+                .filter(|(_, sv)| {
+                    let not_changed = channels
+                        .iter()
+                        .any(|ch| ch == &sv.channel);
+                    if !not_changed {  // if changed
+                        log::debug!("Drop service#{} ({}) due to changes \
+                                     of the channel config",
+                                    sv.triple(), sv.name);
+                    }
+                    not_changed
+                })
+                .collect();
+            self.services_updated_at = stored.updated_at;
+            log::info!("Loaded {} services", self.services.len());
         }
         Ok(())
     }
 
     fn load_clocks(&mut self) -> Result<(), Error> {
-        match self.config.epg.cache_dir {
-            Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("clocks.json");
-                log::debug!("Loading clocks from {}...", json_path.display());
-                let reader = BufReader::new(File::open(&json_path)?);
-                let clocks: HashMap<ServiceTriple, Clock> =
-                    serde_json::from_reader(reader)?;
-                // Drop a clock if the service triple of the clock is not
-                // contained in `self::services`.
-                self.clocks = clocks
-                    .into_iter()
-                    .filter(|(triple, _)| {
-                        let contained = self.services.contains_key(triple);
-                        if !contained {
-                            log::debug!(
-                                "Drop clock for missing service#{}", triple);
-                        }
-                        contained
-                    })
-                    .collect();
-                log::info!("Loaded {} clocks", self.clocks.len());
-            }
-            None => {
-                log::warn!("No epg.cache-dir specified, skip to load clock");
-            }
+        if let Some(ref storage) = self.storage {
+            log::debug!("Loading clocks...");
+            let stored = storage.load_clocks()?;
+            // Drop a clock if the service triple of the clock is not
+            // contained in `self::services`.
+            let services = &self.services;
+            self.clocks = stored.data
+                .into_iter()
+                .filter(|(triple, _)| {
+                    let contained = services.contains_key(triple);
+                    if !contained {
+                        log::debug!(
+                            "Drop clock for missing service#{}", triple);
+                    }
+                    contained
+                })
+                .collect();
+            self.clocks_updated_at = stored.updated_at;
+            log::info!("Loaded {} clocks", self.clocks.len());
         }
         Ok(())
     }
 
     fn load_schedules(&mut self) -> Result<(), Error> {
-        match self.config.epg.cache_dir {
-            Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("schedules.json");
-                log::debug!("Loading schedules from {}...", json_path.display());
-                let reader = BufReader::new(File::open(&json_path)?);
-                let schedules: HashMap<ServiceTriple, EpgSchedule> =
-                    serde_json::from_reader(reader)?;
-                // Drop a clock if the service triple of the clock is not
-                // contained in `self::services`.
-                self.schedules = schedules
-                    .into_iter()
-                    .filter(|(triple, _)| {
-                        let contained = self.services.contains_key(triple);
-                        if !contained {
-                            log::debug!(
-                                "Drop schedule for missing service#{}", triple);
-                        }
-                        contained
-                    })
-                    .collect();
-                log::info!("Loaded schedules for {} services", self.schedules.len());
-            }
-            None => {
-                log::warn!("No epg.cache-dir specified, skip to load");
-            }
+        if let Some(ref storage) = self.storage {
+            log::debug!("Loading schedules...");
+            let stored = storage.load_schedules()?;
+            // Drop a schedule if the service triple of the schedule is not
+            // contained in `self::services`.
+            let services = &self.services;
+            self.schedules = stored.data
+                .into_iter()
+                .filter(|(triple, _)| {
+                    let contained = services.contains_key(triple);
+                    if !contained {
+                        log::debug!(
+                            "Drop schedule for missing service#{}", triple);
+                    }
+                    contained
+                })
+                .collect();
+            self.schedules_updated_at = stored.updated_at;
+            log::info!("Loaded schedules for {} services", self.schedules.len());
         }
         Ok(())
     }
 
-    fn save_services(&self) -> Result<(), Error> {
-        match self.config.epg.cache_dir {
-            Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("services.json");
-                log::debug!("Saving services into {}...", json_path.display());
-                let writer = BufWriter::new(File::create(&json_path)?);
-                serde_json::to_writer(writer, &self.services)?;
-                log::info!("Saved {} services", self.services.len());
-            }
-            None => {
-                log::warn!("No epg.cache-dir specified, skip to save services");
-            }
+    fn save_services(&mut self) -> Result<(), Error> {
+        if let Some(ref mut storage) = self.storage {
+            log::debug!("Saving services...");
+            storage.save_services(&self.services)?;
+            log::info!("Saved {} services", self.services.len());
         }
         Ok(())
     }
 
-    fn save_clocks(&self) -> Result<(), Error> {
-        match self.config.epg.cache_dir {
-            Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("clocks.json");
-                log::debug!("Saving clocks into {}...", json_path.display());
-                let writer = BufWriter::new(File::create(&json_path)?);
-                serde_json::to_writer(writer, &self.clocks)?;
-                log::info!("Saved {} clocks", self.clocks.len());
-            }
+    fn save_clocks(&mut self) -> Result<(), Error> {
+        if let Some(ref mut storage) = self.storage {
+            log::debug!("Saving clocks...");
+            storage.save_clocks(&self.clocks)?;
+            log::info!("Saved {} clocks", self.clocks.len());
+        }
+        Ok(())
+    }
+
+    fn save_schedules(&mut self) -> Result<(), Error> {
+        if let Some(ref mut storage) = self.storage {
+            log::debug!("Saving schedules...");
+            storage.save_schedules(&self.schedules, &self.updated_schedules)?;
+            self.updated_schedules.clear();
+            log::info!("Saved schedules for {} services",
+                       self.schedules.len());
+        }
+        Ok(())
+    }
+
+    fn update_logos(
+        &mut self,
+        results: Vec<(EpgChannel, Option<Vec<EpgLogo>>)>,
+    ) -> ResponseActFuture<Self, ()> {
+        let logos_dir = match self.config.epg.cache_dir {
+            Some(ref cache_dir) => PathBuf::from(cache_dir).join("logos"),
             None => {
-                log::warn!("No epg.cache-dir specified, skip to save clocks");
+                log::warn!("No epg.cache-dir specified, skip to save logos");
+                return Box::pin(actix::fut::ready(()));
+            }
+        };
+
+        // Choose a logo of the largest type for each logo ID.
+        let mut logos: HashMap<(NetworkId, i16), EpgLogo> = HashMap::new();
+        for logo in results.into_iter().filter_map(|(_, logos)| logos).flatten() {
+            let key = (logo.nid, logo.logo_id);
+            match logos.get(&key) {
+                Some(found) if found.logo_type >= logo.logo_type => (),
+                _ => {
+                    logos.insert(key, logo);
+                }
             }
         }
-        Ok(())
+
+        // Writing files may block the actor for a long time.
+        let fut = tokio::task::spawn_blocking(move || {
+            save_logos(&logos_dir, logos)
+        });
+
+        Box::pin(actix::fut::wrap_future::<_, Self>(fut)
+            .map(|result, act, _| {
+                match result {
+                    Ok(Ok(saved)) => {
+                        log::info!("Saved {} logos", saved.len());
+                        act.logos.extend(saved);
+                        act.update_has_logo_data();
+                    }
+                    Ok(Err(err)) =>
+                        log::error!("Failed to save logos: {}", err),
+                    Err(err) =>
+                        log::error!("Failed to save logos: {}", err),
+                }
+            }))
     }
 
-    fn save_schedules(&self) -> Result<(), Error> {
+    fn load_logos(&mut self) -> Result<(), Error> {
         match self.config.epg.cache_dir {
             Some(ref cache_dir) => {
-                let json_path = PathBuf::from(cache_dir).join("schedules.json");
-                log::debug!("Saving schedules into {}...", json_path.display());
-                let writer = BufWriter::new(File::create(&json_path)?);
-                serde_json::to_writer(writer, &self.schedules)?;
-                log::info!("Saved schedules for {} services",
-                           self.schedules.len());
+                let logos_dir = PathBuf::from(cache_dir).join("logos");
+                if !logos_dir.is_dir() {
+                    log::debug!("No logos found");
+                    return Ok(());
+                }
+                log::debug!("Loading logos from {}...", logos_dir.display());
+                for entry in std::fs::read_dir(&logos_dir)? {
+                    let path = entry?.path();
+                    match parse_logo_filename(&path) {
+                        Some(key) => {
+                            self.logos.insert(key);
+                        }
+                        None => log::debug!("Ignore {}", path.display()),
+                    }
+                }
+                log::info!("Loaded {} logos", self.logos.len());
+                self.update_has_logo_data();
             }
             None => {
-                log::warn!(
-                    "No epg.cache-dir specified, skip to save schedules");
+                log::warn!("No epg.cache-dir specified, skip to load logos");
             }
         }
         Ok(())
     }
 
+    fn update_has_logo_data(&mut self) {
+        let logos = &self.logos;
+        for service in self.services.values_mut() {
+            service.has_logo_data =
+                logos.contains(&(service.nid, service.logo_id));
+        }
+    }
+
+    fn query_logo(&self, nid: NetworkId, sid: ServiceId) -> Result<PathBuf, Error> {
+        let service = self.services.values()
+            .find(|sv| sv.nid == nid && sv.sid == sid)
+            .ok_or(Error::ServiceNotFound)?;
+        if !service.has_logo_data {
+            return Err(Error::LogoNotFound);
+        }
+        match self.config.epg.cache_dir {
+            Some(ref cache_dir) => Ok(PathBuf::from(cache_dir)
+                .join("logos")
+                .join(logo_filename(service.nid, service.logo_id))),
+            None => Err(Error::LogoNotFound),
+        }
+    }
+
     fn collect_programs(&mut self) {
         for schedule in self.schedules.values_mut() {
             schedule.collect_programs();
         }
+        self.build_search_index();
+    }
+
+    fn build_search_index(&mut self) {
+        self.search_index = ProgramSearchIndex::build(
+            self.schedules.values()
+                .flat_map(|schedule| schedule.programs.values()));
+        log::debug!("Built the search index");
+    }
+
+    fn search_programs(&self, query: &str) -> Vec<EpgProgram> {
+        let mut programs: Vec<EpgProgram> = self.search_index
+            .search(query)
+            .into_iter()
+            .filter_map(|quad| {
+                self.schedules
+                    .get(&ServiceTriple::from(quad))
+                    .and_then(|schedule| schedule.programs.get(&quad.eid()))
+            })
+            .filter(|program| ProgramSearchIndex::matches(program, query))
+            .cloned()
+            .collect();
+        programs.sort_by_key(|program| (program.start_at, program.quad.value()));
+        programs
+    }
+
+    fn query_status(&self) -> EpgStatus {
+        EpgStatus {
+            num_services: self.services.len(),
+            num_clocks: self.clocks.len(),
+            num_programs: self.schedules.values()
+                .map(|schedule| schedule.programs.len())
+                .sum(),
+            services_updated_at: self.services_updated_at,
+            clocks_updated_at: self.clocks_updated_at,
+            schedules_updated_at: self.schedules_updated_at,
+        }
+    }
+}
+
+// Returns the keys of logos which have been saved successfully.
+fn save_logos(
+    logos_dir: &Path,
+    logos: HashMap<(NetworkId, i16), EpgLogo>,
+) -> Result<Vec<(NetworkId, i16)>, Error> {
+    std::fs::create_dir_all(logos_dir)?;
+    let mut saved = Vec::with_capacity(logos.len());
+    for ((nid, logo_id), logo) in logos.into_iter() {
+        let png_path = logos_dir.join(logo_filename(nid, logo_id));
+        log::debug!("Saving a logo into {}...", png_path.display());
+        match std::fs::write(&png_path, &logo.data) {
+            Ok(_) => saved.push((nid, logo_id)),
+            Err(err) => log::error!("Failed to save a logo into {}: {}",
+                                    png_path.display(), err),
+        }
+    }
+    Ok(saved)
+}
+
+fn logo_filename(nid: NetworkId, logo_id: i16) -> String {
+    format!("{}_{}.png", nid.value(), logo_id)
+}
+
+fn parse_logo_filename(path: &Path) -> Option<(NetworkId, i16)> {
+    if path.extension()? != "png" {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let mut parts = stem.splitn(2, '_');
+    let nid = parts.next()?.parse::<u16>().ok()?;
+    let logo_id = parts.next()?.parse::<i16>().ok()?;
+    Some((NetworkId::from(nid), logo_id))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct EpgStatus {
+    pub num_services: usize,
+    pub num_clocks: usize,
+    pub num_programs: usize,
+    pub services_updated_at: Option<DateTime<Jst>>,
+    pub clocks_updated_at: Option<DateTime<Jst>>,
+    pub schedules_updated_at: Option<DateTime<Jst>>,
+}
+
+impl EpgStatus {
+    // Ready for serving EPG data and streams of programs.
+    pub fn is_ready(&self) -> bool {
+        self.num_services > 0 && self.num_clocks > 0 &&
+            self.schedules_updated_at.is_some()
     }
 }
 
@@ -340,6 +562,9 @@ impl Actor for Epg {
 
     fn started(&mut self, _: &mut Self::Context) {
         log::debug!("Started");
+        if let Err(err) = self.open_storage() {
+            log::error!("Failed to open the EPG storage: {}", err);
+        }
         if let Err(err) = self.load_services() {
             log::error!("Failed to load services: {}", err);
         }
@@ -349,6 +574,9 @@ impl Actor for Epg {
         if let Err(err) = self.load_schedules() {
             log::error!("Failed to load schedules: {}", err);
         }
+        if let Err(err) = self.load_logos() {
+            log::error!("Failed to load logos: {}", err);
+        }
         self.collect_programs();
     }
 
@@ -357,6 +585,33 @@ impl Actor for Epg {
     }
 }
 
+// query status
+
+pub struct QueryEpgStatusMessage;
+
+impl fmt::Display for QueryEpgStatusMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryEpgStatus")
+    }
+}
+
+impl Message for QueryEpgStatusMessage {
+    type Result = Result<EpgStatus, Error>;
+}
+
+impl Handler<QueryEpgStatusMessage> for Epg {
+    type Result = Result<EpgStatus, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryEpgStatusMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        Ok(self.query_status())
+    }
+}
+
 // query channels
 
 pub struct QueryChannelsMessage;
@@ -512,6 +767,47 @@ impl Handler<QueryServiceMessage> for Epg {
     }
 }
 
+// query service programs
+
+pub struct QueryServiceProgramsMessage {
+    pub nid: NetworkId,
+    pub sid: ServiceId,
+    pub from: Option<DateTime<Jst>>,
+    pub to: Option<DateTime<Jst>>,
+}
+
+impl fmt::Display for QueryServiceProgramsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryServicePrograms By ({}, {}) in [{:?}, {:?})",
+               self.nid, self.sid, self.from, self.to)
+    }
+}
+
+impl Message for QueryServiceProgramsMessage {
+    type Result = Result<Vec<EpgProgram>, Error>;
+}
+
+impl Handler<QueryServiceProgramsMessage> for Epg {
+    type Result = Result<Vec<EpgProgram>, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryServiceProgramsMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        let triple = self.services
+            .values()
+            .find(|sv| sv.nid == msg.nid && sv.sid == msg.sid)
+            .map(|sv| sv.triple())
+            .ok_or(Error::ServiceNotFound)?;
+        Ok(self.schedules
+           .get(&triple)
+           .map(|schedule| schedule.query_programs(msg.from, msg.to))
+           .unwrap_or_default())
+    }
+}
+
 // query clock
 
 pub struct QueryClockMessage {
@@ -572,6 +868,86 @@ impl Handler<QueryProgramsMessage> for Epg {
     }
 }
 
+// query programs with filter
+
+pub struct QueryProgramsWithFilterMessage {
+    pub filter: ProgramFilter,
+}
+
+impl fmt::Display for QueryProgramsWithFilterMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryProgramsWithFilter by {:?}", self.filter)
+    }
+}
+
+impl Message for QueryProgramsWithFilterMessage {
+    type Result = Result<Vec<EpgProgram>, Error>;
+}
+
+impl Handler<QueryProgramsWithFilterMessage> for Epg {
+    type Result = Result<Vec<EpgProgram>, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryProgramsWithFilterMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        let filter = &msg.filter;
+        let now = Jst::now();
+        // Clone only programs to be returned.
+        let mut programs: Vec<&EpgProgram> = self.schedules
+            .iter()
+            .filter(|(triple, _)| filter.matches_service(triple))
+            .flat_map(|(_, schedule)| {
+                if filter.ended {
+                    schedule.query_ended_programs(now)
+                } else {
+                    schedule.programs.values().collect()
+                }
+            })
+            .filter(|program| filter.matches(program))
+            .collect();
+        // Sort programs in order to paginate them.
+        programs.sort_by_key(|program| (program.start_at, program.quad.value()));
+        let programs = programs.into_iter().skip(filter.offset);
+        let programs = match filter.limit {
+            Some(limit) => programs.take(limit).cloned().collect(),
+            None => programs.cloned().collect(),
+        };
+        Ok(programs)
+    }
+}
+
+// search programs
+
+pub struct SearchProgramsMessage {
+    pub query: String,
+}
+
+impl fmt::Display for SearchProgramsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SearchPrograms by {:?}", self.query)
+    }
+}
+
+impl Message for SearchProgramsMessage {
+    type Result = Result<Vec<EpgProgram>, Error>;
+}
+
+impl Handler<SearchProgramsMessage> for Epg {
+    type Result = Result<Vec<EpgProgram>, Error>;
+
+    fn handle(
+        &mut self,
+        msg: SearchProgramsMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        Ok(self.search_programs(&msg.query))
+    }
+}
+
 // query program
 
 pub enum QueryProgramMessage {
@@ -624,6 +1000,36 @@ impl Handler<QueryProgramMessage> for Epg {
     }
 }
 
+// query logo
+
+pub struct QueryLogoMessage {
+    pub nid: NetworkId,
+    pub sid: ServiceId,
+}
+
+impl fmt::Display for QueryLogoMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryLogo by ({}, {})", self.nid, self.sid)
+    }
+}
+
+impl Message for QueryLogoMessage {
+    type Result = Result<PathBuf, Error>;
+}
+
+impl Handler<QueryLogoMessage> for Epg {
+    type Result = Result<PathBuf, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryLogoMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        self.query_logo(msg.nid, msg.sid)
+    }
+}
+
 // update services
 
 pub struct UpdateServicesMessage {
@@ -682,6 +1088,35 @@ impl Handler<UpdateClocksMessage> for Epg {
     }
 }
 
+// update logos
+
+pub struct UpdateLogosMessage {
+    pub results: Vec<(EpgChannel, Option<Vec<EpgLogo>>)>,
+}
+
+impl fmt::Display for UpdateLogosMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UpdateLogos")
+    }
+}
+
+impl Message for UpdateLogosMessage {
+    type Result = ();
+}
+
+impl Handler<UpdateLogosMessage> for Epg {
+    type Result = ResponseActFuture<Self, ()>;
+
+    fn handle(
+        &mut self,
+        msg: UpdateLogosMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        self.update_logos(msg.results)
+    }
+}
+
 // update schedules
 
 pub struct UpdateSchedulesMessage {
@@ -835,7 +1270,7 @@ impl Handler<RemoveAirtimeMessage> for Epg {
 
 #[derive(Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct EpgSchedule {
+pub struct EpgSchedule {
     service_triple: ServiceTriple,
     // In Japan, only the following indexes are used:
     //
@@ -843,29 +1278,38 @@ struct EpgSchedule {
     //    1 | 9 | 17 | 25 => the later 4 days of 8 days schedule
     tables: [Option<Box<EpgTable>>; 32],
     overnight_events: Vec<EitEvent>,
+    // Events which have already ended.  Kept until the retention period
+    // expires.
+    #[serde(default)]
+    ended_events: Vec<EitEvent>,
     #[serde(with = "serde_jst")]
     updated_at: DateTime<Jst>,
     #[serde(skip)]
     programs: HashMap<EventId, EpgProgram>,
+    #[serde(skip)]
+    ended_programs: Vec<EpgProgram>,
 }
 
 impl EpgSchedule {
-    fn new(triple: ServiceTriple) -> EpgSchedule {
+    pub fn new(triple: ServiceTriple) -> EpgSchedule {
         EpgSchedule {
             service_triple: triple,
             tables: Default::default(),
             overnight_events: Vec::new(),
+            ended_events: Vec::new(),
             updated_at: Jst::now(),
             programs: HashMap::new(),
+            ended_programs: Vec::new(),
         }
     }
 
-    fn update(&mut self, section: EitSection) {
+    // Returns events in sections replaced with `section`.
+    fn update(&mut self, section: EitSection) -> Vec<EitEvent> {
         let i = section.table_index();
         if self.tables[i].is_none() {
             self.tables[i] = Some(Box::new(EpgTable::default()));
         }
-        self.tables[i].as_mut().unwrap().update(section);
+        self.tables[i].as_mut().unwrap().update(section)
     }
 
     fn save_overnight_events(&mut self, midnight: DateTime<Jst>) {
@@ -880,6 +1324,58 @@ impl EpgSchedule {
         self.overnight_events = events;
     }
 
+    // Saves events in `events` which have ended before `now` so that they are
+    // not lost when they are removed from tables.  Events which have already
+    // expired are ignored.
+    //
+    // Returns the number of saved events.
+    fn save_ended_events(
+        &mut self,
+        events: Vec<EitEvent>,
+        now: DateTime<Jst>,
+        retention: Duration,
+    ) -> usize {
+        let expired_at = now - retention;
+        let mut num_saved = 0;
+        for event in events.into_iter() {
+            if event.end_time() > now || event.end_time() <= expired_at {
+                continue;
+            }
+            let saved = self.ended_events.iter().any(|saved| {
+                saved.event_id == event.event_id &&
+                    saved.start_time == event.start_time
+            });
+            if !saved {
+                self.ended_events.push(event);
+                num_saved += 1;
+            }
+        }
+        if num_saved > 0 {
+            log::debug!("Saved {} ended events of schedule#{}",
+                        num_saved, self.service_triple);
+        }
+        num_saved
+    }
+
+    // Removes saved events which have ended before `now - retention`.
+    //
+    // Returns the number of removed events.
+    fn expire_ended_events(
+        &mut self,
+        now: DateTime<Jst>,
+        retention: Duration,
+    ) -> usize {
+        let expired_at = now - retention;
+        let num_events = self.ended_events.len();
+        self.ended_events.retain(|event| event.end_time() > expired_at);
+        let num_removed = num_events - self.ended_events.len();
+        if num_removed > 0 {
+            log::debug!("Removed {} expired events of schedule#{}",
+                        num_removed, self.service_triple);
+        }
+        num_removed
+    }
+
     fn collect_programs(&mut self) {
         let mut programs = HashMap::new();
         for event in self.overnight_events.iter() {
@@ -896,6 +1392,76 @@ impl EpgSchedule {
             }
         }
         self.programs = programs;
+
+        self.ended_programs = self.ended_events
+            .iter()
+            .map(|event| {
+                let quad = EventQuad::from(
+                    (self.service_triple, EventId::from(event.event_id)));
+                let mut program = EpgProgram::new(quad);
+                program.update(event);
+                program
+            })
+            .collect();
+    }
+
+    // Returns programs which have ended before `now`, including programs which
+    // have not been saved yet.
+    fn query_ended_programs(&self, now: DateTime<Jst>) -> Vec<&EpgProgram> {
+        let saved: HashSet<(EventId, DateTime<Jst>)> = self.ended_programs
+            .iter()
+            .map(|program| (program.quad.eid(), program.start_at))
+            .collect();
+        let not_saved = self.programs
+            .values()
+            .filter(|program| program.end_at() <= now)
+            .filter(|program| {
+                !saved.contains(&(program.quad.eid(), program.start_at))
+            });
+        self.ended_programs.iter().chain(not_saved).collect()
+    }
+
+    // Returns programs overlapping the time window, sorted by `start_at`.
+    //
+    // EIT sections may contain overlapping events when a schedule is changed
+    // but some of the sections haven't been updated yet.  An overlapping
+    // program is shortened so that it ends when the next program starts.  A
+    // program is removed if it starts at the same time as the next program.
+    fn query_programs(
+        &self,
+        from: Option<DateTime<Jst>>,
+        to: Option<DateTime<Jst>>,
+    ) -> Vec<EpgProgram> {
+        let mut programs: Vec<&EpgProgram> = self.programs.values().collect();
+        programs.sort_by_key(|program| (program.start_at, program.quad.value()));
+
+        // Resolve overlaps before filtering because the duration of a program
+        // depends on the next one.  Only programs in the range are cloned.
+        let mut resolved: Vec<(&EpgProgram, Duration)> =
+            Vec::with_capacity(programs.len());
+        for program in programs.into_iter() {
+            if let Some((prev, duration)) = resolved.last_mut() {
+                if prev.start_at == program.start_at {
+                    resolved.pop();
+                } else if prev.start_at + *duration > program.start_at {
+                    *duration = program.start_at - prev.start_at;
+                }
+            }
+            resolved.push((program, program.duration));
+        }
+
+        resolved
+            .into_iter()
+            .filter(|(program, duration)| {
+                from.map_or(true, |from| program.start_at + *duration > from) &&
+                    to.map_or(true, |to| program.start_at < to)
+            })
+            .map(|(program, duration)| {
+                let mut program = program.clone();
+                program.duration = duration;
+                program
+            })
+            .collect()
     }
 }
 
@@ -913,9 +1479,10 @@ struct EpgTable {
 }
 
 impl EpgTable {
-    fn update(&mut self, section: EitSection) {
+    // Returns events in sections replaced with `section`.
+    fn update(&mut self, section: EitSection) -> Vec<EitEvent> {
         let i = section.segment_index();
-        self.segments[i].update(section);
+        self.segments[i].update(section)
     }
 
     fn collect_overnight_events(
@@ -950,14 +1517,24 @@ struct EpgSegment {
 }
 
 impl EpgSegment {
-    fn update(&mut self, section: EitSection) {
+    // Returns events in sections replaced with `section`.
+    fn update(&mut self, section: EitSection) -> Vec<EitEvent> {
+        let mut events = Vec::new();
+
         let n = section.last_section_index() + 1;
         for i in n..8 {
-            self.sections[i] = None;
+            if let Some(removed) = self.sections[i].take() {
+                events.extend(removed.events);
+            }
         }
 
         let i = section.section_index();
-        self.sections[i] = Some(EpgSection::from(section));
+        if let Some(replaced) =
+            self.sections[i].replace(EpgSection::from(section)) {
+            events.extend(replaced.events);
+        }
+
+        events
     }
 
     fn collect_overnight_events(
@@ -1078,6 +1655,9 @@ pub struct EpgService {
     pub remote_control_key_id: u16,
     pub name: String,
     pub channel: EpgChannel,
+    // Not saved.  Updated with logos in the cache dir.
+    #[serde(skip)]
+    pub has_logo_data: bool,
 }
 
 impl EpgService {
@@ -1098,6 +1678,14 @@ impl EpgService {
     }
 }
 
+pub struct EpgLogo {
+    pub nid: NetworkId,
+    pub logo_id: i16,
+    pub logo_type: u8,
+    // PNG data.
+    pub data: Vec<u8>,
+}
+
 impl Into<MirakurunChannelService> for EpgService {
     fn into(self) -> MirakurunChannelService {
         MirakurunChannelService {
@@ -1120,7 +1708,87 @@ pub struct EpgProgram {
     pub extended: Option<IndexMap<String, String>>,
     pub video: Option<EpgVideoInfo>,
     pub audio: Option<EpgAudioInfo>,
+    pub audios: Option<Vec<EpgAudioComponentInfo>>,
     pub genres: Option<Vec<EpgGenre>>,
+    pub series: Option<EpgSeriesInfo>,
+    pub related_items: Option<Vec<EpgRelatedItem>>,
+    pub has_caption: bool,
+}
+
+// A filter for querying programs.  Programs matching all the specified
+// conditions are returned.
+#[derive(Clone, Debug, Default)]
+pub struct ProgramFilter {
+    pub nid: Option<NetworkId>,
+    pub sid: Option<ServiceId>,
+    pub eid: Option<EventId>,
+    // Programs overlapping the time window are matched.
+    pub from: Option<DateTime<Jst>>,
+    pub to: Option<DateTime<Jst>>,
+    pub genre_lv1: Option<u8>,
+    pub genre_lv2: Option<u8>,
+    // Matched with the name or the description.
+    pub keyword: Option<String>,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    // Query ended programs instead of programs in the current schedules.
+    pub ended: bool,
+}
+
+impl ProgramFilter {
+    fn matches_service(&self, triple: &ServiceTriple) -> bool {
+        if let Some(nid) = self.nid {
+            if triple.nid() != nid {
+                return false;
+            }
+        }
+        if let Some(sid) = self.sid {
+            if triple.sid() != sid {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn matches(&self, program: &EpgProgram) -> bool {
+        if let Some(eid) = self.eid {
+            if program.quad.eid() != eid {
+                return false;
+            }
+        }
+        if let Some(from) = self.from {
+            if program.end_at() <= from {
+                return false;
+            }
+        }
+        if let Some(to) = self.to {
+            if program.start_at >= to {
+                return false;
+            }
+        }
+        if self.genre_lv1.is_some() || self.genre_lv2.is_some() {
+            let matched = program.genres
+                .iter()
+                .flatten()
+                .any(|genre| {
+                    self.genre_lv1.map_or(true, |lv1| genre.lv1 == lv1) &&
+                        self.genre_lv2.map_or(true, |lv2| genre.lv2 == lv2)
+                });
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(ref keyword) = self.keyword {
+            let matched = program.name
+                .iter()
+                .chain(program.description.iter())
+                .any(|text| text.contains(keyword.as_str()));
+            if !matched {
+                return false;
+            }
+        }
+        true
+    }
 }
 
 impl EpgProgram {
@@ -1135,7 +1803,11 @@ impl EpgProgram {
             extended: None,
             video: None,
             audio: None,
+            audios: None,
             genres: None,
+            series: None,
+            related_items: None,
+            has_caption: false,
         }
     }
 
@@ -1147,6 +1819,8 @@ impl EpgProgram {
         self.start_at = event.start_time.clone();
         self.duration = event.duration.clone();
         self.scrambled = event.scrambled;
+        let mut audios = Vec::new();
+        let mut related_items = Vec::new();
         for desc in event.descriptors.iter() {
             match desc {
                 EitDescriptor::ShortEvent { event_name, text } => {
@@ -1158,9 +1832,16 @@ impl EpgProgram {
                         EpgVideoInfo::new(*stream_content, *component_type));
                 }
                 EitDescriptor::AudioComponent {
-                    component_type, sampling_rate } => {
-                    self.audio = Some(
-                        EpgAudioInfo::new(*component_type, *sampling_rate));
+                    component_type, sampling_rate, component_tag,
+                    main_component_flag, langs } => {
+                    // The main audio is preferred.
+                    if self.audio.is_none() || *main_component_flag {
+                        self.audio = Some(EpgAudioInfo::new(
+                            *component_type, *sampling_rate));
+                    }
+                    audios.push(EpgAudioComponentInfo::new(
+                        *component_type, *component_tag, *main_component_flag,
+                        *sampling_rate, langs.clone()));
                 }
                 EitDescriptor::Content { nibbles } => {
                     self.genres = Some(nibbles.iter()
@@ -1172,15 +1853,55 @@ impl EpgProgram {
                     map.extend(items.clone());
                     self.extended = Some(map);
                 }
+                EitDescriptor::Series {
+                    series_id, repeat_label, program_pattern, expire_date,
+                    episode_number, last_episode_number, series_name } => {
+                    self.series = Some(EpgSeriesInfo {
+                        id: *series_id,
+                        repeat: *repeat_label,
+                        pattern: *program_pattern,
+                        expires_at: *expire_date,
+                        episode: *episode_number,
+                        last_episode: *last_episode_number,
+                        name: series_name.clone(),
+                    });
+                }
+                EitDescriptor::DataContent { data_component_id } => {
+                    // ARIB caption coding (STD-B24)
+                    if *data_component_id == 0x0008 {
+                        self.has_caption = true;
+                    }
+                }
+                EitDescriptor::EventGroup { group_type, events } => {
+                    let item_type =
+                        match EpgRelatedItemType::from_group_type(*group_type) {
+                            Some(item_type) => item_type,
+                            None => continue,
+                        };
+                    related_items.extend(events.iter().map(|event| {
+                        EpgRelatedItem {
+                            item_type,
+                            network_id: event.original_network_id,
+                            service_id: event.service_id,
+                            event_id: event.event_id,
+                        }
+                    }));
+                }
             }
         }
+        if !audios.is_empty() {
+            self.audios = Some(audios);
+        }
+        if !related_items.is_empty() {
+            self.related_items = Some(related_items);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{Date, TimeZone};
+    use chrono::{Date, FixedOffset, TimeZone};
 
     #[test]
     fn test_update_services() {
@@ -1196,6 +1917,7 @@ mod tests {
                 remote_control_key_id: 0,
                 name: name.to_string(),
                 channel,
+                has_logo_data: false,
             }
         }
 
@@ -1396,6 +2118,25 @@ mod tests {
         assert_eq!(epg.schedules[&triple].overnight_events.len(), 0);
     }
 
+    #[test]
+    fn test_epg_midnight_in_network() {
+        let mut config = Config::default();
+        config.time_zone.networks.insert(
+            1.into(), FixedOffset::west(3 * 60 * 60));
+
+        // 2019-10-13T10:00:00+09:00 == 2019-10-12T22:00:00-03:00
+        let timestamp = Jst.ymd(2019, 10, 13).and_hms(10, 0, 0);
+
+        assert_eq!(
+            Epg::midnight_in_network(&config, 1.into(), timestamp),
+            FixedOffset::west(3 * 60 * 60)
+                .ymd(2019, 10, 12).and_hms(0, 0, 0).with_timezone(&Jst));
+        assert_eq!(
+            Epg::midnight_in_network(&config, 2.into(), timestamp),
+            FixedOffset::east(9 * 60 * 60)
+                .ymd(2019, 10, 13).and_hms(0, 0, 0).with_timezone(&Jst));
+    }
+
     #[test]
     fn test_epg_schedule_update() {
         let triple = ServiceTriple::from((1, 2, 3));
@@ -1415,6 +2156,35 @@ mod tests {
         assert!(sched.tables[0].is_some());
     }
 
+    #[test]
+    fn test_epg_schedule_query_programs() {
+        let triple = ServiceTriple::from((1, 2, 3));
+        let mut sched = EpgSchedule::new(triple);
+        let events: [(u16, u32, i64); 5] = [
+            (1, 12, 90), (2, 13, 60), (3, 13, 30), (4, 13, 60), (5, 14, 60),
+        ];
+        for &(eid, hour, minutes) in events.iter() {
+            let mut program = EpgProgram::new((triple, eid.into()).into());
+            program.start_at = Jst.ymd(2019, 10, 13).and_hms(hour, 0, 0);
+            program.duration = Duration::minutes(minutes);
+            sched.programs.insert(eid.into(), program);
+        }
+
+        let programs = sched.query_programs(None, None);
+        assert_eq!(programs.len(), 3);
+        assert_eq!(programs[0].quad.eid(), 1.into());
+        assert_eq!(programs[0].duration, Duration::minutes(60));
+        assert_eq!(programs[1].quad.eid(), 4.into());
+        assert_eq!(programs[1].duration, Duration::minutes(60));
+        assert_eq!(programs[2].quad.eid(), 5.into());
+
+        let programs = sched.query_programs(
+            Some(Jst.ymd(2019, 10, 13).and_hms(13, 0, 0)),
+            Some(Jst.ymd(2019, 10, 13).and_hms(14, 0, 0)));
+        assert_eq!(programs.len(), 1);
+        assert_eq!(programs[0].quad.eid(), 4.into());
+    }
+
     #[test]
     fn test_epg_schedule_save_overnight_events() {
         let mut sched = create_epg_schedule_with_overnight_events(
@@ -1450,6 +2220,54 @@ mod tests {
         assert_eq!(sched.overnight_events.len(), 0);
     }
 
+    #[test]
+    fn test_epg_schedule_save_ended_events() {
+        let mut sched = create_epg_schedule_with_overnight_events(
+            ServiceTriple::from((1, 2, 3)));
+        let retention = Duration::days(7);
+        // 2019-10-13T23:00:00 - 23:30:00 and 2019-10-13T23:30:00 - 00:30:00
+        let events = create_epg_section_with_overnight_events(
+            Jst.ymd(2019, 10, 13)).events;
+
+        assert_eq!(sched.save_ended_events(
+            events.clone(), Jst.ymd(2019, 10, 13).and_hms(23, 0, 0),
+            retention), 0);
+        assert_eq!(sched.ended_events.len(), 0);
+
+        assert_eq!(sched.save_ended_events(
+            events.clone(), Jst.ymd(2019, 10, 14).and_hms(0, 0, 0),
+            retention), 1);
+        assert_eq!(sched.ended_events.len(), 1);
+
+        // Saved events are not saved twice.
+        assert_eq!(sched.save_ended_events(
+            events.clone(), Jst.ymd(2019, 10, 14).and_hms(1, 0, 0),
+            retention), 1);
+        assert_eq!(sched.ended_events.len(), 2);
+
+        sched.collect_programs();
+        assert_eq!(sched.ended_programs.len(), 2);
+        let ended = sched.query_ended_programs(
+            Jst.ymd(2019, 10, 14).and_hms(1, 0, 0));
+        assert_eq!(ended.len(), 2);
+
+        // Expired events are not saved.
+        assert_eq!(sched.save_ended_events(
+            events.clone(), Jst.ymd(2019, 10, 22).and_hms(0, 0, 0),
+            retention), 0);
+
+        // The event ended at 2019-10-13T23:30:00 is removed.
+        assert_eq!(sched.expire_ended_events(
+            Jst.ymd(2019, 10, 21).and_hms(0, 0, 0), retention), 1);
+        assert_eq!(sched.ended_events.len(), 1);
+        assert_eq!(sched.ended_events[0].start_time,
+                   Jst.ymd(2019, 10, 13).and_hms(23, 30, 0));
+
+        assert_eq!(sched.expire_ended_events(
+            Jst.ymd(2019, 10, 21).and_hms(0, 0, 0), retention), 0);
+        assert_eq!(sched.ended_events.len(), 1);
+    }
+
     #[test]
     fn test_epg_table_update() {
         let mut table: EpgTable = Default::default();
@@ -1509,6 +2327,23 @@ mod tests {
         });
         assert!(segment.sections[0].is_some());
         assert!(segment.sections[1].is_none());
+
+        // Events in replaced or removed sections are returned.
+        let mut segment = create_epg_segment_with_overnight_events(
+            Jst.ymd(2019, 10, 13));
+        let events = segment.update(EitSection {
+            original_network_id: 1.into(),
+            transport_stream_id: 2.into(),
+            service_id: 3.into(),
+            table_id: 0x50,
+            section_number: 0x00,
+            last_section_number: 0xF8,
+            segment_last_section_number: 0x00,
+            version_number: 2,
+            events: Vec::new(),
+        });
+        assert_eq!(events.len(), 2);
+        assert!(segment.sections[1].is_none());
     }
 
     #[test]
@@ -1531,6 +2366,173 @@ mod tests {
         assert_eq!(events[0].event_id, 2.into());
     }
 
+    #[test]
+    fn test_program_filter() {
+        let mut program = EpgProgram::new((1, 2, 3, 4).into());
+        program.start_at = Jst.ymd(2019, 10, 13).and_hms(12, 0, 0);
+        program.duration = Duration::minutes(30);
+        program.name = Some("name".to_string());
+        program.genres = Some(vec![EpgGenre::new((1, 2, 0, 0))]);
+
+        let filter = ProgramFilter::default();
+        assert!(filter.matches_service(&(1, 2, 3).into()));
+        assert!(filter.matches(&program));
+
+        let filter = ProgramFilter {
+            nid: Some(1.into()),
+            sid: Some(3.into()),
+            ..Default::default()
+        };
+        assert!(filter.matches_service(&(1, 2, 3).into()));
+        assert!(!filter.matches_service(&(1, 2, 4).into()));
+        assert!(!filter.matches_service(&(2, 2, 3).into()));
+
+        let filter = ProgramFilter {
+            eid: Some(5.into()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&program));
+
+        let filter = ProgramFilter {
+            from: Some(Jst.ymd(2019, 10, 13).and_hms(12, 29, 59)),
+            to: Some(Jst.ymd(2019, 10, 13).and_hms(13, 0, 0)),
+            ..Default::default()
+        };
+        assert!(filter.matches(&program));
+
+        let filter = ProgramFilter {
+            from: Some(Jst.ymd(2019, 10, 13).and_hms(12, 30, 0)),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&program));
+
+        let filter = ProgramFilter {
+            to: Some(Jst.ymd(2019, 10, 13).and_hms(12, 0, 0)),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&program));
+
+        let filter = ProgramFilter {
+            genre_lv1: Some(1),
+            genre_lv2: Some(2),
+            ..Default::default()
+        };
+        assert!(filter.matches(&program));
+
+        let filter = ProgramFilter {
+            genre_lv1: Some(1),
+            genre_lv2: Some(3),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&program));
+
+        let filter = ProgramFilter {
+            keyword: Some("am".to_string()),
+            ..Default::default()
+        };
+        assert!(filter.matches(&program));
+
+        let filter = ProgramFilter {
+            keyword: Some("x".to_string()),
+            ..Default::default()
+        };
+        assert!(!filter.matches(&program));
+    }
+
+    #[test]
+    fn test_epg_program_update_related_items() {
+        let mut program = EpgProgram::new((1, 2, 3, 4).into());
+        program.update(&EitEvent {
+            event_id: 4.into(),
+            start_time: Jst.ymd(2019, 10, 13).and_hms(0, 0, 0),
+            duration: Duration::minutes(30),
+            scrambled: false,
+            descriptors: vec![
+                EitDescriptor::EventGroup {
+                    group_type: 1,
+                    events: vec![EitEventGroupItem {
+                        original_network_id: None,
+                        transport_stream_id: None,
+                        service_id: 5.into(),
+                        event_id: 6.into(),
+                    }],
+                },
+                EitDescriptor::EventGroup {
+                    group_type: 4,
+                    events: vec![EitEventGroupItem {
+                        original_network_id: Some(7.into()),
+                        transport_stream_id: Some(8.into()),
+                        service_id: 9.into(),
+                        event_id: 10.into(),
+                    }],
+                },
+                EitDescriptor::EventGroup {
+                    group_type: 0,
+                    events: vec![],
+                },
+            ],
+        });
+        let items = program.related_items.unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].item_type, EpgRelatedItemType::Shared);
+        assert_eq!(items[0].network_id, None);
+        assert_eq!(items[0].service_id, 5.into());
+        assert_eq!(items[0].event_id, 6.into());
+        assert_eq!(items[1].item_type, EpgRelatedItemType::Relay);
+        assert_eq!(items[1].network_id, Some(7.into()));
+    }
+
+    #[test]
+    fn test_epg_program_update_audios_series_captions() {
+        let mut program = EpgProgram::new((1, 2, 3, 4).into());
+        program.update(&EitEvent {
+            event_id: 4.into(),
+            start_time: Jst.ymd(2019, 10, 13).and_hms(0, 0, 0),
+            duration: Duration::minutes(30),
+            scrambled: false,
+            descriptors: vec![
+                EitDescriptor::AudioComponent {
+                    component_type: 3,
+                    sampling_rate: 7,
+                    component_tag: 0x10,
+                    main_component_flag: false,
+                    langs: vec!["eng".to_string()],
+                },
+                EitDescriptor::AudioComponent {
+                    component_type: 2,
+                    sampling_rate: 7,
+                    component_tag: 0x11,
+                    main_component_flag: true,
+                    langs: vec!["jpn".to_string(), "eng".to_string()],
+                },
+                EitDescriptor::Series {
+                    series_id: 1,
+                    repeat_label: 0,
+                    program_pattern: 1,
+                    expire_date: None,
+                    episode_number: 3,
+                    last_episode_number: 12,
+                    series_name: Some("series".to_string()),
+                },
+                EitDescriptor::DataContent { data_component_id: 0x0008 },
+            ],
+        });
+
+        assert_eq!(program.audio.as_ref().unwrap().component_type, 2);
+        let audios = program.audios.as_ref().unwrap();
+        assert_eq!(audios.len(), 2);
+        assert_eq!(audios[0].component_tag, 0x10);
+        assert!(!audios[0].is_main);
+        assert_eq!(audios[1].sampling_rate, 48_000);
+        assert!(audios[1].is_main);
+        assert_eq!(audios[1].langs, vec!["jpn", "eng"]);
+        let series = program.series.as_ref().unwrap();
+        assert_eq!(series.id, 1);
+        assert_eq!(series.episode, 3);
+        assert_eq!(series.last_episode, 12);
+        assert!(program.has_caption);
+    }
+
     #[test]
     fn test_eit_event_is_overnight_event() {
         let event = EitEvent {
@@ -1601,7 +2603,8 @@ mod tests {
                 extra_args: String::new(),
                 services: Vec::new(),
                 excluded_services: Vec::new(),
-            }
+            },
+            has_logo_data: false,
         }
     }
 
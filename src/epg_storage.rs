@@ -0,0 +1,510 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeZone, Utc};
+use indexmap::IndexMap;
+use log;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json;
+
+use crate::config::{EpgConfig, EpgStorageType};
+use crate::datetime_ext::*;
+use crate::epg::{EpgSchedule, EpgService};
+use crate::error::Error;
+use crate::fs_util;
+use crate::models::*;
+
+pub type EpgServices = IndexMap<ServiceTriple, EpgService>;
+pub type EpgClocks = HashMap<ServiceTriple, Clock>;
+pub type EpgSchedules = HashMap<ServiceTriple, EpgSchedule>;
+
+// Data loaded from a storage together with the time when it was updated.
+pub struct Stored<T> {
+    pub data: T,
+    pub updated_at: Option<DateTime<Jst>>,
+}
+
+// A storage to persist EPG data.
+pub trait EpgStorage {
+    fn load_services(&self) -> Result<Stored<EpgServices>, Error>;
+    fn load_clocks(&self) -> Result<Stored<EpgClocks>, Error>;
+    fn load_schedules(&self) -> Result<Stored<EpgSchedules>, Error>;
+    fn save_services(&mut self, services: &EpgServices) -> Result<(), Error>;
+    fn save_clocks(&mut self, clocks: &EpgClocks) -> Result<(), Error>;
+    // Saves schedules of services specified by `triples`, and removes
+    // schedules of services which are not contained in `schedules`.
+    fn save_schedules(
+        &mut self,
+        schedules: &EpgSchedules,
+        triples: &HashSet<ServiceTriple>,
+    ) -> Result<(), Error>;
+}
+
+// Returns `None` if no `epg.cache-dir` is specified.
+pub fn open(config: &EpgConfig) -> Result<Option<Box<dyn EpgStorage>>, Error> {
+    let cache_dir = match config.cache_dir {
+        Some(ref cache_dir) => PathBuf::from(cache_dir),
+        None => return Ok(None),
+    };
+    let storage: Box<dyn EpgStorage> = match config.storage {
+        EpgStorageType::Json => Box::new(JsonStorage::new(cache_dir)),
+        EpgStorageType::Sqlite => Box::new(SqliteStorage::open(cache_dir)?),
+    };
+    Ok(Some(storage))
+}
+
+// json
+
+// Saves EPG data into JSON files.  Each file is rewritten entirely even when
+// only a part of the data has been changed.
+//
+// Files are written atomically and the previous version of each file is kept
+// as a backup.  The backup is loaded when the file cannot be loaded.
+pub struct JsonStorage {
+    cache_dir: PathBuf,
+}
+
+impl JsonStorage {
+    const SERVICES: &'static str = "services.json";
+    const CLOCKS: &'static str = "clocks.json";
+    const SCHEDULES: &'static str = "schedules.json";
+
+    pub fn new(cache_dir: PathBuf) -> Self {
+        JsonStorage { cache_dir }
+    }
+
+    fn exists(&self) -> bool {
+        self.cache_dir.join(Self::SERVICES).exists()
+    }
+
+    fn load<T>(&self, name: &str) -> Result<Stored<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let json_path = self.cache_dir.join(name);
+        match Self::load_file(&json_path) {
+            Ok(stored) => Ok(stored),
+            Err(err) => {
+                let backup_path = fs_util::backup_path(&json_path);
+                if !backup_path.exists() {
+                    return Err(err);
+                }
+                log::warn!("Failed to load {}: {}, use the backup instead",
+                           json_path.display(), err);
+                Self::load_file(&backup_path)
+            }
+        }
+    }
+
+    fn load_file<T>(path: &Path) -> Result<Stored<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        log::debug!("Loading {}...", path.display());
+        let reader = BufReader::new(File::open(path)?);
+        Ok(Stored {
+            data: serde_json::from_reader(reader)?,
+            updated_at: modified_at(path),
+        })
+    }
+
+    fn save<T>(&self, name: &str, data: &T) -> Result<(), Error>
+    where
+        T: Serialize,
+    {
+        let json_path = self.cache_dir.join(name);
+        log::debug!("Saving into {}...", json_path.display());
+        fs_util::write_atomically(&json_path, |writer| {
+            serde_json::to_writer(writer, data)?;
+            Ok(())
+        })
+    }
+}
+
+impl EpgStorage for JsonStorage {
+    fn load_services(&self) -> Result<Stored<EpgServices>, Error> {
+        self.load(Self::SERVICES)
+    }
+
+    fn load_clocks(&self) -> Result<Stored<EpgClocks>, Error> {
+        self.load(Self::CLOCKS)
+    }
+
+    fn load_schedules(&self) -> Result<Stored<EpgSchedules>, Error> {
+        self.load(Self::SCHEDULES)
+    }
+
+    fn save_services(&mut self, services: &EpgServices) -> Result<(), Error> {
+        self.save(Self::SERVICES, services)
+    }
+
+    fn save_clocks(&mut self, clocks: &EpgClocks) -> Result<(), Error> {
+        self.save(Self::CLOCKS, clocks)
+    }
+
+    fn save_schedules(
+        &mut self,
+        schedules: &EpgSchedules,
+        _triples: &HashSet<ServiceTriple>,
+    ) -> Result<(), Error> {
+        self.save(Self::SCHEDULES, schedules)
+    }
+}
+
+fn modified_at(path: &Path) -> Option<DateTime<Jst>> {
+    fs_util::modified(path)
+        .map(|modified| DateTime::<Utc>::from(modified).with_timezone(&Jst))
+}
+
+// sqlite
+
+// Saves EPG data into a SQLite database.
+//
+// Each service, clock and schedule is stored in a separate row as a JSON
+// text.  So, schedules can be updated incrementally for each service.  Each
+// save operation is performed in a transaction so that the database is never
+// left in a partially updated state.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    const FILENAME: &'static str = "epg.sqlite3";
+
+    const SCHEMA: &'static str = r#"
+        CREATE TABLE IF NOT EXISTS services (
+            triple INTEGER PRIMARY KEY,
+            position INTEGER NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS clocks (
+            triple INTEGER PRIMARY KEY,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS schedules (
+            triple INTEGER PRIMARY KEY,
+            data TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS updates (
+            name TEXT PRIMARY KEY,
+            updated_at INTEGER NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS metadata (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+    "#;
+
+    pub fn open(cache_dir: PathBuf) -> Result<Self, Error> {
+        let db_path = cache_dir.join(Self::FILENAME);
+        log::debug!("Opening {}...", db_path.display());
+        let conn = Connection::open(&db_path)?;
+        Self::init(conn, Some(JsonStorage::new(cache_dir)))
+    }
+
+    fn init(conn: Connection, json: Option<JsonStorage>) -> Result<Self, Error> {
+        conn.execute_batch(Self::SCHEMA)?;
+        let mut storage = SqliteStorage { conn };
+        if let Some(json) = json {
+            storage.migrate_from_json(&json)?;
+        }
+        Ok(storage)
+    }
+
+    // Imports the JSON files once if they exist.  The JSON files are kept as
+    // they are.
+    fn migrate_from_json(&mut self, json: &JsonStorage) -> Result<(), Error> {
+        const KEY: &'static str = "migrated-from-json";
+
+        let migrated: Option<String> = self.conn.query_row(
+            "SELECT value FROM metadata WHERE key = ?1", params![KEY],
+            |row| row.get(0)).optional()?;
+        if migrated.is_some() || !json.exists() {
+            return Ok(());
+        }
+
+        // Data which cannot be loaded is skipped.  It will be collected by
+        // jobs again.
+        log::info!("Migrating EPG data from JSON files...");
+        match json.load_services() {
+            Ok(services) => {
+                self.save_services(&services.data)?;
+                self.set_updated_at("services", services.updated_at)?;
+            }
+            Err(err) => log::warn!("Skip to migrate services: {}", err),
+        }
+        match json.load_clocks() {
+            Ok(clocks) => {
+                self.save_clocks(&clocks.data)?;
+                self.set_updated_at("clocks", clocks.updated_at)?;
+            }
+            Err(err) => log::warn!("Skip to migrate clocks: {}", err),
+        }
+        match json.load_schedules() {
+            Ok(schedules) => {
+                let triples = schedules.data.keys().cloned().collect();
+                self.save_schedules(&schedules.data, &triples)?;
+                self.set_updated_at("schedules", schedules.updated_at)?;
+            }
+            Err(err) => log::warn!("Skip to migrate schedules: {}", err),
+        }
+        self.conn.execute(
+            "INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)",
+            params![KEY, Jst::now().to_rfc3339()])?;
+        log::info!("Migrated EPG data from JSON files");
+        Ok(())
+    }
+
+    fn updated_at(&self, name: &str) -> Result<Option<DateTime<Jst>>, Error> {
+        let ms: Option<i64> = self.conn.query_row(
+            "SELECT updated_at FROM updates WHERE name = ?1", params![name],
+            |row| row.get(0)).optional()?;
+        Ok(ms.map(|ms| Jst.timestamp_millis(ms)))
+    }
+
+    fn set_updated_at(
+        &self,
+        name: &str,
+        updated_at: Option<DateTime<Jst>>,
+    ) -> Result<(), Error> {
+        if let Some(updated_at) = updated_at {
+            self.conn.execute(
+                "INSERT OR REPLACE INTO updates (name, updated_at) \
+                 VALUES (?1, ?2)",
+                params![name, updated_at.timestamp_millis()])?;
+        }
+        Ok(())
+    }
+
+    fn load_rows<T>(&self, sql: &str) -> Result<Vec<(ServiceTriple, T)>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        let mut stmt = self.conn.prepare(sql)?;
+        let rows = stmt.query_map(params![], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut result = Vec::new();
+        for row in rows {
+            let (triple, data) = row?;
+            result.push((ServiceTriple::from(triple as u64),
+                         serde_json::from_str(&data)?));
+        }
+        Ok(result)
+    }
+}
+
+impl EpgStorage for SqliteStorage {
+    fn load_services(&self) -> Result<Stored<EpgServices>, Error> {
+        let rows = self.load_rows(
+            "SELECT triple, data FROM services ORDER BY position")?;
+        Ok(Stored {
+            data: rows.into_iter().collect(),
+            updated_at: self.updated_at("services")?,
+        })
+    }
+
+    fn load_clocks(&self) -> Result<Stored<EpgClocks>, Error> {
+        let rows = self.load_rows("SELECT triple, data FROM clocks")?;
+        Ok(Stored {
+            data: rows.into_iter().collect(),
+            updated_at: self.updated_at("clocks")?,
+        })
+    }
+
+    fn load_schedules(&self) -> Result<Stored<EpgSchedules>, Error> {
+        let rows = self.load_rows("SELECT triple, data FROM schedules")?;
+        Ok(Stored {
+            data: rows.into_iter().collect(),
+            updated_at: self.updated_at("schedules")?,
+        })
+    }
+
+    fn save_services(&mut self, services: &EpgServices) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM services", params![])?;
+        for (position, (triple, service)) in services.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO services (triple, position, data) \
+                 VALUES (?1, ?2, ?3)",
+                params![triple.value() as i64, position as i64,
+                        serde_json::to_string(service)?])?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO updates (name, updated_at) \
+             VALUES ('services', ?1)",
+            params![Jst::now().timestamp_millis()])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_clocks(&mut self, clocks: &EpgClocks) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        tx.execute("DELETE FROM clocks", params![])?;
+        for (triple, clock) in clocks.iter() {
+            tx.execute(
+                "INSERT INTO clocks (triple, data) VALUES (?1, ?2)",
+                params![triple.value() as i64, serde_json::to_string(clock)?])?;
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO updates (name, updated_at) \
+             VALUES ('clocks', ?1)",
+            params![Jst::now().timestamp_millis()])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn save_schedules(
+        &mut self,
+        schedules: &EpgSchedules,
+        triples: &HashSet<ServiceTriple>,
+    ) -> Result<(), Error> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut stmt = tx.prepare("SELECT triple FROM schedules")?;
+            let stored = stmt
+                .query_map(params![], |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<i64>, _>>()?;
+            for triple in stored.into_iter() {
+                if !schedules.contains_key(&ServiceTriple::from(triple as u64)) {
+                    tx.execute("DELETE FROM schedules WHERE triple = ?1",
+                               params![triple])?;
+                }
+            }
+        }
+        for triple in triples.iter() {
+            if let Some(schedule) = schedules.get(triple) {
+                tx.execute(
+                    "INSERT OR REPLACE INTO schedules (triple, data) \
+                     VALUES (?1, ?2)",
+                    params![triple.value() as i64,
+                            serde_json::to_string(schedule)?])?;
+            }
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO updates (name, updated_at) \
+             VALUES ('schedules', ?1)",
+            params![Jst::now().timestamp_millis()])?;
+        tx.commit()?;
+        log::debug!("Saved schedules for {} services", triples.len());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs_util::test_util::TempDir;
+
+    #[test]
+    fn test_json_storage() {
+        let dir = TempDir::new("epg-storage");
+        let mut storage = JsonStorage::new(dir.path().to_path_buf());
+
+        assert!(storage.load_clocks().is_err());
+
+        let mut clocks = EpgClocks::new();
+        clocks.insert((1, 2, 3).into(), Clock { pid: 1, pcr: 2, time: 3 });
+        storage.save_clocks(&clocks).unwrap();
+        clocks.insert((1, 2, 4).into(), Clock { pid: 1, pcr: 2, time: 3 });
+        storage.save_clocks(&clocks).unwrap();
+        let loaded = storage.load_clocks().unwrap();
+        assert_eq!(loaded.data.len(), 2);
+        assert!(loaded.updated_at.is_some());
+
+        // Fall back to the backup when the file is broken.
+        std::fs::write(dir.path().join(JsonStorage::CLOCKS), "{").unwrap();
+        let loaded = storage.load_clocks().unwrap();
+        assert_eq!(loaded.data.len(), 1);
+    }
+
+    #[test]
+    fn test_sqlite_storage() {
+        let conn = Connection::open_in_memory().unwrap();
+        let mut storage = SqliteStorage::init(conn, None).unwrap();
+
+        let services = storage.load_services().unwrap();
+        assert!(services.data.is_empty());
+        assert!(services.updated_at.is_none());
+
+        let mut services = EpgServices::new();
+        services.insert((1, 2, 4).into(), create_epg_service(4));
+        services.insert((1, 2, 3).into(), create_epg_service(3));
+        storage.save_services(&services).unwrap();
+        let loaded = storage.load_services().unwrap();
+        assert!(loaded.updated_at.is_some());
+        let triples: Vec<ServiceTriple> = loaded.data.keys().cloned().collect();
+        assert_eq!(triples, vec![(1, 2, 4).into(), (1, 2, 3).into()]);
+
+        let mut clocks = EpgClocks::new();
+        clocks.insert((1, 2, 3).into(), Clock { pid: 1, pcr: 2, time: 3 });
+        storage.save_clocks(&clocks).unwrap();
+        let loaded = storage.load_clocks().unwrap();
+        assert_eq!(loaded.data.len(), 1);
+        assert_eq!(loaded.data[&(1, 2, 3).into()].pcr, 2);
+
+        let mut schedules = EpgSchedules::new();
+        schedules.insert((1, 2, 3).into(), EpgSchedule::new((1, 2, 3).into()));
+        schedules.insert((1, 2, 4).into(), EpgSchedule::new((1, 2, 4).into()));
+        let triples = schedules.keys().cloned().collect();
+        storage.save_schedules(&schedules, &triples).unwrap();
+        let loaded = storage.load_schedules().unwrap();
+        assert_eq!(loaded.data.len(), 2);
+
+        // Only removed schedules are deleted when no service is updated.
+        schedules.remove(&(1, 2, 4).into());
+        storage.save_schedules(&schedules, &HashSet::new()).unwrap();
+        let loaded = storage.load_schedules().unwrap();
+        assert_eq!(loaded.data.len(), 1);
+        assert!(loaded.data.contains_key(&(1, 2, 3).into()));
+    }
+
+    #[test]
+    fn test_migrate_from_json() {
+        let dir = TempDir::new("epg-storage-migration");
+        let mut json = JsonStorage::new(dir.path().to_path_buf());
+        let mut clocks = EpgClocks::new();
+        clocks.insert((1, 2, 3).into(), Clock { pid: 1, pcr: 2, time: 3 });
+        json.save_clocks(&clocks).unwrap();
+        // Broken services are skipped.
+        std::fs::write(dir.path().join(JsonStorage::SERVICES), "{").unwrap();
+
+        let conn = Connection::open_in_memory().unwrap();
+        let mut storage = SqliteStorage::init(conn, Some(json)).unwrap();
+        assert!(storage.load_services().unwrap().data.is_empty());
+        assert_eq!(storage.load_clocks().unwrap().data.len(), 1);
+
+        // Migrated only once.
+        let mut services = EpgServices::new();
+        services.insert((1, 2, 3).into(), create_epg_service(3));
+        let mut json = JsonStorage::new(dir.path().to_path_buf());
+        json.save_services(&services).unwrap();
+        storage.migrate_from_json(&json).unwrap();
+        assert!(storage.load_services().unwrap().data.is_empty());
+    }
+
+    fn create_epg_service(sid: u16) -> EpgService {
+        EpgService {
+            nid: 1.into(),
+            tsid: 2.into(),
+            sid: sid.into(),
+            service_type: 1,
+            logo_id: 0,
+            remote_control_key_id: 0,
+            name: "Service".to_string(),
+            channel: crate::epg::EpgChannel {
+                name: "channel".to_string(),
+                channel_type: ChannelType::GR,
+                channel: "0".to_string(),
+                extra_args: "".to_string(),
+                services: vec![],
+                excluded_services: vec![],
+            },
+            has_logo_data: false,
+        }
+    }
+}
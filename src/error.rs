@@ -24,10 +24,30 @@ pub enum Error {
     ClockNotSynced,
     #[fail(display = "Program not found")]
     ProgramNotFound,
+    #[fail(display = "Logo not found")]
+    LogoNotFound,
     #[fail(display = "Session not found")]
     SessionNotFound,
+    #[fail(display = "Tuner not found")]
+    TunerNotFound,
+    #[fail(display = "Stream not found")]
+    StreamNotFound,
     #[fail(display = "Access denied")]
     AccessDenied,
+    #[fail(display = "Authentication required")]
+    AuthenticationRequired,
+    #[fail(display = "Streaming quota exceeded")]
+    StreamingQuotaExceeded,
+    #[fail(display = "Invalid config: {}", 0)]
+    InvalidConfig(String),
+    #[fail(display = "Invalid query: {}", 0)]
+    InvalidQuery(String),
+    #[fail(display = "Job not found")]
+    JobNotFound,
+    #[fail(display = "Job already running")]
+    JobAlreadyRunning,
+    #[fail(display = "Channel scan already running")]
+    ChannelScanAlreadyRunning,
     #[fail(display = "Command failed: {}", 0)]
     CommandFailed(command_util::Error),
     #[fail(display = "std::fmt::error: {}", 0)]
@@ -46,6 +66,10 @@ pub enum Error {
     MustacheError(mustache::Error),
     #[fail(display = "std::env error: {}", 0)]
     EnvVarError(env::VarError),
+    #[fail(display = "SQLite error: {}", 0)]
+    SqliteError(rusqlite::Error),
+    #[fail(display = "TLS error: {}", 0)]
+    TlsError(String),
     #[fail(display = "tokio::sync::broadcast error: {:?}", 0)]
     TokioSyncBroadcastError(tokio::sync::broadcast::RecvError),
 }
@@ -110,6 +134,12 @@ impl From<env::VarError> for Error {
     }
 }
 
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::SqliteError(err)
+    }
+}
+
 impl From<tokio::sync::broadcast::RecvError> for Error {
     fn from(err: tokio::sync::broadcast::RecvError) -> Self {
         Self::TokioSyncBroadcastError(err)
@@ -1,8 +1,9 @@
-use std::fs;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
-fn modified<P>(path: P) -> Option<SystemTime>
+pub fn modified<P>(path: P) -> Option<SystemTime>
 where
     P: AsRef<Path>
 {
@@ -12,32 +13,140 @@ where
         .flatten()
 }
 
-pub fn unmodified_since<P>(path: P, datetime: Option<SystemTime>) -> bool
+// Returns the path of the backup file of `path`.
+pub fn backup_path<P>(path: P) -> PathBuf
 where
     P: AsRef<Path>
 {
-    match datetime {
-        Some(datetime) => {
-            match modified(path) {
-                Some(modified) => modified < datetime,
-                None => false,
-            }
+    append_extension(path, "bak")
+}
+
+// Writes a file atomically.
+//
+// Data is written into a temporary file, which is renamed to `path` after the
+// data is flushed to the disk.  The existing file is kept as the backup file.
+// So, one of them is always available even if the system crashes while
+// writing.  The temporary file is removed when writing fails.
+pub fn write_atomically<P, F, E>(path: P, write: F) -> Result<(), E>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), E>,
+    E: From<io::Error>,
+{
+    let path = path.as_ref();
+    let tmp_path = append_extension(path, "tmp");
+
+    if let Err(err) = write_and_sync(&tmp_path, write) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    if path.exists() {
+        fs::rename(path, backup_path(path))?;
+    }
+    fs::rename(&tmp_path, path)?;
+
+    // Make the renames durable.
+    if let Some(dir) = path.parent() {
+        if let Ok(dir) = File::open(dir) {
+            dir.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+fn write_and_sync<F, E>(path: &Path, write: F) -> Result<(), E>
+where
+    F: FnOnce(&mut BufWriter<File>) -> Result<(), E>,
+    E: From<io::Error>,
+{
+    let mut writer = BufWriter::new(File::create(path)?);
+    write(&mut writer)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    Ok(())
+}
+
+fn append_extension<P>(path: P, ext: &str) -> PathBuf
+where
+    P: AsRef<Path>
+{
+    let mut path = path.as_ref().as_os_str().to_owned();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+// Helpers for tests using files.
+#[cfg(test)]
+pub mod test_util {
+    use super::*;
+
+    // A temporary directory removed when dropped.
+    //
+    // `name` has to be unique in tests because tests run in parallel in the
+    // same process.
+    pub struct TempDir(PathBuf);
+
+    impl TempDir {
+        pub fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "mirakc-{}-{}", name, std::process::id()));
+            // Remove files left by a previous run.
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
         }
-        None => false,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
+    use super::test_util::TempDir;
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = TempDir::new("fs-util");
+        let path = dir.path().join("data.json");
+
+        let result: Result<(), io::Error> = write_atomically(&path, |writer| {
+            writer.write_all(b"1")
+        });
+        assert!(result.is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1");
+        assert!(!backup_path(&path).exists());
+
+        let result: Result<(), io::Error> = write_atomically(&path, |writer| {
+            writer.write_all(b"2")
+        });
+        assert!(result.is_ok());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2");
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "1");
+
+        // The file is never changed when writing fails.
+        let result: Result<(), io::Error> = write_atomically(&path, |_| {
+            Err(io::Error::new(io::ErrorKind::Other, "error"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2");
+        assert_eq!(fs::read_to_string(backup_path(&path)).unwrap(), "1");
+        assert!(!append_extension(&path, "tmp").exists());
+    }
 
     #[test]
-    fn test_unmodified_since() {
-        let path = PathBuf::from(file!());
-        let modified = modified(&path);
-        assert!(!unmodified_since(&path, None));
-        assert!(!unmodified_since(&path, modified));
-        assert!(unmodified_since(&path, Some(SystemTime::now())));
+    fn test_backup_path() {
+        assert_eq!(backup_path("/path/to/data.json"),
+                   PathBuf::from("/path/to/data.json.bak"));
     }
 }
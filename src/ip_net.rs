@@ -0,0 +1,186 @@
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
+
+// An IP network in the CIDR notation like "192.168.0.0/16" or "fc00::/7".
+//
+// An address without the prefix length is treated as a network containing
+// only the address.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpNet {
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        let max_prefix_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix_len > max_prefix_len {
+            return None;
+        }
+        Some(IpNet { addr, prefix_len })
+    }
+
+    // IPv4-mapped IPv6 addresses are compared as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, canonicalize(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = mask_u32(self.prefix_len);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = mask_u128(self.prefix_len);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("invalid IP network: {}", s);
+        let mut parts = s.splitn(2, '/');
+        let addr: IpAddr = parts.next()
+            .ok_or_else(err)?
+            .parse()
+            .map_err(|_| err())?;
+        let prefix_len = match (parts.next(), addr) {
+            (Some(len), _) => len.parse().map_err(|_| err())?,
+            (None, IpAddr::V4(_)) => 32,
+            (None, IpAddr::V6(_)) => 128,
+        };
+        IpNet::new(addr, prefix_len).ok_or_else(err)
+    }
+}
+
+impl<'de> Deserialize<'de> for IpNet {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Converts an IPv4-mapped IPv6 address into an IPv4 address.
+//
+// Unlike `Ipv6Addr::to_ipv4()`, IPv4-compatible addresses like `::1` are not
+// converted.
+pub fn canonicalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => match v6.segments() {
+            [0, 0, 0, 0, 0, 0xFFFF, hi, lo] => IpAddr::V4(Ipv4Addr::new(
+                (hi >> 8) as u8, hi as u8, (lo >> 8) as u8, lo as u8)),
+            _ => ip,
+        },
+        _ => ip,
+    }
+}
+
+// Returns true if the address is a loopback, private or link-local address.
+//
+// TODO: IpAddr::is_global() is a nightly-only API at this point.
+pub fn is_private(ip: IpAddr) -> bool {
+    match canonicalize(ip) {
+        IpAddr::V4(ip) => is_private_ipv4(ip),
+        IpAddr::V6(ip) => is_private_ipv6(ip),
+    }
+}
+
+fn is_private_ipv4(ip: Ipv4Addr) -> bool {
+    ip.is_loopback() || ip.is_private() || ip.is_link_local()
+}
+
+fn is_private_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback() ||
+        first & 0xFE00 == 0xFC00 ||  // unique local (fc00::/7)
+        first & 0xFFC0 == 0xFE80  // link-local (fe80::/10)
+}
+
+fn mask_u32(prefix_len: u8) -> u32 {
+    match prefix_len {
+        0 => 0,
+        n => !0u32 << (32 - n as u32),
+    }
+}
+
+fn mask_u128(prefix_len: u8) -> u128 {
+    match prefix_len {
+        0 => 0,
+        n => !0u128 << (128 - n as u32),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn net(s: &str) -> IpNet {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(net("192.168.0.0/16"),
+                   IpNet::new(ip("192.168.0.0"), 16).unwrap());
+        assert_eq!(net("192.168.0.1"),
+                   IpNet::new(ip("192.168.0.1"), 32).unwrap());
+        assert_eq!(net("fc00::/7"), IpNet::new(ip("fc00::"), 7).unwrap());
+        assert_eq!(net("::1"), IpNet::new(ip("::1"), 128).unwrap());
+        assert!("192.168.0.0/33".parse::<IpNet>().is_err());
+        assert!("fc00::/129".parse::<IpNet>().is_err());
+        assert!("192.168.0.0/".parse::<IpNet>().is_err());
+        assert!("localhost".parse::<IpNet>().is_err());
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(net("192.168.0.0/16").contains(ip("192.168.1.2")));
+        assert!(!net("192.168.0.0/16").contains(ip("192.169.0.1")));
+        assert!(net("192.168.0.0/16").contains(ip("::ffff:192.168.1.2")));
+        assert!(net("0.0.0.0/0").contains(ip("8.8.8.8")));
+        assert!(!net("0.0.0.0/0").contains(ip("2001:db8::1")));
+        assert!(net("2001:db8::/32").contains(ip("2001:db8:1::1")));
+        assert!(!net("2001:db8::/32").contains(ip("2001:db9::1")));
+        assert!(net("::/0").contains(ip("2001:db8::1")));
+        assert!(net("10.0.0.1").contains(ip("10.0.0.1")));
+        assert!(!net("10.0.0.1").contains(ip("10.0.0.2")));
+    }
+
+    #[test]
+    fn test_is_private() {
+        assert!(is_private(ip("127.0.0.1")));
+        assert!(is_private(ip("10.0.0.1")));
+        assert!(is_private(ip("172.16.0.1")));
+        assert!(is_private(ip("192.168.0.1")));
+        assert!(is_private(ip("169.254.0.1")));
+        assert!(!is_private(ip("8.8.8.8")));
+        assert!(is_private(ip("::1")));
+        assert!(is_private(ip("::ffff:192.168.0.1")));
+        assert!(!is_private(ip("::ffff:8.8.8.8")));
+        assert!(is_private(ip("fd00::1")));
+        assert!(is_private(ip("fe80::1")));
+        assert!(!is_private(ip("2001:db8::1")));
+        assert!(!is_private(ip("::8.8.8.8")));
+    }
+}
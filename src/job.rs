@@ -1,20 +1,27 @@
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use actix::prelude::*;
 use chrono::DateTime;
 use cron;
+use futures::future::{AbortHandle, Abortable};
 use humantime;
 use log;
+use serde::Serialize;
 use tokio::sync::Semaphore;
 
+use crate::channel_job::ChannelJobOptions;
 use crate::clock_synchronizer::ClockSynchronizer;
 use crate::config::Config;
+use crate::custom_job::CustomJob;
 use crate::datetime_ext::*;
 use crate::eit_feeder::*;
 use crate::epg::*;
+use crate::error::Error;
+use crate::logo_collector::LogoCollector;
 use crate::service_scanner::ServiceScanner;
 use crate::tuner::*;
 
@@ -24,7 +31,7 @@ pub fn start(
     epg: Addr<Epg>,
     eit_feeder: Addr<EitFeeder>,
 ) -> Addr<JobManager> {
-    JobManager::new(config, tuner_manager, epg, eit_feeder).start()
+    JobManager::new(config, tuner_manager.recipient(), epg, eit_feeder).start()
 }
 
 struct Job {
@@ -37,7 +44,7 @@ impl Job {
         Job { kind, semaphore }
     }
 
-    async fn perform<T, F>(self, fut: F) -> T
+    async fn perform<T, F>(self, fut: F) -> (T, Duration)
     where
         F: Future<Output = T>,
     {
@@ -49,14 +56,17 @@ impl Job {
         let elapsed = now.elapsed();
         log::info!("{}: Done, {} elapsed",
                    self.kind, humantime::format_duration(elapsed));
-        results
+        (results, elapsed)
     }
 }
 
+#[derive(Clone)]
 enum JobKind {
     ScanServices,
     SyncClocks,
     UpdateSchedules,
+    CollectLogos,
+    Custom(String),
 }
 
 impl JobKind {
@@ -72,17 +82,41 @@ impl fmt::Display for JobKind {
             ScanServices => write!(f, "scan-services"),
             SyncClocks => write!(f, "sync-clocks"),
             UpdateSchedules => write!(f, "update-schedules"),
+            CollectLogos => write!(f, "collect-logos"),
+            Custom(ref name) => write!(f, "{}", name),
         }
     }
 }
 
+#[derive(Default)]
+struct JobState {
+    // Available only while the job is running.
+    abort_handle: Option<AbortHandle>,
+    last_duration: Option<Duration>,
+    last_error: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub name: String,
+    pub schedule: String,
+    #[serde(with = "serde_jst")]
+    pub next_scheduled_at: DateTime<Jst>,
+    pub is_running: bool,
+    // In milliseconds.
+    pub last_duration: Option<u64>,
+    pub last_error: Option<String>,
+}
+
 pub struct JobManager {
     config: Arc<Config>,
     semaphore: Arc<Semaphore>,  // job concurrency
-    scanning_services: bool,
-    synchronizing_clocks: bool,
-    updating_schedules: bool,
-    tuner_manager: Addr<TunerManager>,
+    states: HashMap<String, JobState>,
+    // Jobs performed one by one when mirakc starts with missing or stale EPG
+    // data.  The front is the running one.
+    cold_start_jobs: VecDeque<JobKind>,
+    stream_manager: Recipient<StartStreamingMessage>,
     epg: Addr<Epg>,
     eit_feeder: Addr<EitFeeder>,
 }
@@ -90,17 +124,16 @@ pub struct JobManager {
 impl JobManager {
     pub fn new(
         config: Arc<Config>,
-        tuner_manager: Addr<TunerManager>,
+        stream_manager: Recipient<StartStreamingMessage>,
         epg: Addr<Epg>,
         eit_feeder: Addr<EitFeeder>,
     ) -> Self {
         JobManager {
             config,
             semaphore: Arc::new(Semaphore::new(1)),
-            scanning_services: false,
-            synchronizing_clocks: false,
-            updating_schedules: false,
-            tuner_manager,
+            states: HashMap::new(),
+            cold_start_jobs: VecDeque::new(),
+            stream_manager,
             epg,
             eit_feeder,
         }
@@ -109,40 +142,232 @@ impl JobManager {
     fn calc_next_scheduled_datetime(&self, schedule: &str) -> DateTime<Jst> {
         cron::Schedule::from_str(schedule)
             .unwrap()
-            .upcoming(Jst)
+            .upcoming(self.config.time_zone.offset)
             .take(1)
             .nth(0)
             .unwrap()
+            .with_timezone(&Jst)
+    }
+
+    fn is_running(&self, kind: &JobKind) -> bool {
+        self.states
+            .get(&kind.to_string())
+            .map_or(false, |state| state.abort_handle.is_some())
+    }
+
+    // Spawns a job which can be canceled with `cancel_job()`.
+    //
+    // `handler` is called with the output of `fut` when the job finishes, and
+    // returns an error message if the job failed.
+    fn spawn_job<T, F, H>(
+        &mut self,
+        kind: JobKind,
+        fut: F,
+        handler: H,
+        ctx: &mut Context<Self>,
+    ) where
+        T: 'static,
+        F: Future<Output = T> + 'static,
+        H: FnOnce(T, &mut Self) -> Option<String> + 'static,
+    {
+        let name = kind.to_string();
+
+        let (abort_handle, abort_registration) = AbortHandle::new_pair();
+        self.states.entry(name.clone()).or_default().abort_handle =
+            Some(abort_handle);
+
+        let job = Abortable::new(
+            kind.create(self.semaphore.clone()).perform(fut),
+            abort_registration);
+
+        actix::fut::wrap_future::<_, Self>(job)
+            .then(move |result, act, ctx| {
+                let (duration, error) = match result {
+                    Ok((output, elapsed)) =>
+                        (Some(elapsed), handler(output, act)),
+                    Err(_) => {
+                        log::warn!("{}: Canceled", name);
+                        (None, Some("Canceled".to_string()))
+                    }
+                };
+                let state = act.states.entry(name.clone()).or_default();
+                state.abort_handle = None;
+                if duration.is_some() {
+                    state.last_duration = duration;
+                }
+                state.last_error = error;
+                act.on_job_finished(&name, ctx);
+                actix::fut::ready(())
+            })
+            .spawn(ctx);
+    }
+
+    fn cold_start(&mut self, status: EpgStatus, ctx: &mut Context<Self>) {
+        let now = Jst::now();
+        let max_age = chrono::Duration::milliseconds(
+            self.config.epg.max_cache_age as i64);
+        let is_stale = |updated_at: Option<DateTime<Jst>>| {
+            updated_at.map_or(true, |updated_at| now - updated_at > max_age)
+        };
+
+        // Later jobs depend on results of earlier jobs.
+        let mut stale = false;
+        stale |= status.num_services == 0 ||
+            is_stale(status.services_updated_at);
+        if stale {
+            self.cold_start_jobs.push_back(JobKind::ScanServices);
+        }
+        stale |= status.num_clocks == 0 || is_stale(status.clocks_updated_at);
+        if stale {
+            self.cold_start_jobs.push_back(JobKind::SyncClocks);
+        }
+        stale |= is_stale(status.schedules_updated_at);
+        if stale {
+            self.cold_start_jobs.push_back(JobKind::UpdateSchedules);
+        }
+
+        if self.cold_start_jobs.is_empty() {
+            log::info!("EPG data is up to date, skip cold start");
+            return;
+        }
+
+        log::info!("EPG data is missing or stale, start cold start");
+        self.invoke_cold_start_job(ctx);
+    }
+
+    fn invoke_cold_start_job(&mut self, ctx: &mut Context<Self>) {
+        while let Some(kind) = self.cold_start_jobs.front().cloned() {
+            let result = match kind {
+                JobKind::ScanServices => self.invoke_scan_services(ctx),
+                JobKind::SyncClocks => self.invoke_sync_clocks(ctx),
+                JobKind::UpdateSchedules => self.invoke_update_schedules(ctx),
+                _ => unreachable!(),
+            };
+            match result {
+                // The next job will be invoked when this job finishes.
+                Ok(_) => return,
+                Err(Error::JobAlreadyRunning) => return,
+                Err(_) => {
+                    self.cold_start_jobs.pop_front();
+                }
+            }
+        }
+    }
+
+    fn on_job_finished(&mut self, name: &str, ctx: &mut Context<Self>) {
+        let is_cold_start_job = self.cold_start_jobs
+            .front()
+            .map_or(false, |kind| kind.to_string() == name);
+        if is_cold_start_job {
+            self.cold_start_jobs.pop_front();
+            if self.cold_start_jobs.is_empty() {
+                log::info!("Cold start completed");
+            } else {
+                self.invoke_cold_start_job(ctx);
+            }
+        }
+    }
+
+    fn cancel_job(&mut self, name: &str) -> Result<(), Error> {
+        if !self.job_exists(name) {
+            return Err(Error::JobNotFound);
+        }
+
+        let abort_handle = self.states
+            .get_mut(name)
+            .and_then(|state| state.abort_handle.take());
+
+        match abort_handle {
+            Some(abort_handle) => {
+                log::info!("{}: Canceling...", name);
+                abort_handle.abort();
+                if name == JobKind::UpdateSchedules.to_string() {
+                    // The EIT sections are collected in the EitFeeder.
+                    self.eit_feeder.do_send(CancelFeedEitSectionsMessage);
+                }
+            }
+            None => log::debug!("{}: Not running", name),
+        }
+
+        Ok(())
+    }
+
+    fn job_exists(&self, name: &str) -> bool {
+        self.collect_schedules().iter().any(|(job_name, _)| job_name == name)
+    }
+
+    fn collect_schedules(&self) -> Vec<(String, String)> {
+        let jobs = &self.config.jobs;
+        let mut schedules = vec![
+            (JobKind::ScanServices.to_string(),
+             jobs.scan_services.schedule.clone()),
+            (JobKind::SyncClocks.to_string(),
+             jobs.sync_clocks.schedule.clone()),
+            (JobKind::UpdateSchedules.to_string(),
+             jobs.update_schedules.schedule.clone()),
+            (JobKind::CollectLogos.to_string(),
+             jobs.collect_logos.schedule.clone()),
+        ];
+        for (name, config) in jobs.custom.iter() {
+            schedules.push((name.clone(), config.schedule.clone()));
+        }
+        schedules
+    }
+
+    fn query_jobs(&self) -> Vec<JobInfo> {
+        self.collect_schedules()
+            .into_iter()
+            .map(|(name, schedule)| {
+                let next_scheduled_at =
+                    self.calc_next_scheduled_datetime(&schedule);
+                let state = self.states.get(&name);
+                JobInfo {
+                    is_running: state
+                        .map_or(false, |state| state.abort_handle.is_some()),
+                    last_duration: state
+                        .and_then(|state| state.last_duration)
+                        .map(|duration| duration.as_millis() as u64),
+                    last_error: state
+                        .and_then(|state| state.last_error.clone()),
+                    name,
+                    schedule,
+                    next_scheduled_at,
+                }
+            })
+            .collect()
     }
 
     fn scan_services(&mut self, ctx: &mut Context<Self>) {
-        self.invoke_scan_services(ctx);
+        let _ = self.invoke_scan_services(ctx);
         self.schedule_scan_services(ctx);
     }
 
-    fn invoke_scan_services(&mut self, ctx: &mut Context<Self>) {
-        if self.scanning_services {
+    fn invoke_scan_services(
+        &mut self,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        if self.is_running(&JobKind::ScanServices) {
             log::warn!("scan-services: Already running, skip");
-            return;
+            return Err(Error::JobAlreadyRunning);
         }
 
-        self.scanning_services = true;
-
         let scanner = ServiceScanner::new(
             self.config.jobs.scan_services.command.clone(),
             self.collect_enabled_channels(),
-            self.tuner_manager.clone().recipient());
+            self.stream_manager.clone(),
+            ChannelJobOptions::from(&self.config.jobs.scan_services));
 
-        let job = JobKind::ScanServices.create(self.semaphore.clone())
-            .perform(scanner.scan_services());
-
-        actix::fut::wrap_future::<_, Self>(job)
-            .then(|results, act, _| {
+        self.spawn_job(
+            JobKind::ScanServices, scanner.scan_services(),
+            |results, act| {
+                let error = make_error(results.iter()
+                    .filter(|(_, result)| result.is_none())
+                    .map(|(channel, _)| channel.name.as_str()));
                 act.epg.do_send(UpdateServicesMessage { results });
-                act.scanning_services = false;
-                actix::fut::ready(())
-            })
-            .spawn(ctx);
+                error
+            }, ctx);
+
+        Ok(())
     }
 
     fn schedule_scan_services(&self, ctx: &mut Context<Self>) {
@@ -154,33 +379,36 @@ impl JobManager {
     }
 
     fn sync_clocks(&mut self, ctx: &mut Context<Self>) {
-        self.invoke_sync_clocks(ctx);
+        let _ = self.invoke_sync_clocks(ctx);
         self.schedule_sync_clocks(ctx);
     }
 
-    fn invoke_sync_clocks(&mut self, ctx: &mut Context<Self>) {
-        if self.synchronizing_clocks {
+    fn invoke_sync_clocks(
+        &mut self,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        if self.is_running(&JobKind::SyncClocks) {
             log::warn!("sync-clocks: Already running, skip");
-            return;
+            return Err(Error::JobAlreadyRunning);
         }
 
-        self.synchronizing_clocks = true;
-
         let sync = ClockSynchronizer::new(
             self.config.jobs.sync_clocks.command.clone(),
             self.collect_enabled_channels(),
-            self.tuner_manager.clone().recipient());
-
-        let job = JobKind::SyncClocks.create(self.semaphore.clone())
-            .perform(sync.sync_clocks());
+            self.stream_manager.clone(),
+            ChannelJobOptions::from(&self.config.jobs.sync_clocks));
 
-        actix::fut::wrap_future::<_, Self>(job)
-            .then(|results, act, _| {
+        self.spawn_job(
+            JobKind::SyncClocks, sync.sync_clocks(),
+            |results, act| {
+                let error = make_error(results.iter()
+                    .filter(|(_, result)| result.is_none())
+                    .map(|(channel, _)| channel.name.as_str()));
                 act.epg.do_send(UpdateClocksMessage { results });
-                act.synchronizing_clocks = false;
-                actix::fut::ready(())
-            })
-            .spawn(ctx);
+                error
+            }, ctx);
+
+        Ok(())
     }
 
     fn schedule_sync_clocks(&self, ctx: &mut Context<Self>) {
@@ -192,32 +420,30 @@ impl JobManager {
     }
 
     fn update_schedules(&mut self, ctx: &mut Context<Self>) {
-        self.invoke_update_schedules(ctx);
+        let _ = self.invoke_update_schedules(ctx);
         self.schedule_update_schedules(ctx);
     }
 
-    fn invoke_update_schedules(&mut self, ctx: &mut Context<Self>) {
-        if self.updating_schedules {
+    fn invoke_update_schedules(
+        &mut self,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        if self.is_running(&JobKind::UpdateSchedules) {
             log::warn!("update-schedules: Already running, skip");
-            return;
+            return Err(Error::JobAlreadyRunning);
         }
 
-        self.updating_schedules = true;
-
         let eit_feeder = self.eit_feeder.clone();
 
-        let job = JobKind::UpdateSchedules.create(self.semaphore.clone())
-            .perform(async move {
-                eit_feeder.send(FeedEitSectionsMessage).await?
-            });
-
-        actix::fut::wrap_future::<_, Self>(job)
-            .then(|_, act, _| {
+        self.spawn_job(
+            JobKind::UpdateSchedules,
+            async move { eit_feeder.send(FeedEitSectionsMessage).await? },
+            |result: Result<(), Error>, act| {
                 act.epg.do_send(SaveSchedulesMessage);
-                act.updating_schedules = false;
-                actix::fut::ready(())
-            })
-            .spawn(ctx);
+                result.err().map(|err| err.to_string())
+            }, ctx);
+
+        Ok(())
     }
 
     fn schedule_update_schedules(&mut self, ctx: &mut Context<Self>) {
@@ -228,6 +454,112 @@ impl JobManager {
         ctx.run_later(interval, Self::update_schedules);
     }
 
+    fn collect_logos(&mut self, ctx: &mut Context<Self>) {
+        let _ = self.invoke_collect_logos(ctx);
+        self.schedule_collect_logos(ctx);
+    }
+
+    fn invoke_collect_logos(
+        &mut self,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        if self.is_running(&JobKind::CollectLogos) {
+            log::warn!("collect-logos: Already running, skip");
+            return Err(Error::JobAlreadyRunning);
+        }
+
+        let collector = LogoCollector::new(
+            self.config.jobs.collect_logos.command.clone(),
+            self.collect_enabled_channels(),
+            self.stream_manager.clone(),
+            ChannelJobOptions::from(&self.config.jobs.collect_logos));
+
+        self.spawn_job(
+            JobKind::CollectLogos, collector.collect_logos(),
+            |results, act| {
+                let error = make_error(results.iter()
+                    .filter(|(_, result)| result.is_none())
+                    .map(|(channel, _)| channel.name.as_str()));
+                act.epg.do_send(UpdateLogosMessage { results });
+                error
+            }, ctx);
+
+        Ok(())
+    }
+
+    fn schedule_collect_logos(&self, ctx: &mut Context<Self>) {
+        let datetime = self.calc_next_scheduled_datetime(
+            &self.config.jobs.collect_logos.schedule);
+        log::info!("collect-logos: Scheduled for {}", datetime);
+        let interval = (datetime - Jst::now()).to_std().unwrap();
+        ctx.run_later(interval, Self::collect_logos);
+    }
+
+    fn run_custom_job(&mut self, name: String, ctx: &mut Context<Self>) {
+        let _ = self.invoke_custom_job(name.clone(), ctx);
+        self.schedule_custom_job(name, ctx);
+    }
+
+    fn invoke_custom_job(
+        &mut self,
+        name: String,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), Error> {
+        let config = match self.config.jobs.custom.get(&name) {
+            Some(config) => config,
+            None => {
+                log::error!("{}: No such job", name);
+                return Err(Error::JobNotFound);
+            }
+        };
+
+        let kind = JobKind::Custom(name.clone());
+        if self.is_running(&kind) {
+            log::warn!("{}: Already running, skip", name);
+            return Err(Error::JobAlreadyRunning);
+        }
+
+        let channels = self.collect_enabled_channels()
+            .into_iter()
+            .filter(|channel| {
+                config.channels.is_empty() ||
+                    config.channels.contains(&channel.name)
+            })
+            .collect();
+
+        let job = CustomJob::new(
+            &name, config, channels, self.stream_manager.clone());
+
+        self.spawn_job(kind, job.run(), |results, _| {
+            let errors: Vec<String> = results.iter()
+                .filter_map(|result| match result.result {
+                    Ok(_) => None,
+                    Err(ref err) => Some(format!(
+                        "{}: {} ({} attempts)",
+                        result.channel.name, err, result.attempts)),
+                })
+                .collect();
+            if errors.is_empty() {
+                None
+            } else {
+                Some(errors.join(", "))
+            }
+        }, ctx);
+
+        Ok(())
+    }
+
+    fn schedule_custom_job(&self, name: String, ctx: &mut Context<Self>) {
+        let config = match self.config.jobs.custom.get(&name) {
+            Some(config) => config,
+            None => return,
+        };
+        let datetime = self.calc_next_scheduled_datetime(&config.schedule);
+        log::info!("{}: Scheduled for {}", name, datetime);
+        let interval = (datetime - Jst::now()).to_std().unwrap();
+        ctx.run_later(interval, move |act, ctx| act.run_custom_job(name, ctx));
+    }
+
     fn collect_enabled_channels(&self) -> Vec<EpgChannel> {
         self.config
             .channels
@@ -239,14 +571,53 @@ impl JobManager {
     }
 }
 
+fn make_error<'a, I>(failed_channels: I) -> Option<String>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let names: Vec<&str> = failed_channels.collect();
+    if names.is_empty() {
+        None
+    } else {
+        Some(format!("Failed in {}", names.join(", ")))
+    }
+}
+
 impl Actor for JobManager {
     type Context = Context<Self>;
 
     fn started(&mut self, ctx: &mut Self::Context) {
         log::debug!("Started");
-        self.scan_services(ctx);
-        self.sync_clocks(ctx);
-        self.update_schedules(ctx);
+
+        self.schedule_scan_services(ctx);
+        self.schedule_sync_clocks(ctx);
+        self.schedule_update_schedules(ctx);
+        self.schedule_collect_logos(ctx);
+        let names: Vec<String> = self.config.jobs.custom.keys()
+            .cloned()
+            .collect();
+        for name in names.into_iter() {
+            self.schedule_custom_job(name, ctx);
+        }
+
+        actix::fut::wrap_future::<_, Self>(
+            self.epg.send(QueryEpgStatusMessage))
+            .then(|result, act, ctx| {
+                let status = match result {
+                    Ok(Ok(status)) => status,
+                    Ok(Err(err)) => {
+                        log::error!("Failed to query EPG status: {}", err);
+                        Default::default()
+                    }
+                    Err(err) => {
+                        log::error!("Failed to query EPG status: {}", err);
+                        Default::default()
+                    }
+                };
+                act.cold_start(status, ctx);
+                actix::fut::ready(())
+            })
+            .spawn(ctx);
     }
 
     fn stopped(&mut self, _: &mut Self::Context) {
@@ -254,9 +625,36 @@ impl Actor for JobManager {
     }
 }
 
+// query jobs
+
+pub struct QueryJobsMessage;
+
+impl fmt::Display for QueryJobsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryJobs")
+    }
+}
+
+impl Message for QueryJobsMessage {
+    type Result = Result<Vec<JobInfo>, Error>;
+}
+
+impl Handler<QueryJobsMessage> for JobManager {
+    type Result = Result<Vec<JobInfo>, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryJobsMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        Ok(self.query_jobs())
+    }
+}
+
 // invoke scan services
 
-struct InvokeScanServicesMessage;
+pub struct InvokeScanServicesMessage;
 
 impl fmt::Display for InvokeScanServicesMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -265,11 +663,11 @@ impl fmt::Display for InvokeScanServicesMessage {
 }
 
 impl Message for InvokeScanServicesMessage {
-    type Result = ();
+    type Result = Result<(), Error>;
 }
 
 impl Handler<InvokeScanServicesMessage> for JobManager {
-    type Result = ();
+    type Result = Result<(), Error>;
 
     fn handle(
         &mut self,
@@ -277,13 +675,13 @@ impl Handler<InvokeScanServicesMessage> for JobManager {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         log::debug!("{}", msg);
-        self.invoke_scan_services(ctx);
+        self.invoke_scan_services(ctx)
     }
 }
 
 // invoke sync clocks
 
-struct InvokeSyncClocksMessage;
+pub struct InvokeSyncClocksMessage;
 
 impl fmt::Display for InvokeSyncClocksMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -292,11 +690,11 @@ impl fmt::Display for InvokeSyncClocksMessage {
 }
 
 impl Message for InvokeSyncClocksMessage {
-    type Result = ();
+    type Result = Result<(), Error>;
 }
 
 impl Handler<InvokeSyncClocksMessage> for JobManager {
-    type Result = ();
+    type Result = Result<(), Error>;
 
     fn handle(
         &mut self,
@@ -304,13 +702,13 @@ impl Handler<InvokeSyncClocksMessage> for JobManager {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         log::debug!("{}", msg);
-        self.invoke_sync_clocks(ctx);
+        self.invoke_sync_clocks(ctx)
     }
 }
 
 // invoke update schedules
 
-struct InvokeUpdateSchedulesMessage;
+pub struct InvokeUpdateSchedulesMessage;
 
 impl fmt::Display for InvokeUpdateSchedulesMessage {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -319,11 +717,11 @@ impl fmt::Display for InvokeUpdateSchedulesMessage {
 }
 
 impl Message for InvokeUpdateSchedulesMessage {
-    type Result = ();
+    type Result = Result<(), Error>;
 }
 
 impl Handler<InvokeUpdateSchedulesMessage> for JobManager {
-    type Result = ();
+    type Result = Result<(), Error>;
 
     fn handle(
         &mut self,
@@ -331,6 +729,308 @@ impl Handler<InvokeUpdateSchedulesMessage> for JobManager {
         ctx: &mut Self::Context,
     ) -> Self::Result {
         log::debug!("{}", msg);
-        self.invoke_update_schedules(ctx);
+        self.invoke_update_schedules(ctx)
+    }
+}
+
+// invoke collect logos
+
+pub struct InvokeCollectLogosMessage;
+
+impl fmt::Display for InvokeCollectLogosMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InvokeCollectLogos")
+    }
+}
+
+impl Message for InvokeCollectLogosMessage {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<InvokeCollectLogosMessage> for JobManager {
+    type Result = Result<(), Error>;
+
+    fn handle(
+        &mut self,
+        msg: InvokeCollectLogosMessage,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        self.invoke_collect_logos(ctx)
+    }
+}
+
+// invoke custom job
+
+pub struct InvokeCustomJobMessage {
+    pub name: String,
+}
+
+impl fmt::Display for InvokeCustomJobMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "InvokeCustomJob for {}", self.name)
+    }
+}
+
+impl Message for InvokeCustomJobMessage {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<InvokeCustomJobMessage> for JobManager {
+    type Result = Result<(), Error>;
+
+    fn handle(
+        &mut self,
+        msg: InvokeCustomJobMessage,
+        ctx: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        self.invoke_custom_job(msg.name, ctx)
+    }
+}
+
+// cancel job
+
+pub struct CancelJobMessage {
+    pub name: String,
+}
+
+impl fmt::Display for CancelJobMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CancelJob for {}", self.name)
+    }
+}
+
+impl Message for CancelJobMessage {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<CancelJobMessage> for JobManager {
+    type Result = Result<(), Error>;
+
+    fn handle(
+        &mut self,
+        msg: CancelJobMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        self.cancel_job(&msg.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+    use crate::models::TunerUserInfo;
+    use crate::channel_job::test_util::*;
+
+    #[actix_rt::test]
+    async fn test_invoke_custom_job() {
+        let job_manager = start_job_manager();
+
+        let result = job_manager.send(InvokeCustomJobMessage {
+            name: "hello".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Ok(()));
+
+        let job = wait_for_job(&job_manager, "hello").await;
+        assert!(!job.is_running);
+        assert!(job.last_duration.is_some());
+        assert_eq!(job.last_error, None);
+
+        let result = job_manager.send(InvokeCustomJobMessage {
+            name: "unknown".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Err(Error::JobNotFound));
+    }
+
+    #[actix_rt::test]
+    async fn test_cancel_job() {
+        let job_manager = start_job_manager();
+
+        let result = job_manager.send(InvokeCustomJobMessage {
+            name: "sleep".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Ok(()));
+
+        let job = query_job(&job_manager, "sleep").await;
+        assert!(job.is_running);
+
+        let result = job_manager.send(InvokeCustomJobMessage {
+            name: "sleep".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Err(Error::JobAlreadyRunning));
+
+        let result = job_manager.send(CancelJobMessage {
+            name: "sleep".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Ok(()));
+
+        let job = wait_for_job(&job_manager, "sleep").await;
+        assert!(!job.is_running);
+        assert_eq!(job.last_duration, None);
+        assert_eq!(job.last_error, Some("Canceled".to_string()));
+
+        // Canceling a job which is not running is not an error.
+        let result = job_manager.send(CancelJobMessage {
+            name: "sleep".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Ok(()));
+
+        let result = job_manager.send(CancelJobMessage {
+            name: "unknown".to_string(),
+        }).await.unwrap();
+        assert_matches!(result, Err(Error::JobNotFound));
+    }
+
+    #[actix_rt::test]
+    async fn test_cold_start() {
+        let config = Arc::new(serde_yaml::from_str::<Config>(r#"
+            channels:
+              - name: channel
+                type: GR
+                channel: '0'
+            jobs:
+              scan-services:
+                command: >-
+                  echo '[{"nid":1,"tsid":2,"sid":3,"type":1,"name":"sv"}]'
+                schedule: '0 0 0 1 1 * *'
+              sync-clocks:
+                command: >-
+                  echo '[{"nid":1,"tsid":2,"sid":3,
+                  "clock":{"pid":1,"pcr":2,"time":3}}]'
+                schedule: '0 0 0 1 1 * *'
+              update-schedules:
+                command: >-
+                  sh -c 'sleep 0.5; echo "$0"'
+                  '{"originalNetworkId":1,"transportStreamId":2,
+                  "serviceId":3,"tableId":80,"sectionNumber":0,
+                  "lastSectionNumber":0,"segmentLastSectionNumber":0,
+                  "versionNumber":1,"events":[]}'
+                schedule: '0 0 0 1 1 * *'
+              collect-logos:
+                command: 'true'
+                schedule: '0 0 0 1 1 * *'
+        "#).unwrap());
+
+        let users = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tuner_manager = create_job_recording_mock(users.clone());
+        let epg = crate::epg::start(config.clone());
+
+        let status = epg.send(QueryEpgStatusMessage).await.unwrap().unwrap();
+        assert!(!status.is_ready());
+
+        let eit_feeder = EitFeeder::new(
+            config.clone(), tuner_manager.clone().recipient(), epg.clone())
+            .start();
+        let job_manager = JobManager::new(
+            config, tuner_manager.recipient(), epg.clone(), eit_feeder)
+            .start();
+
+        // Wait until update-schedules starts collecting EIT sections.
+        let label = EitCollector::LABEL.to_string();
+        while !users.lock().unwrap().contains(&label) {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
+
+        let job = query_job(&job_manager, "update-schedules").await;
+        assert!(job.is_running);
+        let status = epg.send(QueryEpgStatusMessage).await.unwrap().unwrap();
+        assert_eq!(status.num_services, 1);
+        assert_eq!(status.num_clocks, 1);
+        assert!(!status.is_ready());
+
+        let job = wait_for_job(&job_manager, "update-schedules").await;
+        assert!(job.last_duration.is_some());
+        let status = epg.send(QueryEpgStatusMessage).await.unwrap().unwrap();
+        assert!(status.is_ready());
+
+        // Each job is invoked after the previous one finished.
+        assert_eq!(*users.lock().unwrap(), vec![
+            ServiceScanner::LABEL.to_string(),
+            ClockSynchronizer::LABEL.to_string(),
+            EitCollector::LABEL.to_string(),
+        ]);
+    }
+
+    // Returns a mock which records the name of a job using a tuner.
+    fn create_job_recording_mock(
+        users: Arc<std::sync::Mutex<Vec<String>>>,
+    ) -> Addr<TunerManagerMock> {
+        use crate::broadcaster::BroadcasterStream;
+        use crate::mpeg_ts_stream::MpegTsStream;
+
+        TunerManagerMock::mock(Box::new(move |msg, ctx| {
+            if let Some(msg) = msg.downcast_ref::<StartStreamingMessage>() {
+                if let TunerUserInfo::Job { ref name } = msg.user.info {
+                    users.lock().unwrap().push(name.clone());
+                }
+                let (_, stream) = BroadcasterStream::new_for_test();
+                let result: Result<_, Error> = Ok(MpegTsStream::new(
+                    Default::default(), stream, ctx.address().recipient()));
+                Box::new(Some(result))
+            } else if let Some(_) = msg.downcast_ref::<StopStreamingMessage>() {
+                Box::new(Some(()))
+            } else {
+                unimplemented!();
+            }
+        })).start()
+    }
+
+    fn start_job_manager() -> Addr<JobManager> {
+        let config = Arc::new(serde_yaml::from_str::<Config>(r#"
+            channels:
+              - name: channel
+                type: GR
+                channel: '0'
+            jobs:
+              scan-services:
+                command: 'true'
+                schedule: '0 0 0 1 1 * *'
+              sync-clocks:
+                command: 'true'
+                schedule: '0 0 0 1 1 * *'
+              update-schedules:
+                command: 'true'
+                schedule: '0 0 0 1 1 * *'
+              collect-logos:
+                command: 'true'
+                schedule: '0 0 0 1 1 * *'
+              custom:
+                hello:
+                  command: 'echo hello'
+                  schedule: '0 0 0 1 1 * *'
+                sleep:
+                  command: 'sleep 10'
+                  schedule: '0 0 0 1 1 * *'
+        "#).unwrap());
+        let tuner_manager = create_tuner_manager_mock();
+        let epg = crate::epg::start(config.clone());
+        let eit_feeder = EitFeeder::new(
+            config.clone(), tuner_manager.clone().recipient(), epg.clone())
+            .start();
+        JobManager::new(config, tuner_manager.recipient(), epg, eit_feeder)
+            .start()
+    }
+
+    async fn query_job(job_manager: &Addr<JobManager>, name: &str) -> JobInfo {
+        job_manager.send(QueryJobsMessage).await.unwrap().unwrap()
+            .into_iter()
+            .find(|job| job.name == name)
+            .unwrap()
+    }
+
+    async fn wait_for_job(
+        job_manager: &Addr<JobManager>,
+        name: &str,
+    ) -> JobInfo {
+        loop {
+            let job = query_job(job_manager, name).await;
+            if !job.is_running {
+                return job;
+            }
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+        }
     }
 }
@@ -0,0 +1,120 @@
+use actix::prelude::*;
+use base64;
+use failure::Error;
+use log;
+use serde::Deserialize;
+use serde_json;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+use crate::channel_job::*;
+use crate::epg::*;
+use crate::models::*;
+use crate::tuner::*;
+
+pub struct LogoCollector {
+    runner: ChannelJobRunner,
+}
+
+impl LogoCollector {
+    pub const LABEL: &'static str = "logo-collector";
+
+    pub fn new(
+        command: String,
+        channels: Vec<EpgChannel>,
+        stream_manager: Recipient<StartStreamingMessage>,
+        options: ChannelJobOptions,
+    ) -> Self {
+        LogoCollector {
+            runner: ChannelJobRunner::new(
+                Self::LABEL, command, channels, stream_manager, options),
+        }
+    }
+
+    pub async fn collect_logos(
+        self
+    ) -> Vec<(EpgChannel, Option<Vec<EpgLogo>>)> {
+        log::debug!("Collecting logos...");
+
+        let results = self.runner.run(Self::collect_logos_in_channel).await;
+
+        log::debug!("Collected logos in {} channels", results.len());
+
+        results
+            .into_iter()
+            .map(|result| result.ok())
+            .collect()
+    }
+
+    async fn collect_logos_in_channel(
+        channel: EpgChannel,
+        output: ChannelJobOutput,
+    ) -> Result<Vec<EpgLogo>, Error> {
+        let mut reader = BufReader::new(output);
+        let mut json = String::new();
+        let mut logos = Vec::new();
+        while reader.read_line(&mut json).await? > 0 {
+            let data = serde_json::from_str::<CdtLogoData>(&json)?;
+            logos.push(data.into_logo()?);
+            json.clear();
+        }
+
+        log::debug!("Collected {} logos in {}", logos.len(), channel.name);
+
+        Ok(logos)
+    }
+}
+
+// A logo data extracted from CDT, output from the command as a JSON object in
+// each line.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CdtLogoData {
+    original_network_id: NetworkId,
+    logo_id: i16,
+    logo_type: u8,
+    // A PNG image encoded in BASE64.
+    data: String,
+}
+
+impl CdtLogoData {
+    fn into_logo(self) -> Result<EpgLogo, Error> {
+        Ok(EpgLogo {
+            nid: self.original_network_id,
+            logo_id: self.logo_id,
+            logo_type: self.logo_type,
+            data: base64::decode(&self.data)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel_job::test_util::*;
+
+    #[actix_rt::test]
+    async fn test_collect_logos() {
+        let mock = create_tuner_manager_mock();
+        let channels = create_channels();
+
+        let cmd = r#"echo '{"originalNetworkId":1,"logoId":2,"logoVersion":3,"logoType":5,"data":"UE5H"}'"#;
+        let collector = LogoCollector::new(
+            cmd.to_string(), channels.clone(), mock.clone().recipient(),
+            Default::default());
+        let results = collector.collect_logos().await;
+        assert_eq!(results.len(), 1);
+        let logos = results[0].1.as_ref().unwrap();
+        assert_eq!(logos.len(), 1);
+        assert_eq!(logos[0].nid, 1.into());
+        assert_eq!(logos[0].logo_id, 2);
+        assert_eq!(logos[0].logo_type, 5);
+        assert_eq!(logos[0].data, b"PNG");
+
+        let collector = LogoCollector::new(
+            "echo '{}'".to_string(), channels.clone(),
+            mock.clone().recipient(), Default::default());
+        let results = collector.collect_logos().await;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].1.is_none());
+    }
+}
@@ -1,19 +1,29 @@
 mod airtime_tracker;
 mod broadcaster;
+mod channel_job;
+mod channel_scanner;
 mod chunk_stream;
 mod clock_synchronizer;
 mod command_util;
 mod config;
+mod custom_job;
 mod datetime_ext;
 mod eit_feeder;
 mod epg;
+mod epg_storage;
 mod error;
-//mod fs_util;
+mod fs_util;
+mod ip_net;
 mod job;
+mod logo_collector;
 mod models;
 mod mpeg_ts_stream;
+mod search_index;
+mod series;
 mod service_scanner;
+mod streaming_quota;
 mod string_table;
+mod tls;
 mod tokio_snippet;
 mod tracing_ext;
 mod tuner;
@@ -24,8 +34,19 @@ use clap;
 use crate::error::Error;
 use crate::tracing_ext::init_tracing;
 
-#[actix_rt::main]
-async fn main() -> Result<(), Error> {
+fn main() -> Result<(), Error> {
+    // The actor system is shut down when `block_on()` returns.  Actors are
+    // dropped and child processes like tuner commands are terminated before
+    // restarting.
+    let restart = actix_rt::System::new("mirakc").block_on(run())?;
+    if restart {
+        restart_self()?;
+    }
+    Ok(())
+}
+
+// Returns true if a restart has been requested.
+async fn run() -> Result<bool, Error> {
     let args = clap::App::new(clap::crate_name!())
         .version(clap::crate_version!())
         .about(clap::crate_description!())
@@ -69,12 +90,23 @@ async fn main() -> Result<(), Error> {
     let eit_feeder = eit_feeder::start(
         config.clone(), tuner_manager.clone(), epg.clone());
 
-    let _job_manager = job::start(
+    let job_manager = job::start(
         config.clone(), tuner_manager.clone(), epg.clone(), eit_feeder.clone());
 
     web::serve(
         config.clone(), string_table.clone(), tuner_manager.clone(),
-        epg.clone()).await?;
+        epg.clone(), job_manager.clone()).await
+}
 
-    Ok(())
+// Replaces the current process with a new one in order to reload the config.
+fn restart_self() -> Result<(), Error> {
+    use std::os::unix::process::CommandExt;
+
+    let exe = std::env::current_exe()?;
+    log::info!("Restarting {}...", exe.display());
+    // exec() returns only when it fails.
+    let err = std::process::Command::new(exe)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    Err(err.into())
 }
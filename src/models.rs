@@ -4,9 +4,12 @@ use chrono::{DateTime, Duration};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+use crate::config::{ChannelConfig, ServerAddr, ServerConfig, TunerConfig};
 use crate::datetime_ext::{serde_jst, serde_duration_in_millis, Jst};
 use crate::epg::{EpgChannel, EpgService, EpgProgram};
+use crate::error::Error;
 use crate::mpeg_ts_stream::MpegTsStreamId;
+use crate::series::SeriesInfo;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 #[derive(Deserialize, Serialize)]
@@ -143,6 +146,12 @@ impl From<(NetworkId, TransportStreamId, ServiceId)> for ServiceTriple {
     }
 }
 
+impl From<u64> for ServiceTriple {
+    fn from(value: u64) -> Self {
+        ServiceTriple(value)
+    }
+}
+
 impl From<EventQuad> for ServiceTriple {
     fn from(quad: EventQuad) -> Self {
         ServiceTriple::new(quad.nid(), quad.tsid(), quad.sid())
@@ -296,6 +305,50 @@ impl EpgAudioInfo {
     }
 }
 
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgAudioComponentInfo {
+    pub component_type: u8,
+    pub component_tag: u8,
+    pub is_main: bool,
+    pub sampling_rate: i32,
+    pub langs: Vec<String>,
+}
+
+impl EpgAudioComponentInfo {
+    pub fn new(
+        component_type: u8,
+        component_tag: u8,
+        is_main: bool,
+        sampling_rate: u8,
+        langs: Vec<String>,
+    ) -> EpgAudioComponentInfo {
+        EpgAudioComponentInfo {
+            component_type,
+            component_tag,
+            is_main,
+            sampling_rate: EpgAudioInfo::get_sampling_rate(sampling_rate),
+            langs,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgSeriesInfo {
+    pub id: u16,
+    pub repeat: u8,
+    pub pattern: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    pub episode: u16,
+    pub last_episode: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -312,12 +365,50 @@ impl EpgGenre {
     }
 }
 
+#[derive(Clone, Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EpgRelatedItem {
+    #[serde(rename = "type")]
+    pub item_type: EpgRelatedItemType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_id: Option<NetworkId>,
+    pub service_id: ServiceId,
+    pub event_id: EventId,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EpgRelatedItemType {
+    Shared,
+    Relay,
+    Movement,
+}
+
+impl EpgRelatedItemType {
+    // See ARIB STD-B10 for the group types of the event group descriptor.
+    pub fn from_group_type(group_type: u8) -> Option<Self> {
+        match group_type {
+            1 => Some(EpgRelatedItemType::Shared),
+            2 | 4 => Some(EpgRelatedItemType::Relay),
+            3 | 5 => Some(EpgRelatedItemType::Movement),
+            _ => None,
+        }
+    }
+}
+
 // user
 
 #[derive(Clone)]
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum TunerUserInfo {
     Job { name: String },
-    Tracker { stream_id: MpegTsStreamId },
+    Tracker {
+        #[serde(rename = "streamId")]
+        stream_id: MpegTsStreamId,
+    },
     Web { remote: Option<String>, agent: Option<String> },
 }
 
@@ -351,6 +442,7 @@ impl fmt::Display for TunerUserInfo {
 }
 
 #[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+#[derive(Serialize)]
 pub struct TunerUserPriority(i32);
 
 impl TunerUserPriority {
@@ -511,6 +603,23 @@ pub struct MirakurunTuner {
     pub is_free: bool,
     pub is_using: bool,
     pub is_fault: bool,
+    // mirakc extensions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<TunerSessionInfo>,
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TunerSessionInfo {
+    #[serde(with = "serde_jst")]
+    pub started_at: DateTime<Jst>,
+    pub channel_type: ChannelType,
+    pub channel: String,
+    pub bytes_per_second: u64,
+    pub dropped_chunks: u64,
+    // In milliseconds.
+    pub time_since_last_packet: u64,
 }
 
 #[derive(Debug)]
@@ -573,7 +682,7 @@ impl From<EpgService> for MirakurunService {
             remote_control_key_id: sv.remote_control_key_id,
             name: sv.name,
             channel: sv.channel.into(),
-            has_logo_data: false,
+            has_logo_data: sv.has_logo_data,
         }
     }
 }
@@ -620,11 +729,26 @@ pub struct MirakurunProgram {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub audio: Option<EpgAudioInfo>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audios: Option<Vec<EpgAudioComponentInfo>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub genres: Option<Vec<EpgGenre>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series: Option<EpgSeriesInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub related_items: Option<Vec<EpgRelatedItem>>,
+    // mirakc extensions
+    pub has_caption: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub series_title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub episode_number: Option<u32>,
 }
 
 impl From<EpgProgram> for MirakurunProgram {
     fn from(program: EpgProgram) -> Self {
+        let series = SeriesInfo::detect(&program);
         Self {
             id: program.quad.into(),
             event_id: program.quad.eid(),
@@ -638,7 +762,207 @@ impl From<EpgProgram> for MirakurunProgram {
             extended: program.extended,
             video: program.video,
             audio: program.audio,
+            audios: program.audios,
             genres: program.genres,
+            series: program.series,
+            related_items: program.related_items,
+            has_caption: program.has_caption,
+            series_id: series.as_ref().map(|series| series.id),
+            episode_number: series.as_ref().and_then(|series| series.episode),
+            series_title: series.map(|series| series.title),
+        }
+    }
+}
+
+// Mirakurun-compatible shapes of the config.
+
+#[derive(Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirakurunConfigServer {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+impl From<&ServerConfig> for MirakurunConfigServer {
+    fn from(config: &ServerConfig) -> Self {
+        let path = config.addrs.iter()
+            .filter_map(|addr| match addr {
+                ServerAddr::Unix(path) => Some(path.clone()),
+                _ => None,
+            })
+            .next();
+        let (hostname, port) = config.addrs.iter()
+            .filter_map(|addr| match addr {
+                ServerAddr::Http(addr) => split_host_port(addr),
+                _ => None,
+            })
+            .next()
+            .map_or((None, None), |(host, port)| (Some(host), Some(port)));
+        MirakurunConfigServer { path, port, hostname }
+    }
+}
+
+impl MirakurunConfigServer {
+    // Returns addresses replacing the first HTTP and UNIX domain socket
+    // addresses in `addrs`, which are the ones returned from `from()`.  Other
+    // addresses are kept.
+    //
+    // The first HTTP address is removed if `port` is not specified.  The first
+    // UNIX domain socket address is removed if `path` is not specified.
+    pub fn apply_to_addrs(
+        &self,
+        addrs: &[ServerAddr],
+    ) -> Result<Vec<ServerAddr>, Error> {
+        let mut http = self.port.map(|port| {
+            let hostname = self.hostname.as_deref().unwrap_or("0.0.0.0");
+            if hostname.contains(':') {
+                ServerAddr::Http(format!("[{}]:{}", hostname, port))
+            } else {
+                ServerAddr::Http(format!("{}:{}", hostname, port))
+            }
+        });
+        let mut unix = self.path.clone().map(ServerAddr::Unix);
+
+        let mut http_replaced = false;
+        let mut unix_replaced = false;
+        let mut result = Vec::new();
+        for addr in addrs.iter() {
+            match addr {
+                ServerAddr::Http(_) if !http_replaced => {
+                    http_replaced = true;
+                    result.extend(http.take());
+                }
+                ServerAddr::Unix(_) if !unix_replaced => {
+                    unix_replaced = true;
+                    result.extend(unix.take());
+                }
+                _ => result.push(addr.clone()),
+            }
+        }
+        result.extend(http);
+        result.extend(unix);
+
+        let has_addr = result.iter().any(|addr| match addr {
+            ServerAddr::Http(_) | ServerAddr::Unix(_) => true,
+            _ => false,
+        });
+        if !has_addr {
+            return Err(Error::InvalidConfig(
+                "No HTTP or UNIX domain socket address".to_string()));
+        }
+        Ok(result)
+    }
+}
+
+fn split_host_port(addr: &str) -> Option<(String, u16)> {
+    let pos = addr.rfind(':')?;
+    let port = addr[pos + 1..].parse().ok()?;
+    let host = addr[..pos].trim_start_matches('[').trim_end_matches(']');
+    Some((host.to_string(), port))
+}
+
+#[derive(Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirakurunConfigChannel {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub channel_type: ChannelType,
+    pub channel: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service_id: Option<ServiceId>,
+    #[serde(default)]
+    pub is_disabled: bool,
+    // mirakc extensions
+    #[serde(default)]
+    pub services: Vec<ServiceId>,
+    #[serde(default)]
+    pub excluded_services: Vec<ServiceId>,
+    #[serde(default)]
+    pub extra_args: String,
+}
+
+impl From<&ChannelConfig> for MirakurunConfigChannel {
+    fn from(config: &ChannelConfig) -> Self {
+        let service_id = match config.services.as_slice() {
+            [sid] => Some(*sid),
+            _ => None,
+        };
+        MirakurunConfigChannel {
+            name: config.name.clone(),
+            channel_type: config.channel_type,
+            channel: config.channel.clone(),
+            service_id,
+            is_disabled: config.disabled,
+            services: config.services.clone(),
+            excluded_services: config.excluded_services.clone(),
+            extra_args: config.extra_args.clone(),
+        }
+    }
+}
+
+impl Into<ChannelConfig> for MirakurunConfigChannel {
+    fn into(self) -> ChannelConfig {
+        let mut services = self.services;
+        if let Some(sid) = self.service_id {
+            if !services.contains(&sid) {
+                services.push(sid);
+            }
+        }
+        ChannelConfig {
+            name: self.name,
+            channel_type: self.channel_type,
+            channel: self.channel,
+            extra_args: self.extra_args,
+            services,
+            excluded_services: self.excluded_services,
+            disabled: self.is_disabled,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirakurunConfigTuner {
+    pub name: String,
+    pub types: Vec<ChannelType>,
+    #[serde(default)]
+    pub command: String,
+    #[serde(default)]
+    pub is_disabled: bool,
+    // mirakc extensions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit: Option<u64>,
+}
+
+impl From<&TunerConfig> for MirakurunConfigTuner {
+    fn from(config: &TunerConfig) -> Self {
+        MirakurunConfigTuner {
+            name: config.name.clone(),
+            types: config.channel_types.clone(),
+            command: config.command.clone(),
+            is_disabled: config.disabled,
+            time_limit: Some(config.time_limit),
+        }
+    }
+}
+
+impl Into<TunerConfig> for MirakurunConfigTuner {
+    fn into(self) -> TunerConfig {
+        let time_limit = self.time_limit
+            .unwrap_or_else(TunerConfig::default_time_limit);
+        TunerConfig {
+            name: self.name,
+            channel_types: self.types,
+            command: self.command,
+            time_limit,
+            disabled: self.is_disabled,
         }
     }
 }
@@ -695,6 +1019,95 @@ mod tests {
         assert!(TunerUserPriority::from(128).is_grab());
     }
 
+    #[test]
+    fn test_mirakurun_config_server() {
+        let config = ServerConfig {
+            addrs: vec![
+                ServerAddr::Http("localhost:40772".to_string()),
+                ServerAddr::Https {
+                    addr: "0.0.0.0:40773".to_string(),
+                    cert: "/cert.pem".to_string(),
+                    key: "/key.pem".to_string(),
+                },
+                ServerAddr::Unix("/var/run/mirakc.sock".to_string()),
+            ],
+            ..Default::default()
+        };
+        let server = MirakurunConfigServer::from(&config);
+        assert_eq!(server, MirakurunConfigServer {
+            path: Some("/var/run/mirakc.sock".to_string()),
+            port: Some(40772),
+            hostname: Some("localhost".to_string()),
+        });
+        assert_eq!(server.apply_to_addrs(&config.addrs).unwrap(),
+                   config.addrs);
+
+        let server = MirakurunConfigServer {
+            path: None,
+            port: Some(40772),
+            hostname: Some("::".to_string()),
+        };
+        assert_eq!(server.apply_to_addrs(&[]).unwrap(), vec![
+            ServerAddr::Http("[::]:40772".to_string()),
+        ]);
+
+        // Only the first HTTP address is replaced.
+        let addrs = vec![
+            ServerAddr::Http("localhost:40772".to_string()),
+            ServerAddr::Http("192.168.0.1:40772".to_string()),
+            ServerAddr::Unix("/var/run/mirakc.sock".to_string()),
+        ];
+        let server = MirakurunConfigServer {
+            path: None,
+            port: Some(8080),
+            hostname: None,
+        };
+        assert_eq!(server.apply_to_addrs(&addrs).unwrap(), vec![
+            ServerAddr::Http("0.0.0.0:8080".to_string()),
+            ServerAddr::Http("192.168.0.1:40772".to_string()),
+        ]);
+
+        let server = MirakurunConfigServer {
+            path: None,
+            port: None,
+            hostname: None,
+        };
+        assert_eq!(server.apply_to_addrs(&addrs).unwrap(), vec![
+            ServerAddr::Http("192.168.0.1:40772".to_string()),
+        ]);
+        assert!(server.apply_to_addrs(&config.addrs).is_err());
+        assert!(server.apply_to_addrs(&[]).is_err());
+        assert_eq!(split_host_port("[::]:40772"), Some(("::".to_string(), 40772)));
+    }
+
+    #[test]
+    fn test_mirakurun_config_channel() {
+        let channel: ChannelConfig = serde_json::from_str::<MirakurunConfigChannel>(
+            r#"{"name":"ch","type":"GR","channel":"27","serviceId":1024}"#)
+            .unwrap()
+            .into();
+        assert_eq!(channel.services, vec![1024.into()]);
+        assert!(!channel.disabled);
+
+        let mirakurun = MirakurunConfigChannel::from(&channel);
+        assert_eq!(mirakurun.service_id, Some(1024.into()));
+        let back: ChannelConfig = mirakurun.into();
+        assert_eq!(back, channel);
+    }
+
+    #[test]
+    fn test_mirakurun_config_tuner() {
+        let tuner: TunerConfig = serde_json::from_str::<MirakurunConfigTuner>(
+            r#"{"name":"t","types":["GR"],"command":"cmd","isDisabled":true}"#)
+            .unwrap()
+            .into();
+        assert_eq!(tuner.time_limit, TunerConfig::default_time_limit());
+        assert!(tuner.disabled);
+
+        let back: TunerConfig = MirakurunConfigTuner::from(&tuner).into();
+        assert_eq!(back, tuner);
+    }
+
     #[test]
     fn test_mirakurun_service_id() {
         let nid = 1.into();
@@ -0,0 +1,315 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::epg::EpgProgram;
+use crate::models::{EventQuad, ServiceTriple};
+
+// An in-memory inverted index for searching programs by text.
+//
+// Japanese texts have no word boundaries.  So, each text is split into
+// character bigrams after normalization, and the bigrams are used as keys of
+// the index.  Candidates found in the index may contain programs which have
+// all the bigrams of a search term but not the term itself.  They're removed
+// by `matches()` before returning the search result.
+//
+// The index is updated for each service so that updates of a service don't
+// rebuild the whole index.
+#[derive(Default)]
+pub struct ProgramSearchIndex {
+    postings: HashMap<(char, char), HashSet<EventQuad>>,
+    // Bigrams of each program, used for removing programs of a service from
+    // `postings`.
+    services: HashMap<ServiceTriple, HashMap<EventQuad, HashSet<(char, char)>>>,
+}
+
+impl ProgramSearchIndex {
+    // Used as the second character of the last bigram in each text so that
+    // every character in the text appears as the first character of a bigram.
+    const TERMINATOR: char = '\0';
+
+    pub fn build<'a, I>(programs: I) -> Self
+    where
+        I: IntoIterator<Item = &'a EpgProgram>,
+    {
+        let mut index = Self::default();
+        for program in programs.into_iter() {
+            index.add_program(program);
+        }
+        index
+    }
+
+    // Replaces programs of the service with `programs`.
+    pub fn update_service<'a, I>(&mut self, triple: ServiceTriple, programs: I)
+    where
+        I: IntoIterator<Item = &'a EpgProgram>,
+    {
+        self.remove_service(triple);
+        for program in programs.into_iter() {
+            self.add_program(program);
+        }
+    }
+
+    // Returns candidates of programs matching all terms in the query.
+    //
+    // Use `matches()` in order to remove false positives from the candidates.
+    pub fn search(&self, query: &str) -> HashSet<EventQuad> {
+        let mut result: Option<HashSet<EventQuad>> = None;
+        for term in normalize(query).split_whitespace() {
+            let candidates = self.search_term(term);
+            result = Some(match result {
+                Some(result) => result.intersection(&candidates)
+                    .cloned().collect(),
+                None => candidates,
+            });
+        }
+        result.unwrap_or_default()
+    }
+
+    // Returns true if the program contains all terms in the query.
+    pub fn matches(program: &EpgProgram, query: &str) -> bool {
+        let texts: Vec<String> = texts(program).map(normalize).collect();
+        let query = normalize(query);
+        let mut terms = query.split_whitespace().peekable();
+        if terms.peek().is_none() {
+            return false;
+        }
+        terms.all(|term| texts.iter().any(|text| text.contains(term)))
+    }
+
+    fn add_program(&mut self, program: &EpgProgram) {
+        for text in texts(program) {
+            self.add(program.quad, &normalize(text));
+        }
+    }
+
+    fn add(&mut self, quad: EventQuad, text: &str) {
+        let service_bigrams = self.services
+            .entry(ServiceTriple::from(quad))
+            .or_default()
+            .entry(quad)
+            .or_default();
+        for bigram in bigrams(text) {
+            self.postings
+                .entry(bigram)
+                .or_default()
+                .insert(quad);
+            service_bigrams.insert(bigram);
+        }
+    }
+
+    fn remove_service(&mut self, triple: ServiceTriple) {
+        let programs = match self.services.remove(&triple) {
+            Some(programs) => programs,
+            None => return,
+        };
+        for (quad, bigrams) in programs.into_iter() {
+            for bigram in bigrams.into_iter() {
+                if let Some(quads) = self.postings.get_mut(&bigram) {
+                    quads.remove(&quad);
+                    if quads.is_empty() {
+                        self.postings.remove(&bigram);
+                    }
+                }
+            }
+        }
+    }
+
+    fn search_term(&self, term: &str) -> HashSet<EventQuad> {
+        let chars: Vec<char> = term.chars().collect();
+        if chars.len() == 1 {
+            // Collect all programs having a bigram which starts with the
+            // character.
+            return self.postings
+                .iter()
+                .filter(|(bigram, _)| bigram.0 == chars[0])
+                .flat_map(|(_, quads)| quads.iter().cloned())
+                .collect();
+        }
+
+        let mut result: Option<HashSet<EventQuad>> = None;
+        for bigram in chars.windows(2).map(|pair| (pair[0], pair[1])) {
+            let quads = match self.postings.get(&bigram) {
+                Some(quads) => quads,
+                None => return HashSet::new(),
+            };
+            result = Some(match result {
+                Some(result) => result.intersection(quads).cloned().collect(),
+                None => quads.clone(),
+            });
+        }
+        result.unwrap_or_default()
+    }
+}
+
+fn texts(program: &EpgProgram) -> impl Iterator<Item = &str> {
+    program.name
+        .iter()
+        .chain(program.description.iter())
+        .chain(program.extended.iter().flat_map(|extended| extended.values()))
+        .map(|text| text.as_str())
+}
+
+fn bigrams(text: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = text.chars()
+        .filter(|ch| !ch.is_whitespace())
+        .chain(std::iter::once(ProgramSearchIndex::TERMINATOR))
+        .collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+// Normalizes a text for searching.
+//
+// * Full-width alphanumerics and symbols are converted into ASCII characters
+// * Half-width katakana are converted into full-width katakana
+// * Katakana are converted into hiragana
+// * ASCII characters are converted into lowercase
+pub fn normalize(text: &str) -> String {
+    let mut chars: Vec<char> = Vec::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '\u{FF9E}' | '\u{FF9F}' => {
+                if let Some(last) = chars.last_mut() {
+                    if let Some(composed) = compose_sound_mark(*last, ch) {
+                        *last = composed;
+                        continue;
+                    }
+                }
+                chars.push(HALFWIDTH_KATAKANA[(ch as usize) - 0xFF61]);
+            }
+            '\u{FF61}'..='\u{FF9D}' =>
+                chars.push(HALFWIDTH_KATAKANA[(ch as usize) - 0xFF61]),
+            _ => chars.push(ch),
+        }
+    }
+    chars.into_iter().map(normalize_char).collect()
+}
+
+fn normalize_char(ch: char) -> char {
+    let ch = match ch {
+        // Full-width ASCII
+        '\u{FF01}'..='\u{FF5E}' =>
+            std::char::from_u32(ch as u32 - 0xFEE0).unwrap(),
+        // Ideographic space
+        '\u{3000}' => ' ',
+        // Katakana
+        '\u{30A1}'..='\u{30F6}' | '\u{30FD}'..='\u{30FE}' =>
+            std::char::from_u32(ch as u32 - 0x60).unwrap(),
+        _ => ch,
+    };
+    ch.to_ascii_lowercase()
+}
+
+// Composes a full-width katakana and a half-width (semi-)voiced sound mark.
+fn compose_sound_mark(ch: char, mark: char) -> Option<char> {
+    let code = ch as u32;
+    let voiceable = match code {
+        0x30AB..=0x30C2 => (code - 0x30AB) % 2 == 0,  // KA..DI
+        0x30C4 | 0x30C6 | 0x30C8 => true,  // TU, TE, TO
+        0x30CF..=0x30DD => (code - 0x30CF) % 3 == 0,  // HA..HO
+        _ => false,
+    };
+    match mark {
+        '\u{FF9E}' if code == 0x30A6 => Some('\u{30F4}'),  // VU
+        '\u{FF9E}' if voiceable => std::char::from_u32(code + 1),
+        '\u{FF9F}' if voiceable && code >= 0x30CF =>
+            std::char::from_u32(code + 2),
+        _ => None,
+    }
+}
+
+// Full-width characters corresponding to U+FF61..U+FF9F.
+const HALFWIDTH_KATAKANA: [char; 63] = [
+    '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ',
+    'ョ', 'ッ', 'ー', 'ア', 'イ', 'ウ', 'エ', 'オ', 'カ', 'キ', 'ク', 'ケ', 'コ',
+    'サ', 'シ', 'ス', 'セ', 'ソ', 'タ', 'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ',
+    'ネ', 'ノ', 'ハ', 'ヒ', 'フ', 'ヘ', 'ホ', 'マ', 'ミ', 'ム', 'メ', 'モ', 'ヤ',
+    'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ', 'ン', '゛', '゜',
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("ＡＢＣ１２３"), "abc123");
+        assert_eq!(normalize("カタカナ"), "かたかな");
+        assert_eq!(normalize("ｶﾀｶﾅ"), "かたかな");
+        assert_eq!(normalize("ｶﾞｷﾞﾊﾟﾋﾟｳﾞ"), "がぎぱぴゔ");
+        assert_eq!(normalize("ｱﾞ"), "あ゛");
+        assert_eq!(normalize("ニュース　７"), "にゅーす 7");
+    }
+
+    #[test]
+    fn test_search() {
+        let mut program1 = EpgProgram::new((1, 2, 3, 1).into());
+        program1.name = Some("ＮＨＫニュース７".to_string());
+        let mut program2 = EpgProgram::new((1, 2, 3, 2).into());
+        program2.description = Some("ﾆｭｰｽ".to_string());
+        let mut program3 = EpgProgram::new((1, 2, 3, 3).into());
+        program3.extended = Some(indexmap::indexmap!{
+            "出演者".to_string() => "ドラマ".to_string(),
+        });
+        let programs = [program1, program2, program3];
+
+        let index = ProgramSearchIndex::build(programs.iter());
+        assert!(!index.postings.is_empty());
+
+        let result = index.search("にゅーす");
+        assert_eq!(result.len(), 2);
+        assert!(result.contains(&(1, 2, 3, 1).into()));
+        assert!(result.contains(&(1, 2, 3, 2).into()));
+
+        let result = index.search("nhk ニュース");
+        assert_eq!(result.len(), 1);
+        assert!(result.contains(&(1, 2, 3, 1).into()));
+
+        let result = index.search("ドラマ");
+        assert_eq!(result.len(), 1);
+        assert!(result.contains(&(1, 2, 3, 3).into()));
+
+        let result = index.search("出演者");
+        assert!(result.is_empty());
+
+        let result = index.search("7");
+        assert_eq!(result.len(), 1);
+
+        assert!(index.search("").is_empty());
+        assert!(index.search("天気").is_empty());
+    }
+
+    #[test]
+    fn test_update_service() {
+        let mut program1 = EpgProgram::new((1, 2, 3, 1).into());
+        program1.name = Some("ニュース".to_string());
+        let mut program2 = EpgProgram::new((1, 2, 4, 1).into());
+        program2.name = Some("ニュース".to_string());
+        let mut index =
+            ProgramSearchIndex::build([program1, program2].iter());
+        assert_eq!(index.search("ニュース").len(), 2);
+
+        let mut program3 = EpgProgram::new((1, 2, 3, 2).into());
+        program3.name = Some("天気".to_string());
+        index.update_service((1, 2, 3).into(), [program3].iter());
+        let result = index.search("ニュース");
+        assert_eq!(result.len(), 1);
+        assert!(result.contains(&(1, 2, 4, 1).into()));
+        let result = index.search("天気");
+        assert_eq!(result.len(), 1);
+        assert!(result.contains(&(1, 2, 3, 2).into()));
+
+        index.update_service((1, 2, 4).into(), [].iter());
+        assert!(index.search("ニュース").is_empty());
+        assert!(!index.postings.contains_key(&('に', 'ゅ')));
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut program = EpgProgram::new((1, 2, 3, 1).into());
+        program.name = Some("ＮＨＫニュース７".to_string());
+        assert!(ProgramSearchIndex::matches(&program, "nhk"));
+        assert!(ProgramSearchIndex::matches(&program, "ﾆｭｰｽ 7"));
+        assert!(!ProgramSearchIndex::matches(&program, "nhkにゅーす 8"));
+        assert!(!ProgramSearchIndex::matches(&program, "hkn"));
+        assert!(!ProgramSearchIndex::matches(&program, ""));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::epg::EpgProgram;
+use crate::models::NetworkId;
+use crate::search_index;
+
+// Series information detected from a program.
+//
+// Programs in the same series have the same series ID.  The series ID is
+// computed from the network ID and the series title normalized for searching,
+// so that the same ID is assigned to a series every time.  The series ID in
+// the series descriptor is used instead of the title if it exists.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeriesInfo {
+    pub id: u32,
+    pub title: String,
+    pub episode: Option<u32>,
+}
+
+impl SeriesInfo {
+    pub fn detect(program: &EpgProgram) -> Option<Self> {
+        let (title, episode) = match program.name {
+            Some(ref name) => parse_title(name),
+            None => (String::new(), None),
+        };
+
+        // Prefer the series descriptor if it exists.
+        if let Some(ref series) = program.series {
+            let title = match series.name {
+                Some(ref name) if !name.is_empty() => name.clone(),
+                _ => title,
+            };
+            let episode = match series.episode {
+                0 => episode,
+                n => Some(n as u32),
+            };
+            return Some(SeriesInfo {
+                id: series_id(
+                    program.quad.nid(), SeriesKey::Descriptor(series.id)),
+                title,
+                episode,
+            });
+        }
+
+        if title.is_empty() {
+            return None;
+        }
+        Some(SeriesInfo {
+            id: series_id(program.quad.nid(), SeriesKey::Title(&title)),
+            title,
+            episode,
+        })
+    }
+}
+
+// Markers enclosed in brackets, which are removed from titles.
+const MARKERS: &[&str] = &[
+    "新", "再", "終", "字", "デ", "二", "多", "解", "手", "双", "天", "映",
+    "無", "料", "無料", "生", "初", "吹", "前", "後", "声", "S", "SS", "B",
+    "N", "HV", "SD", "PV", "MV", "5.1", "PPV",
+];
+
+// Returns the series title and the episode number.
+fn parse_title(name: &str) -> (String, Option<u32>) {
+    let name = strip_markers(&normalize_width(name));
+    match find_episode(&name) {
+        Some((pos, episode)) => {
+            let title = name[..pos].trim();
+            if title.is_empty() {
+                // The title starts with an episode marker like "#1 Title".
+                (name.trim().to_string(), Some(episode))
+            } else {
+                (title.to_string(), Some(episode))
+            }
+        }
+        None => (name.trim().to_string(), None),
+    }
+}
+
+// Converts full-width alphanumerics, symbols and spaces into ASCII characters.
+fn normalize_width(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            '\u{FF01}'..='\u{FF5E}' =>
+                std::char::from_u32(ch as u32 - 0xFEE0).unwrap(),
+            '\u{3000}' => ' ',
+            _ => ch,
+        })
+        .collect()
+}
+
+fn strip_markers(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        // Enclosed characters like U+1F21F used for ARIB additional symbols.
+        if ('\u{1F100}'..='\u{1F2FF}').contains(&ch) {
+            i += 1;
+            continue;
+        }
+        if let Some(close) = closing_bracket(ch) {
+            if let Some(len) = chars[i + 1..].iter().position(|&c| c == close) {
+                let content: String = chars[i + 1..i + 1 + len].iter().collect();
+                if MARKERS.contains(&content.as_str()) {
+                    i += len + 2;
+                    continue;
+                }
+            }
+        }
+        result.push(ch);
+        i += 1;
+    }
+    result
+}
+
+fn closing_bracket(ch: char) -> Option<char> {
+    match ch {
+        '[' => Some(']'),
+        '(' => Some(')'),
+        '【' => Some('】'),
+        '〔' => Some('〕'),
+        _ => None,
+    }
+}
+
+// Returns the byte position of the first episode marker and the episode
+// number.
+//
+// Supported markers: #12, 第12話, 第12回, 第十二話, Ep.12
+fn find_episode(text: &str) -> Option<(usize, u32)> {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    for (i, &(pos, ch)) in chars.iter().enumerate() {
+        let rest = &chars[i + 1..];
+        let episode = match ch {
+            '#' => parse_digits(rest).map(|(n, _)| n),
+            '第' => parse_digits(rest)
+                .or_else(|| parse_kanji_numerals(rest))
+                .filter(|&(_, len)| {
+                    rest.get(len).map_or(false, |&(_, c)| c == '話' || c == '回')
+                })
+                .map(|(n, _)| n),
+            // Ignore "ep" in a word like "Step1".
+            'E' | 'e' if i == 0 || !chars[i - 1].1.is_ascii_alphabetic() => {
+                match rest.first() {
+                    Some(&(_, 'p')) | Some(&(_, 'P')) => {
+                        let skip = match rest.get(1) {
+                            Some(&(_, '.')) => 2,
+                            _ => 1,
+                        };
+                        parse_digits(&rest[skip.min(rest.len())..])
+                            .map(|(n, _)| n)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        if let Some(episode) = episode {
+            return Some((pos, episode));
+        }
+    }
+    None
+}
+
+// Returns the number and the number of characters parsed.
+fn parse_digits(chars: &[(usize, char)]) -> Option<(u32, usize)> {
+    let digits: String = chars.iter()
+        .map(|&(_, ch)| ch)
+        .take_while(|ch| ch.is_ascii_digit())
+        .collect();
+    let n = digits.parse().ok()?;
+    Some((n, digits.len()))
+}
+
+fn parse_kanji_numerals(chars: &[(usize, char)]) -> Option<(u32, usize)> {
+    let mut total = 0;
+    let mut digit = 0;
+    let mut len = 0;
+    for &(_, ch) in chars.iter() {
+        match ch {
+            '一' => digit = 1,
+            '二' => digit = 2,
+            '三' => digit = 3,
+            '四' => digit = 4,
+            '五' => digit = 5,
+            '六' => digit = 6,
+            '七' => digit = 7,
+            '八' => digit = 8,
+            '九' => digit = 9,
+            '十' | '百' => {
+                let unit = if ch == '十' { 10 } else { 100 };
+                total += if digit == 0 { 1 } else { digit } * unit;
+                digit = 0;
+            }
+            _ => break,
+        }
+        len += 1;
+    }
+    if len == 0 {
+        return None;
+    }
+    Some((total + digit, len))
+}
+
+enum SeriesKey<'a> {
+    Title(&'a str),
+    // The series ID in the series descriptor.
+    Descriptor(u16),
+}
+
+// FNV-1a is used so that the same ID is computed regardless of the build.
+//
+// The network ID is followed by a different separator for each kind of key so
+// that a title never produces the same string as a series ID.
+fn series_id(nid: NetworkId, key: SeriesKey) -> u32 {
+    const OFFSET_BASIS: u32 = 0x811C9DC5;
+    const PRIME: u32 = 0x01000193;
+    let key = match key {
+        SeriesKey::Title(title) =>
+            format!("{}:{}", nid.value(), search_index::normalize(title)),
+        SeriesKey::Descriptor(id) => format!("{}#{}", nid.value(), id),
+    };
+    key.bytes().fold(OFFSET_BASIS, |hash, b| {
+        (hash ^ b as u32).wrapping_mul(PRIME)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::EpgSeriesInfo;
+
+    #[test]
+    fn test_parse_title() {
+        assert_eq!(parse_title("タイトル"), ("タイトル".to_string(), None));
+        assert_eq!(parse_title("[新]タイトル #12"),
+                   ("タイトル".to_string(), Some(12)));
+        assert_eq!(parse_title("タイトル　＃１２「サブタイトル」"),
+                   ("タイトル".to_string(), Some(12)));
+        assert_eq!(parse_title("タイトル 第12話「サブタイトル」(再)"),
+                   ("タイトル".to_string(), Some(12)));
+        assert_eq!(parse_title("【字】タイトル 第十二回"),
+                   ("タイトル".to_string(), Some(12)));
+        assert_eq!(parse_title("タイトル Ep.3"),
+                   ("タイトル".to_string(), Some(3)));
+        assert_eq!(parse_title("\u{1F21F}タイトル\u{1F211}"),
+                   ("タイトル".to_string(), None));
+        assert_eq!(parse_title("第3次産業"), ("第3次産業".to_string(), None));
+        assert_eq!(parse_title("Step1"), ("Step1".to_string(), None));
+        assert_eq!(parse_title("[映画]タイトル"),
+                   ("[映画]タイトル".to_string(), None));
+        assert_eq!(parse_title("#1"), ("#1".to_string(), Some(1)));
+    }
+
+    #[test]
+    fn test_parse_kanji_numerals() {
+        fn parse(s: &str) -> Option<(u32, usize)> {
+            let chars: Vec<(usize, char)> = s.char_indices().collect();
+            parse_kanji_numerals(&chars)
+        }
+        assert_eq!(parse("一"), Some((1, 1)));
+        assert_eq!(parse("十"), Some((10, 1)));
+        assert_eq!(parse("十二"), Some((12, 2)));
+        assert_eq!(parse("二十"), Some((20, 2)));
+        assert_eq!(parse("百二十三話"), Some((123, 4)));
+        assert_eq!(parse("話"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let mut program1 = EpgProgram::new((1, 2, 3, 4).into());
+        program1.name = Some("[新]タイトル #1".to_string());
+        let series1 = SeriesInfo::detect(&program1).unwrap();
+        assert_eq!(series1.title, "タイトル");
+        assert_eq!(series1.episode, Some(1));
+
+        let mut program2 = EpgProgram::new((1, 2, 5, 6).into());
+        program2.name = Some("ﾀｲﾄﾙ　第２話".to_string());
+        let series2 = SeriesInfo::detect(&program2).unwrap();
+        assert_eq!(series2.episode, Some(2));
+        assert_eq!(series1.id, series2.id);
+
+        let mut program3 = EpgProgram::new((7, 2, 3, 4).into());
+        program3.name = Some("タイトル".to_string());
+        let series3 = SeriesInfo::detect(&program3).unwrap();
+        assert_ne!(series1.id, series3.id);
+
+        let program4 = EpgProgram::new((1, 2, 3, 4).into());
+        assert!(SeriesInfo::detect(&program4).is_none());
+
+        let mut program5 = EpgProgram::new((1, 2, 3, 4).into());
+        program5.name = Some("タイトル #2".to_string());
+        program5.series = Some(EpgSeriesInfo {
+            id: 10,
+            repeat: 0,
+            pattern: 0,
+            expires_at: None,
+            episode: 3,
+            last_episode: 0,
+            name: Some("シリーズ".to_string()),
+        });
+        let series5 = SeriesInfo::detect(&program5).unwrap();
+        assert_eq!(series5.title, "シリーズ");
+        assert_eq!(series5.episode, Some(3));
+        assert_ne!(series5.id, series1.id);
+
+        program5.series.as_mut().unwrap().name = None;
+        program5.series.as_mut().unwrap().episode = 0;
+        let series6 = SeriesInfo::detect(&program5).unwrap();
+        assert_eq!(series6.title, "タイトル");
+        assert_eq!(series6.episode, Some(2));
+        assert_eq!(series6.id, series5.id);
+
+        // A title never collides with a series ID.
+        let mut program7 = EpgProgram::new((1, 2, 3, 4).into());
+        program7.name = Some("#10".to_string());
+        let series7 = SeriesInfo::detect(&program7).unwrap();
+        assert_ne!(series7.id, series5.id);
+    }
+}
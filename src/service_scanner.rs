@@ -9,29 +9,28 @@ use tokio::io::AsyncReadExt;
 #[cfg(test)]
 use serde::Serialize;
 
-use crate::command_util;
+use crate::channel_job::*;
 use crate::epg::*;
 use crate::models::*;
 use crate::tuner::*;
 
 pub struct ServiceScanner {
-    command: String,
-    channels: Vec<EpgChannel>,
-    stream_manager: Recipient<StartStreamingMessage>,
+    runner: ChannelJobRunner,
 }
 
-// TODO: The following implementation has code clones similar to
-//       ClockSynchronizer and EitCollector.
-
 impl ServiceScanner {
-    const LABEL: &'static str = "service-scanner";
+    pub const LABEL: &'static str = "service-scanner";
 
     pub fn new(
         command: String,
         channels: Vec<EpgChannel>,
         stream_manager: Recipient<StartStreamingMessage>,
+        options: ChannelJobOptions,
     ) -> Self {
-        ServiceScanner { command, channels, stream_manager }
+        ServiceScanner {
+            runner: ChannelJobRunner::new(
+                Self::LABEL, command, channels, stream_manager, options),
+        }
     }
 
     pub async fn scan_services(
@@ -39,73 +38,43 @@ impl ServiceScanner {
     ) -> Vec<(EpgChannel, Option<IndexMap<ServiceTriple, EpgService>>)> {
         log::debug!("Scanning services...");
 
-        let mut results = Vec::new();
-        for channel in self.channels.iter() {
-            let result = match Self::scan_services_in_channel(
-                &channel, &self.command, &self.stream_manager).await {
-                Ok(services) => {
+        let results = self.scan_services_with_errors().await;
+
+        log::debug!("Scanned {} channels", results.len());
+
+        results
+            .into_iter()
+            .map(|result| {
+                let (channel, services) = result.ok();
+                let services = services.map(|services| {
                     let mut map = IndexMap::new();
                     for service in services.into_iter() {
-                        map.insert(service.triple(), service.clone());
+                        map.insert(service.triple(), service);
                     }
-                    Some(map)
-                }
-                Err(err) => {
-                    log::warn!("Failed to scan services in {}: {}",
-                               channel.name, err);
-                    None
-                }
-            };
-            results.push((channel.clone(), result));
-        }
-
-        log::debug!("Scanned {} channels", self.channels.len());
+                    map
+                });
+                (channel, services)
+            })
+            .collect()
+    }
 
-        results
+    // Same as `scan_services()` except that the error is kept for each channel
+    // which failed to scan.
+    pub async fn scan_services_with_errors(
+        self
+    ) -> Vec<ChannelJobResult<Vec<EpgService>>> {
+        self.runner.run(Self::scan_services_in_channel).await
     }
 
     async fn scan_services_in_channel(
-        channel: &EpgChannel,
-        command: &str,
-        stream_manager: &Recipient<StartStreamingMessage>,
+        channel: EpgChannel,
+        mut output: ChannelJobOutput,
     ) -> Result<Vec<EpgService>, Error> {
         log::debug!("Scanning services in {}...", channel.name);
 
-        let user = TunerUser {
-            info: TunerUserInfo::Job { name: Self::LABEL.to_string() },
-            priority: (-1).into(),
-        };
-
-        let stream = stream_manager.send(StartStreamingMessage {
-            channel: channel.clone(),
-            user
-        }).await??;
-
-        let template = mustache::compile_str(command)?;
-        let data = mustache::MapBuilder::new()
-            .insert("sids", &channel.services)?
-            .insert("xsids", &channel.excluded_services)?
-            .build();
-        let cmd = template.render_data_to_string(&data)?;
-
-        let mut pipeline = command_util::spawn_pipeline(
-            vec![cmd], stream.id())?;
-
-        let (input, mut output) = pipeline.take_endpoints().unwrap();
-
-        let handle = tokio::spawn(stream.pipe(input));
-
         let mut buf = Vec::new();
         output.read_to_end(&mut buf).await?;
 
-        // Explicitly dropping the output of the pipeline is needed.  The output
-        // holds the child processes and it kills them when dropped.
-        drop(pipeline);
-
-        // Wait for the task so that the tuner is released before a request for
-        // streaming in the next iteration.
-        let _ = handle.await;
-
         failure::ensure!(buf.len() > 0, "No service, maybe out of service");
 
         let services: Vec<TsService> = serde_json::from_slice(&buf)?;
@@ -113,7 +82,7 @@ impl ServiceScanner {
 
         Ok(services
            .into_iter()
-           .map(|sv| EpgService::from((channel, &sv)))
+           .map(|sv| EpgService::from((&channel, &sv)))
            .collect())
     }
 }
@@ -145,6 +114,7 @@ impl From<(&EpgChannel, &TsService)> for EpgService {
             remote_control_key_id: sv.remote_control_key_id,
             name: sv.name.clone(),
             channel: ch.clone(),
+            has_logo_data: false,
         }
     }
 }
@@ -195,7 +165,8 @@ mod tests {
         let cmd = format!(
             "echo '{}'", serde_json::to_string(&expected).unwrap());
         let scan = ServiceScanner::new(
-            cmd, channels.clone(), mock.clone().recipient());
+            cmd, channels.clone(), mock.clone().recipient(),
+            Default::default());
         let results = scan.scan_services().await;
         assert!(results[0].1.is_some());
         assert_eq!(results[0].1.as_ref().unwrap().len(), 1);
@@ -203,7 +174,8 @@ mod tests {
         // Emulate out of services by using `false`
         let cmd = "false".to_string();
         let scan = ServiceScanner::new(
-            cmd, channels.clone(), mock.clone().recipient());
+            cmd, channels.clone(), mock.clone().recipient(),
+            Default::default());
         let results = scan.scan_services().await;
         assert!(results[0].1.is_none());
     }
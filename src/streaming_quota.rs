@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use chrono::{FixedOffset, NaiveDate};
+use futures::stream::Stream;
+
+use crate::config::{QuotaConfig, QuotasConfig};
+use crate::datetime_ext::*;
+use crate::error::Error;
+
+// The remaining streaming time of a stream is checked at this interval.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// A key identifying a client for per-client quotas.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum QuotaKey {
+    Token(String),
+    Addr(IpAddr),
+    // UNIX domain socket
+    Local,
+}
+
+impl fmt::Display for QuotaKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // Never show the token itself.
+            QuotaKey::Token(_) => write!(f, "token"),
+            QuotaKey::Addr(addr) => write!(f, "{}", addr),
+            QuotaKey::Local => write!(f, "local"),
+        }
+    }
+}
+
+// Limits the number of streams and the streaming time.
+//
+// Counters are reset at midnight.  The streaming time of streams in progress
+// is taken into account when a new stream starts and while streaming.
+pub struct StreamingQuotas {
+    config: QuotasConfig,
+    // Day boundaries depend on the time zone of broadcasts.
+    offset: FixedOffset,
+    state: Mutex<QuotaState>,
+}
+
+struct QuotaState {
+    date: NaiveDate,
+    next_id: u64,
+    global: QuotaUsage,
+    clients: HashMap<QuotaKey, QuotaUsage>,
+}
+
+#[derive(Default)]
+struct QuotaUsage {
+    // Start times of streams in progress.
+    active: HashMap<u64, Instant>,
+    num_streams: usize,
+    // The streaming time of ended streams.
+    time: Duration,
+}
+
+impl StreamingQuotas {
+    pub fn new(config: QuotasConfig, offset: FixedOffset) -> Self {
+        StreamingQuotas {
+            config,
+            offset,
+            state: Mutex::new(QuotaState {
+                date: Jst::now().with_timezone(&offset).date().naive_local(),
+                next_id: 0,
+                global: Default::default(),
+                clients: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn acquire(
+        self: &Arc<Self>,
+        key: QuotaKey,
+    ) -> Result<QuotaPermit, Error> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reset_if_needed(&self.offset, now);
+
+        state.global.check(&self.config.global, now)?;
+        match state.clients.get(&key) {
+            Some(usage) => usage.check(&self.config.per_client, now)?,
+            None => QuotaUsage::default()
+                .check(&self.config.per_client, now)?,
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.global.start(id, now);
+        state.clients.entry(key.clone()).or_default().start(id, now);
+
+        log::debug!("Acquired a streaming quota permit#{} for {}", id, key);
+        Ok(QuotaPermit {
+            quotas: self.clone(),
+            id,
+            key,
+            committed: false,
+            next_check: now,
+        })
+    }
+
+    // Returns the streaming time which a stream can use from now.
+    //
+    // The remaining time is computed from the current usage including other
+    // streams in progress, so that it decreases faster while other streams are
+    // running, and is restored at midnight.
+    fn remaining_time(&self, key: &QuotaKey) -> Option<Duration> {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reset_if_needed(&self.offset, now);
+        let global = state.global.remaining_time(&self.config.global, now);
+        let client = state.clients.get(key)
+            .and_then(|usage| {
+                usage.remaining_time(&self.config.per_client, now)
+            });
+        match (global, client) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (Some(a), None) | (None, Some(a)) => Some(a),
+            (None, None) => None,
+        }
+    }
+
+    fn release(&self, id: u64, key: &QuotaKey, committed: bool) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        state.reset_if_needed(&self.offset, now);
+        state.global.end(id, now, committed);
+        if let Some(usage) = state.clients.get_mut(key) {
+            usage.end(id, now, committed);
+        }
+        log::debug!("Released the streaming quota permit#{} for {}", id, key);
+    }
+}
+
+impl QuotaState {
+    fn reset_if_needed(&mut self, offset: &FixedOffset, now: Instant) {
+        let jst_now = Jst::now();
+        let today = jst_now.with_timezone(offset).date().naive_local();
+        if self.date == today {
+            return;
+        }
+        let since_midnight = (jst_now - midnight_in(offset, jst_now))
+            .to_std()
+            .unwrap_or_default();
+        let midnight = now.checked_sub(since_midnight).unwrap_or(now);
+        self.date = today;
+        self.global.reset(midnight);
+        for usage in self.clients.values_mut() {
+            usage.reset(midnight);
+        }
+        self.clients.retain(|_, usage| !usage.active.is_empty());
+    }
+}
+
+impl QuotaUsage {
+    // Returns the remaining streaming time if the time is limited.
+    fn check(
+        &self,
+        config: &QuotaConfig,
+        now: Instant,
+    ) -> Result<(), Error> {
+        if let Some(max) = config.max_concurrent_streams {
+            if self.active.len() >= max {
+                return Err(Error::StreamingQuotaExceeded);
+            }
+        }
+        if let Some(max) = config.max_daily_streams {
+            if self.num_streams >= max {
+                return Err(Error::StreamingQuotaExceeded);
+            }
+        }
+        if self.remaining_time(config, now) == Some(Duration::default()) {
+            return Err(Error::StreamingQuotaExceeded);
+        }
+        Ok(())
+    }
+
+    // Returns the remaining streaming time if the time is limited.
+    fn remaining_time(
+        &self,
+        config: &QuotaConfig,
+        now: Instant,
+    ) -> Option<Duration> {
+        let max = Duration::from_millis(config.max_daily_streaming_time?);
+        let used = self.active.values()
+            .fold(self.time, |acc, start| {
+                acc + now.saturating_duration_since(*start)
+            });
+        Some(max.checked_sub(used).unwrap_or_default())
+    }
+
+    fn start(&mut self, id: u64, now: Instant) {
+        self.active.insert(id, now);
+        self.num_streams += 1;
+    }
+
+    // A stream which has not been committed is not counted.
+    fn end(&mut self, id: u64, now: Instant, committed: bool) {
+        if let Some(start) = self.active.remove(&id) {
+            if committed {
+                self.time += now.saturating_duration_since(start);
+            } else {
+                self.num_streams = self.num_streams.saturating_sub(1);
+            }
+        }
+    }
+
+    fn reset(&mut self, midnight: Instant) {
+        for start in self.active.values_mut() {
+            if *start < midnight {
+                *start = midnight;
+            }
+        }
+        self.num_streams = self.active.len();
+        self.time = Duration::default();
+    }
+}
+
+// Released when the stream ends.
+//
+// A permit has to be committed once the stream has started.  The permit is
+// rolled back if it's dropped before committed, e.g. when no tuner is
+// available.
+pub struct QuotaPermit {
+    quotas: Arc<StreamingQuotas>,
+    id: u64,
+    key: QuotaKey,
+    committed: bool,
+    next_check: Instant,
+}
+
+impl QuotaPermit {
+    pub fn commit(&mut self) {
+        self.committed = true;
+    }
+
+    // Returns true if the streaming time quota has been exhausted.
+    fn is_exhausted(&mut self) -> bool {
+        let now = Instant::now();
+        if now < self.next_check {
+            return false;
+        }
+        match self.quotas.remaining_time(&self.key) {
+            Some(remaining) if remaining == Duration::default() => true,
+            Some(remaining) => {
+                self.next_check = now + remaining.min(CHECK_INTERVAL);
+                false
+            }
+            None => {
+                self.next_check = now + CHECK_INTERVAL;
+                false
+            }
+        }
+    }
+}
+
+impl Drop for QuotaPermit {
+    fn drop(&mut self) {
+        self.quotas.release(self.id, &self.key, self.committed);
+    }
+}
+
+// Ends the inner stream when the streaming time budget runs out.
+pub struct QuotaLimitedStream<S> {
+    inner: S,
+    permit: Option<QuotaPermit>,
+}
+
+impl<S> QuotaLimitedStream<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin
+{
+    pub fn new(inner: S, permit: Option<QuotaPermit>) -> Self {
+        QuotaLimitedStream { inner, permit }
+    }
+}
+
+impl<S> Stream for QuotaLimitedStream<S>
+where
+    S: Stream<Item = io::Result<Bytes>> + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let exhausted = self.permit.as_mut()
+            .map_or(false, |permit| permit.is_exhausted());
+        if exhausted {
+            if let Some(permit) = self.permit.take() {
+                log::info!("Streaming time quota for {} exhausted",
+                           permit.key);
+            }
+            return std::task::Poll::Ready(None);
+        }
+        Pin::new(&mut self.inner).poll_next(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+
+    fn quotas(global: QuotaConfig, per_client: QuotaConfig)
+              -> Arc<StreamingQuotas> {
+        Arc::new(StreamingQuotas::new(
+            QuotasConfig { global, per_client },
+            FixedOffset::east(Jst::OFFSET)))
+    }
+
+    #[test]
+    fn test_max_concurrent_streams() {
+        let quotas = quotas(Default::default(), QuotaConfig {
+            max_concurrent_streams: Some(1),
+            ..Default::default()
+        });
+        let addr1 = QuotaKey::Addr("192.168.0.1".parse().unwrap());
+        let addr2 = QuotaKey::Addr("192.168.0.2".parse().unwrap());
+
+        let permit = quotas.acquire(addr1.clone()).unwrap();
+        assert_matches!(quotas.acquire(addr1.clone()).err(),
+                        Some(Error::StreamingQuotaExceeded));
+        assert!(quotas.acquire(addr2.clone()).is_ok());
+
+        drop(permit);
+        assert!(quotas.acquire(addr1.clone()).is_ok());
+    }
+
+    #[test]
+    fn test_max_daily_streams() {
+        let quotas = quotas(QuotaConfig {
+            max_daily_streams: Some(2),
+            ..Default::default()
+        }, Default::default());
+
+        let mut permit1 = quotas.acquire(QuotaKey::Local).unwrap();
+        permit1.commit();
+        let mut permit2 =
+            quotas.acquire(QuotaKey::Token("a".to_string())).unwrap();
+        permit2.commit();
+        assert_matches!(quotas.acquire(QuotaKey::Local).err(),
+                        Some(Error::StreamingQuotaExceeded));
+    }
+
+    #[test]
+    fn test_max_daily_streaming_time() {
+        let quotas = quotas(Default::default(), QuotaConfig {
+            max_daily_streaming_time: Some(60_000),
+            ..Default::default()
+        });
+
+        let permit = quotas.acquire(QuotaKey::Local).unwrap();
+        assert!(quotas.remaining_time(&QuotaKey::Local).is_some());
+        drop(permit);
+
+        quotas.state.lock().unwrap().clients
+            .get_mut(&QuotaKey::Local).unwrap()
+            .time = Duration::from_secs(60);
+        assert_matches!(quotas.acquire(QuotaKey::Local).err(),
+                        Some(Error::StreamingQuotaExceeded));
+
+        assert!(quotas.acquire(QuotaKey::Token("a".to_string())).is_ok());
+    }
+
+    #[test]
+    fn test_streaming_time_shared_by_streams() {
+        let quotas = quotas(QuotaConfig {
+            max_daily_streaming_time: Some(60_000),
+            ..Default::default()
+        }, Default::default());
+
+        let mut permit = quotas.acquire(QuotaKey::Local).unwrap();
+        permit.commit();
+        assert!(!permit.is_exhausted());
+
+        // Other streams consumed the budget while streaming.
+        quotas.state.lock().unwrap().global.time = Duration::from_secs(60);
+        permit.next_check = Instant::now();
+        assert!(permit.is_exhausted());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let quotas = quotas(QuotaConfig {
+            max_daily_streams: Some(1),
+            ..Default::default()
+        }, Default::default());
+
+        // The tuner was not granted.
+        let permit = quotas.acquire(QuotaKey::Local).unwrap();
+        drop(permit);
+
+        let mut permit = quotas.acquire(QuotaKey::Local).unwrap();
+        permit.commit();
+        drop(permit);
+
+        assert_matches!(quotas.acquire(QuotaKey::Local).err(),
+                        Some(Error::StreamingQuotaExceeded));
+    }
+
+    #[test]
+    fn test_no_limits() {
+        let quotas = quotas(Default::default(), Default::default());
+        let mut permit = quotas.acquire(QuotaKey::Local).unwrap();
+        assert!(quotas.remaining_time(&QuotaKey::Local).is_none());
+        assert!(!permit.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn test_quota_limited_stream() {
+        use futures::stream::StreamExt;
+
+        let quotas = quotas(QuotaConfig {
+            max_daily_streaming_time: Some(60_000),
+            ..Default::default()
+        }, Default::default());
+        let mut permit = quotas.acquire(QuotaKey::Local).unwrap();
+        permit.commit();
+        quotas.state.lock().unwrap().global.time = Duration::from_secs(60);
+
+        let inner = futures::stream::iter(vec![Ok(Bytes::from("data"))]);
+        let mut stream = QuotaLimitedStream::new(inner, Some(permit));
+        assert!(stream.next().await.is_none());
+        assert_eq!(quotas.state.lock().unwrap().global.active.len(), 0);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::{Arc, RwLock};
+
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::error::Error;
+
+// Resolves a certificate which can be reloaded at runtime.
+//
+// Connections established before reloading continue to use the old
+// certificate.
+pub struct ReloadableCertResolver {
+    cert_path: String,
+    key_path: String,
+    certified_key: RwLock<CertifiedKey>,
+}
+
+impl ReloadableCertResolver {
+    pub fn new(cert_path: &str, key_path: &str) -> Result<Self, Error> {
+        let certified_key = load_certified_key(cert_path, key_path)?;
+        Ok(ReloadableCertResolver {
+            cert_path: cert_path.to_string(),
+            key_path: key_path.to_string(),
+            certified_key: RwLock::new(certified_key),
+        })
+    }
+
+    pub fn reload(&self) -> Result<(), Error> {
+        let certified_key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.certified_key.write().unwrap() = certified_key;
+        log::info!("Reloaded {}", self.cert_path);
+        Ok(())
+    }
+}
+
+impl rustls::ResolvesServerCert for ReloadableCertResolver {
+    fn resolve(&self, _: rustls::ClientHello) -> Option<CertifiedKey> {
+        Some(self.certified_key.read().unwrap().clone())
+    }
+}
+
+pub fn create_server_config(
+    resolver: Arc<ReloadableCertResolver>,
+) -> rustls::ServerConfig {
+    let mut config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    config.cert_resolver = resolver;
+    config
+}
+
+// Reloads certificates every time SIGHUP is received.
+//
+// The old certificate continues to be used if reloading fails, so that a
+// temporarily broken file doesn't stop the server.
+pub async fn reload_on_sighup(resolvers: Vec<Arc<ReloadableCertResolver>>) {
+    let mut stream = match signal(SignalKind::hangup()) {
+        Ok(stream) => stream,
+        Err(err) => {
+            log::error!("Failed to listen SIGHUP: {}", err);
+            return;
+        }
+    };
+    while let Some(_) = stream.recv().await {
+        log::info!("SIGHUP received, reloading certificates...");
+        for resolver in resolvers.iter() {
+            if let Err(err) = resolver.reload() {
+                log::error!("Failed to reload {}: {}",
+                            resolver.cert_path, err);
+            }
+        }
+    }
+}
+
+fn load_certified_key(
+    cert_path: &str,
+    key_path: &str,
+) -> Result<CertifiedKey, Error> {
+    let mut reader = BufReader::new(File::open(cert_path)?);
+    let certs = pemfile::certs(&mut reader)
+        .map_err(|_| Error::TlsError(
+            format!("Invalid certificate file: {}", cert_path)))?;
+    if certs.is_empty() {
+        return Err(Error::TlsError(
+            format!("No certificate found: {}", cert_path)));
+    }
+
+    // Try PKCS#8 first, and then PKCS#1.
+    let mut reader = BufReader::new(File::open(key_path)?);
+    let mut keys = pemfile::pkcs8_private_keys(&mut reader)
+        .map_err(|_| Error::TlsError(
+            format!("Invalid private key file: {}", key_path)))?;
+    if keys.is_empty() {
+        let mut reader = BufReader::new(File::open(key_path)?);
+        keys = pemfile::rsa_private_keys(&mut reader)
+            .map_err(|_| Error::TlsError(
+                format!("Invalid private key file: {}", key_path)))?;
+    }
+    let key = match keys.into_iter().next() {
+        Some(key) => key,
+        None => return Err(Error::TlsError(
+            format!("No private key found: {}", key_path))),
+    };
+
+    let signing_key = sign::any_supported_type(&key)
+        .map_err(|_| Error::TlsError(
+            format!("Unsupported private key: {}", key_path)))?;
+
+    Ok(CertifiedKey::new(certs, Arc::new(signing_key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::*;
+
+    #[test]
+    fn test_load_certified_key() {
+        assert_matches!(
+            load_certified_key("/path/to/not-found", "/path/to/not-found")
+                .err(),
+            Some(Error::IoError(_)));
+
+        // An empty file contains no certificate.
+        assert_matches!(
+            load_certified_key("/dev/null", "/dev/null").err(),
+            Some(Error::TlsError(_)));
+    }
+}
@@ -1,14 +1,19 @@
 use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use actix::prelude::*;
+use chrono::DateTime;
 use log;
 use mustache;
+use serde::{Serialize, Serializer};
 
 use crate::broadcaster::*;
 use crate::command_util::{spawn_pipeline, CommandPipeline};
 use crate::config::{Config, TunerConfig};
+use crate::datetime_ext::{serde_jst, Jst};
 use crate::epg::EpgChannel;
 use crate::error::Error;
 use crate::models::*;
@@ -20,7 +25,7 @@ pub fn start(config: Arc<Config>) -> Addr<TunerManager> {
 
 // identifiers
 
-#[derive(Clone, Copy, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct TunerSessionId {
     tuner_index: usize,
 }
@@ -31,7 +36,7 @@ impl fmt::Display for TunerSessionId {
     }
 }
 
-#[derive(Clone, Copy, Default, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct TunerSubscriptionId {
     session_id: TunerSessionId,
     serial_number: u32,
@@ -49,6 +54,34 @@ impl fmt::Display for TunerSubscriptionId {
     }
 }
 
+// The Web API uses "<tuner-index>.<serial-number>" because '#' cannot be used
+// in a URL path.
+impl FromStr for TunerSubscriptionId {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '.');
+        let tuner_index = parts.next()
+            .and_then(|index| index.parse().ok())
+            .ok_or(Error::StreamNotFound)?;
+        let serial_number = parts.next()
+            .and_then(|serial_number| serial_number.parse().ok())
+            .ok_or(Error::StreamNotFound)?;
+        Ok(TunerSubscriptionId::new(
+            TunerSessionId { tuner_index }, serial_number))
+    }
+}
+
+impl Serialize for TunerSubscriptionId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!(
+            "{}.{}", self.session_id.tuner_index, self.serial_number))
+    }
+}
+
 // tuner manager
 
 pub struct TunerManager {
@@ -59,6 +92,7 @@ pub struct TunerManager {
 struct TunerSubscription {
     id: TunerSubscriptionId,
     broadcaster: Addr<Broadcaster>,
+    bytes_sent: Arc<AtomicU64>,
 }
 
 impl TunerManager {
@@ -83,11 +117,12 @@ impl TunerManager {
         &mut self,
         channel: EpgChannel,
         user: TunerUser,
+        filters: Vec<String>,
     ) -> Result<TunerSubscription, Error> {
         if let TunerUserInfo::Tracker { stream_id } = user.info {
             let tuner = &mut self.tuners[stream_id.session_id.tuner_index];
             if tuner.is_active() {
-                return Ok(tuner.subscribe(user));
+                return Ok(tuner.subscribe(user, filters));
             }
             return Err(Error::TunerUnavailable);
         }
@@ -98,7 +133,7 @@ impl TunerManager {
         if let Some(tuner) = found {
             log::info!("tuner#{}: Reuse tuner already activated for {}",
                        tuner.index, channel);
-            return Ok(tuner.subscribe(user));
+            return Ok(tuner.subscribe(user, filters));
         }
 
         let found = self.tuners
@@ -106,10 +141,10 @@ impl TunerManager {
             .position(|tuner| tuner.is_available_for(&channel));
         if let Some(index) = found {
             log::info!("tuner#{}: Activate for {}", index, channel);
-            let filters = self.make_filter_commands(index, &channel)?;
+            let tuner_filters = self.make_filter_commands(index, &channel)?;
             let tuner = &mut self.tuners[index];
-            tuner.activate(channel, filters)?;
-            return Ok(tuner.subscribe(user));
+            tuner.activate(channel, tuner_filters)?;
+            return Ok(tuner.subscribe(user, filters));
         }
 
         // No available tuner at this point.  Take over the right to use
@@ -121,11 +156,11 @@ impl TunerManager {
         if let Some(index) = found {
             log::info!("tuner#{}: Grab tuner, rectivate for {}",
                        index, channel);
-            let filters = self.make_filter_commands(index, &channel)?;
+            let tuner_filters = self.make_filter_commands(index, &channel)?;
             let tuner = &mut self.tuners[index];
             tuner.deactivate();
-            tuner.activate(channel, filters)?;
-            return Ok(tuner.subscribe(user));
+            tuner.activate(channel, tuner_filters)?;
+            return Ok(tuner.subscribe(user, filters));
         }
 
         log::warn!("No tuner available for {} {}", channel, user);
@@ -139,7 +174,10 @@ impl TunerManager {
 
     fn stop_streaming(&mut self, id: TunerSubscriptionId) {
         log::info!("{}: Stop streaming", id);
-        let _ = self.tuners[id.session_id.tuner_index].stop_streaming(id);
+        // The ID may come from the Web API.
+        if let Some(tuner) = self.tuners.get_mut(id.session_id.tuner_index) {
+            let _ = tuner.stop_streaming(id);
+        }
     }
 
     fn make_filter_commands(
@@ -221,11 +259,97 @@ impl Handler<QueryTunersMessage> for TunerManager {
     }
 }
 
+// query streams
+
+pub struct QueryStreamsMessage;
+
+impl fmt::Display for QueryStreamsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "QueryStreams")
+    }
+}
+
+impl Message for QueryStreamsMessage {
+    type Result = Result<Vec<StreamInfo>, Error>;
+}
+
+impl Handler<QueryStreamsMessage> for TunerManager {
+    type Result = Result<Vec<StreamInfo>, Error>;
+
+    fn handle(
+        &mut self,
+        msg: QueryStreamsMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        let streams: Vec<StreamInfo> = self.tuners
+            .iter()
+            .flat_map(|tuner| tuner.get_streams())
+            .collect();
+        Ok(streams)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamInfo {
+    pub id: TunerSubscriptionId,
+    pub tuner_index: usize,
+    pub channel_type: ChannelType,
+    pub channel: String,
+    pub user: TunerUserInfo,
+    pub priority: TunerUserPriority,
+    #[serde(with = "serde_jst")]
+    pub started_at: DateTime<Jst>,
+    pub bytes_sent: u64,
+    pub filters: Vec<String>,
+}
+
+// deactivate tuner
+
+pub struct DeactivateTunerMessage {
+    pub index: usize,
+}
+
+impl fmt::Display for DeactivateTunerMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DeactivateTuner tuner#{}", self.index)
+    }
+}
+
+impl Message for DeactivateTunerMessage {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeactivateTunerMessage> for TunerManager {
+    type Result = Result<(), Error>;
+
+    fn handle(
+        &mut self,
+        msg: DeactivateTunerMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        match self.tuners.get_mut(msg.index) {
+            Some(tuner) => {
+                // Subscribers stop when the broadcaster stops after the tuner
+                // command is terminated.
+                log::info!("tuner#{}: Deactivate", msg.index);
+                tuner.deactivate();
+                Ok(())
+            }
+            None => Err(Error::TunerNotFound),
+        }
+    }
+}
+
 // start streaming
 
 pub struct StartStreamingMessage {
     pub channel: EpgChannel,
     pub user: TunerUser,
+    // Commands processing the stream, shown in `/api/streams`.
+    pub filters: Vec<String>,
 }
 
 impl fmt::Display for StartStreamingMessage {
@@ -248,14 +372,16 @@ impl Handler<StartStreamingMessage> for TunerManager {
     ) -> Self::Result {
         log::debug!("{}", msg);
 
-        let subscription = match self.activate_tuner(msg.channel, msg.user) {
+        let subscription = match self.activate_tuner(
+            msg.channel, msg.user, msg.filters) {
             Ok(broadcaster) => broadcaster,
             Err(err) => return ActorResponse::reply(Err(Error::from(err))),
         };
 
         let fut = actix::fut::wrap_future::<_, Self>(
             subscription.broadcaster.send(SubscribeMessage {
-                id: subscription.id
+                id: subscription.id,
+                bytes_sent: subscription.bytes_sent.clone(),
             }))
             .map(move |result, act, ctx| {
                 if result.is_ok() {
@@ -306,6 +432,45 @@ impl Handler<StopStreamingMessage> for TunerManager {
     }
 }
 
+// kill stream
+
+// Stops a stream specified by the Web API.  Unlike `StopStreamingMessage`,
+// `Error::StreamNotFound` is returned if the stream doesn't exist.
+pub struct KillStreamMessage {
+    pub id: TunerSubscriptionId,
+}
+
+impl fmt::Display for KillStreamMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "KillStream {}", self.id)
+    }
+}
+
+impl Message for KillStreamMessage {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<KillStreamMessage> for TunerManager {
+    type Result = Result<(), Error>;
+
+    fn handle(
+        &mut self,
+        msg: KillStreamMessage,
+        _: &mut Self::Context,
+    ) -> Self::Result {
+        log::debug!("{}", msg);
+        let exists = self.tuners.get(msg.id.session_id.tuner_index)
+            .map_or(false, |tuner| {
+                tuner.get_streams().iter().any(|stream| stream.id == msg.id)
+            });
+        if !exists {
+            return Err(Error::StreamNotFound);
+        }
+        self.stop_streaming(msg.id);
+        Ok(())
+    }
+}
+
 // tuner
 
 struct Tuner {
@@ -315,6 +480,8 @@ struct Tuner {
     command: String,
     time_limit: u64,
     activity: TunerActivity,
+    // True if the last session ended because of the no-packet timeout.
+    last_session_timed_out: bool,
 }
 
 impl Tuner {
@@ -329,6 +496,7 @@ impl Tuner {
             command: config.command.clone(),
             time_limit: config.time_limit,
             activity: TunerActivity::Inactive,
+            last_session_timed_out: false,
         }
     }
 
@@ -367,11 +535,26 @@ impl Tuner {
     }
 
     fn deactivate(&mut self) {
+        if self.is_active() {
+            self.last_session_timed_out = self.activity.is_timed_out();
+        }
         self.activity.deactivate();
     }
 
-    fn subscribe(&mut self, user: TunerUser) -> TunerSubscription {
-        self.activity.subscribe(user)
+    fn is_fault(&self) -> bool {
+        if self.is_active() {
+            self.activity.is_timed_out()
+        } else {
+            self.last_session_timed_out
+        }
+    }
+
+    fn subscribe(
+        &mut self,
+        user: TunerUser,
+        filters: Vec<String>,
+    ) -> TunerSubscription {
+        self.activity.subscribe(user, filters)
     }
 
     fn stop_streaming(
@@ -386,7 +569,7 @@ impl Tuner {
     }
 
     fn get_model(&self) -> MirakurunTuner {
-        let (command, pid, users) = self.activity.get_models();
+        let (command, pid, users, session) = self.activity.get_models();
 
         MirakurunTuner {
             index: self.index,
@@ -399,10 +582,15 @@ impl Tuner {
             is_remote: false,
             is_free: self.is_available(),
             is_using: !self.is_available(),
-            is_fault: false,
+            is_fault: self.is_fault(),
+            session,
         }
     }
 
+    fn get_streams(&self) -> Vec<StreamInfo> {
+        self.activity.get_streams()
+    }
+
     fn make_command(&self, channel: &EpgChannel) -> Result<String, Error> {
         let template = mustache::compile_str(&self.command)?;
         let data = mustache::MapBuilder::new()
@@ -457,6 +645,13 @@ impl TunerActivity {
         !self.is_active()
     }
 
+    fn is_timed_out(&self) -> bool {
+        match self {
+            Self::Inactive => false,
+            Self::Active(session) => session.stats.is_timed_out(),
+        }
+    }
+
     fn is_reuseable(&self, channel: &EpgChannel) -> bool {
         match self {
             Self::Inactive => false,
@@ -464,10 +659,14 @@ impl TunerActivity {
         }
     }
 
-    fn subscribe(&mut self, user: TunerUser) -> TunerSubscription {
+    fn subscribe(
+        &mut self,
+        user: TunerUser,
+        filters: Vec<String>,
+    ) -> TunerSubscription {
         match self {
             Self::Inactive => panic!("Must be activated before subscribing"),
-            Self::Active(session) => session.subscribe(user),
+            Self::Active(session) => session.subscribe(user, filters),
         }
     }
 
@@ -488,14 +687,24 @@ impl TunerActivity {
         }
     }
 
-    fn get_models(
-        &self
-    ) -> (Option<String>, Option<u32>, Vec<MirakurunTunerUser>) {
+    fn get_models(&self) -> (
+        Option<String>,
+        Option<u32>,
+        Vec<MirakurunTunerUser>,
+        Option<TunerSessionInfo>,
+    ) {
         match self {
-            Self::Inactive => (None, None, Vec::new()),
+            Self::Inactive => (None, None, Vec::new(), None),
             Self::Active(session) => session.get_models(),
         }
     }
+
+    fn get_streams(&self) -> Vec<StreamInfo> {
+        match self {
+            Self::Inactive => Vec::new(),
+            Self::Active(session) => session.get_streams(),
+        }
+    }
 }
 
 // session
@@ -507,10 +716,19 @@ struct TunerSession {
     // Used for closing the tuner in order to take over the right to use it.
     pipeline: CommandPipeline<TunerSessionId>,
     broadcaster: Addr<Broadcaster>,
-    subscribers: HashMap<u32, TunerUser>,
+    stats: Arc<BroadcasterStats>,
+    started_at: DateTime<Jst>,
+    subscribers: HashMap<u32, TunerSubscriber>,
     next_serial_number: u32,
 }
 
+struct TunerSubscriber {
+    user: TunerUser,
+    filters: Vec<String>,
+    started_at: DateTime<Jst>,
+    bytes_sent: Arc<AtomicU64>,
+}
+
 impl TunerSession {
     fn new(
         tuner_index: usize,
@@ -524,14 +742,17 @@ impl TunerSession {
         let id = TunerSessionId { tuner_index };
         let mut pipeline = spawn_pipeline(commands, id)?;
         let (_, output) = pipeline.take_endpoints()?;
-        let broadcaster = Broadcaster::create(|ctx| {
-            Broadcaster::new(id.clone(), output, time_limit, ctx)
+        let stats = Arc::new(BroadcasterStats::new());
+        let broadcaster = Broadcaster::create({
+            let stats = stats.clone();
+            move |ctx| Broadcaster::new(id, output, time_limit, stats, ctx)
         });
 
         log::info!("{}: Activated with {}", id, channel);
 
         Ok(TunerSession {
-            id, channel, command, pipeline, broadcaster,
+            id, channel, command, pipeline, broadcaster, stats,
+            started_at: Jst::now(),
             subscribers: HashMap::new(), next_serial_number: 1
         })
     }
@@ -541,21 +762,33 @@ impl TunerSession {
             self.channel.channel == channel.channel
     }
 
-    fn subscribe(&mut self, user: TunerUser) -> TunerSubscription {
+    fn subscribe(
+        &mut self,
+        user: TunerUser,
+        filters: Vec<String>,
+    ) -> TunerSubscription {
         let serial_number = self.next_serial_number;
         self.next_serial_number += 1;
 
         let id = TunerSubscriptionId::new(self.id, serial_number);
         log::info!("{}: Subscribed: {}", id, user);
-        self.subscribers.insert(serial_number, user);
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        self.subscribers.insert(serial_number, TunerSubscriber {
+            user,
+            filters,
+            started_at: Jst::now(),
+            bytes_sent: bytes_sent.clone(),
+        });
 
-        TunerSubscription { id, broadcaster: self.broadcaster.clone() }
+        TunerSubscription {
+            id, broadcaster: self.broadcaster.clone(), bytes_sent,
+        }
     }
 
     fn can_grab(&self, priority: TunerUserPriority) -> bool {
         self.subscribers
             .values()
-            .all(|user| priority > user.priority)
+            .all(|subscriber| priority > subscriber.user.priority)
     }
 
     fn stop_streaming(
@@ -568,22 +801,58 @@ impl TunerSession {
             return Err(Error::SessionNotFound);
         }
         match self.subscribers.remove(&id.serial_number) {
-            Some(user) => log::info!("{}: Unsubscribed: {}", id, user),
+            Some(subscriber) =>
+                log::info!("{}: Unsubscribed: {}", id, subscriber.user),
             None => log::warn!("{}: Not subscribed", id),
         }
         self.broadcaster.do_send(UnsubscribeMessage { id });
         Ok(self.subscribers.len())
     }
 
-    fn get_models(
-        &self
-    ) -> (Option<String>, Option<u32>, Vec<MirakurunTunerUser>) {
+    fn get_models(&self) -> (
+        Option<String>,
+        Option<u32>,
+        Vec<MirakurunTunerUser>,
+        Option<TunerSessionInfo>,
+    ) {
+        let session = TunerSessionInfo {
+            started_at: self.started_at,
+            channel_type: self.channel.channel_type,
+            channel: self.channel.channel.clone(),
+            bytes_per_second: self.stats.bytes_per_second(),
+            dropped_chunks: self.stats.dropped_chunks(),
+            time_since_last_packet:
+                self.stats.time_since_last_packet().as_millis() as u64,
+        };
         (
             Some(self.command.clone()),
-            Some(self.pipeline.pids().as_slice().first().cloned().unwrap()),
-            self.subscribers.values().map(|user| user.get_model()).collect(),
+            // The PID of the tuner command which is the first command in the
+            // pipeline.
+            self.pipeline.pids().first().cloned(),
+            self.subscribers.values()
+                .map(|subscriber| subscriber.user.get_model())
+                .collect(),
+            Some(session),
         )
     }
+
+    fn get_streams(&self) -> Vec<StreamInfo> {
+        let mut streams: Vec<StreamInfo> = self.subscribers.iter()
+            .map(|(&serial_number, subscriber)| StreamInfo {
+                id: TunerSubscriptionId::new(self.id, serial_number),
+                tuner_index: self.id.tuner_index,
+                channel_type: self.channel.channel_type,
+                channel: self.channel.channel.clone(),
+                user: subscriber.user.info.clone(),
+                priority: subscriber.user.priority,
+                started_at: subscriber.started_at,
+                bytes_sent: subscriber.bytes_sent.load(Ordering::Relaxed),
+                filters: subscriber.filters.clone(),
+            })
+            .collect();
+        streams.sort_by_key(|stream| stream.id.serial_number);
+        streams
+    }
 }
 
 impl Drop for TunerSession {
@@ -657,7 +926,7 @@ mod tests {
         let subscription = tuner.subscribe(TunerUser {
             info: TunerUserInfo::Web { remote: None, agent: None },
             priority: 0.into(),
-        });
+        }, vec![]);
 
         let result = tuner.stop_streaming(Default::default());
         assert_matches!(result, Err(Error::SessionNotFound));
@@ -668,6 +937,86 @@ mod tests {
         tokio::task::yield_now().await;
     }
 
+    #[test]
+    fn test_subscription_id_from_str() {
+        let id: TunerSubscriptionId = "1.2".parse().unwrap();
+        assert_eq!(id, TunerSubscriptionId::new(
+            TunerSessionId { tuner_index: 1 }, 2));
+        assert_matches!("1".parse::<TunerSubscriptionId>(),
+                        Err(Error::StreamNotFound));
+        assert_matches!("tuner#1.2".parse::<TunerSubscriptionId>(),
+                        Err(Error::StreamNotFound));
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""1.2""#);
+    }
+
+    #[actix_rt::test]
+    async fn test_tuner_get_streams() {
+        let config = create_config("true".to_string());
+        let mut tuner = Tuner::new(1, &config);
+        assert!(tuner.get_streams().is_empty());
+
+        tuner.activate(create_channel("1"), vec![]).unwrap();
+        let subscription1 = tuner.subscribe(
+            create_user(0.into()), vec!["filter".to_string()]);
+        let subscription2 = tuner.subscribe(create_user(1.into()), vec![]);
+
+        let streams = tuner.get_streams();
+        assert_eq!(streams.len(), 2);
+        assert_eq!(streams[0].id, subscription1.id);
+        assert_eq!(streams[0].tuner_index, 1);
+        assert_eq!(streams[0].filters, vec!["filter".to_string()]);
+        assert_eq!(streams[1].id, subscription2.id);
+
+        subscription1.bytes_sent.fetch_add(10, Ordering::Relaxed);
+        assert_eq!(tuner.get_streams()[0].bytes_sent, 10);
+
+        tuner.stop_streaming(subscription1.id).unwrap();
+        assert_eq!(tuner.get_streams().len(), 1);
+
+        tokio::task::yield_now().await;
+    }
+
+    #[actix_rt::test]
+    async fn test_tuner_get_model() {
+        let mut config = create_config("sleep 1".to_string());
+        config.time_limit = 50;
+        let mut tuner = Tuner::new(0, &config);
+
+        let model = tuner.get_model();
+        assert!(model.pid.is_none());
+        assert!(model.session.is_none());
+        assert!(!model.is_fault);
+
+        tuner.activate(create_channel("1"), vec![]).unwrap();
+        let model = tuner.get_model();
+        assert!(model.pid.is_some());
+        assert_matches!(model.session, Some(session) => {
+            assert_eq!(session.channel, "1");
+            assert_eq!(session.bytes_per_second, 0);
+            assert_eq!(session.dropped_chunks, 0);
+        });
+        assert!(!model.is_fault);
+
+        // Wait for the no-packet timeout.
+        while !tuner.get_model().is_fault {
+            // Yield in order to process messages on the Broadcaster.
+            tokio::task::yield_now().await;
+        }
+
+        tuner.deactivate();
+        let model = tuner.get_model();
+        assert!(model.session.is_none());
+        assert!(model.is_fault);
+
+        tuner.activate(create_channel("1"), vec![]).unwrap();
+        assert!(!tuner.get_model().is_fault);
+
+        tuner.deactivate();
+        assert!(!tuner.get_model().is_fault);
+
+        tokio::task::yield_now().await;
+    }
+
     #[actix_rt::test]
     async fn test_tuner_can_grab() {
         let config = create_config("true".to_string());
@@ -675,21 +1024,21 @@ mod tests {
         assert!(tuner.can_grab(0.into()));
 
         tuner.activate(create_channel("1"), vec![]).unwrap();
-        tuner.subscribe(create_user(0.into()));
+        tuner.subscribe(create_user(0.into()), vec![]);
 
         assert!(!tuner.can_grab(0.into()));
         assert!(tuner.can_grab(1.into()));
         assert!(tuner.can_grab(2.into()));
         assert!(tuner.can_grab(TunerUserPriority::GRAB));
 
-        tuner.subscribe(create_user(1.into()));
+        tuner.subscribe(create_user(1.into()), vec![]);
 
         assert!(!tuner.can_grab(0.into()));
         assert!(!tuner.can_grab(1.into()));
         assert!(tuner.can_grab(2.into()));
         assert!(tuner.can_grab(TunerUserPriority::GRAB));
 
-        tuner.subscribe(create_user(TunerUserPriority::GRAB));
+        tuner.subscribe(create_user(TunerUserPriority::GRAB), vec![]);
 
         assert!(!tuner.can_grab(0.into()));
         assert!(!tuner.can_grab(1.into()));
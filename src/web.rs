@@ -1,15 +1,16 @@
 use std::collections::HashMap;
 use std::fmt::Write as _;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::net::IpAddr;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
 use actix::prelude::*;
 use actix_files;
 use actix_service;
-use actix_web::{self, FromRequest};
+use actix_web::{self, FromRequest, HttpMessage};
 use bytes::{Bytes, BytesMut};
 use futures;
 use futures::stream::{Stream, StreamExt};
@@ -18,15 +19,24 @@ use serde_qs;
 use tokio::sync::mpsc;
 
 use crate::airtime_tracker;
+use crate::channel_job::ChannelJobOptions;
+use crate::channel_scanner::*;
 use crate::chunk_stream::ChunkStream;
 use crate::command_util::*;
-use crate::config::{Config, ServerAddr, FilterConfig, PostFilterConfig};
-use crate::datetime_ext::Jst;
+use crate::config::{
+    self, AccessControlConfig, AuthConfig, AuthScope, ChannelConfig, Config,
+    CorsConfig, ServerAddr, FilterConfig, PostFilterConfig, TunerConfig,
+};
+use crate::datetime_ext::{midnight_in, Jst};
 use crate::error::Error;
 use crate::epg::*;
+use crate::ip_net;
+use crate::job::*;
 use crate::models::*;
 use crate::mpeg_ts_stream::*;
+use crate::streaming_quota::*;
 use crate::string_table::*;
+use crate::tls;
 use crate::tuner::*;
 
 #[cfg(not(test))]
@@ -39,13 +49,31 @@ type EpgActor = Epg;
 #[cfg(test)]
 type EpgActor = actix::actors::mocker::Mocker<Epg>;
 
+#[cfg(not(test))]
+type JobManagerActor = JobManager;
+#[cfg(test)]
+type JobManagerActor = actix::actors::mocker::Mocker<JobManager>;
+
 pub async fn serve(
     config: Arc<Config>,
     string_table: Arc<StringTable>,
     tuner_manager: Addr<TunerManager>,
     epg: Addr<Epg>,
-) -> Result<(), Error> {
+    job_manager: Addr<JobManager>,
+) -> Result<bool, Error> {
+    // Same as the default format except that the query string and the Referer
+    // header are not logged.  Both of them may contain a `token` parameter.
+    const ACCESS_LOG_FORMAT: &'static str =
+        r#"%a "%U" %s %b "%{User-Agent}i" %T"#;
+
     let server_config = config.server.clone();
+    let access_control = AccessControl::new(&server_config.access_control);
+    let authentication = Authentication::new(&server_config.auth);
+    let quotas = Arc::new(StreamingQuotas::new(
+        server_config.quotas.clone(), config.time_zone.offset));
+    let cors = Cors::new(&server_config.cors);
+    let (restart_sender, restart_receiver) = mpsc::channel(1);
+    let restarter = Restarter(restart_sender);
     let mut server = actix_web::HttpServer::new(
         move || {
             actix_web::App::new()
@@ -53,30 +81,124 @@ pub async fn serve(
                 .data(string_table.clone())
                 .data(tuner_manager.clone())
                 .data(epg.clone())
-                .wrap(actix_web::middleware::Logger::default())
+                .data(job_manager.clone())
+                .data(quotas.clone())
+                .data(restarter.clone())
+                .wrap(actix_web::middleware::Logger::new(ACCESS_LOG_FORMAT))
                 .wrap(actix_web::middleware::DefaultHeaders::new()
                       .header("Server", server_name()))
-                .wrap(AccessControl)
+                .wrap(authentication.clone())
+                .wrap(cors.clone())
+                .wrap(access_control.clone())
                 .service(create_api_service())
+                .service(create_ui_service())
         });
+    let mut cert_resolvers = Vec::new();
     for addr in server_config.addrs.iter() {
         server = match addr {
             ServerAddr::Http(addr) => server.bind(addr.as_str())?,
+            ServerAddr::Https { addr, cert, key } => {
+                let resolver =
+                    Arc::new(tls::ReloadableCertResolver::new(cert, key)?);
+                cert_resolvers.push(resolver.clone());
+                server.bind_rustls(
+                    addr.as_str(), tls::create_server_config(resolver))?
+            }
             ServerAddr::Unix(path) => server.bind_uds(path.as_str())?,
         };
     }
-    server
+    if !cert_resolvers.is_empty() {
+        actix::spawn(tls::reload_on_sighup(cert_resolvers));
+    }
+    let server = server
         .keep_alive(0)  // disable keep-alive
         .workers(server_config.workers)
-        .run()
-        .await?;
-    Ok(())
+        .run();
+    let restart_requested = Arc::new(AtomicBool::new(false));
+    actix::spawn(wait_for_restart(
+        restart_receiver, server.clone(), restart_requested.clone()));
+    server.await?;
+    Ok(restart_requested.load(Ordering::SeqCst))
+}
+
+// Used for applying changes in the config file.
+#[derive(Clone)]
+struct Restarter(mpsc::Sender<()>);
+
+impl Restarter {
+    fn request(&self) {
+        // The channel may be full, but one request is enough.
+        let _ = self.0.clone().try_send(());
+    }
+}
+
+async fn wait_for_restart(
+    mut receiver: mpsc::Receiver<()>,
+    server: actix_web::dev::Server,
+    restart_requested: Arc<AtomicBool>,
+) {
+    // Clients like Mirakurun UI update the config with multiple requests.
+    // Wait for subsequent requests in order to apply them at once.
+    const DELAY: Duration = Duration::from_secs(3);
+
+    if receiver.recv().await.is_none() {
+        return;
+    }
+    while let Ok(Some(_)) = tokio::time::timeout(DELAY, receiver.recv()).await {
+    }
+
+    log::info!("Restarting in order to apply changes in the config...");
+    restart_requested.store(true, Ordering::SeqCst);
+    server.stop(true).await;
 }
 
 fn server_name() -> String {
     format!("{}/{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
 }
 
+// admin ui
+
+// Assets are embedded in the executable so that the admin UI works without any
+// additional files.
+const UI_ASSETS: &[(&str, &str, &str)] = &[
+    ("index.html", "text/html; charset=utf-8",
+     include_str!("../ui/index.html")),
+    ("app.js", "application/javascript; charset=utf-8",
+     include_str!("../ui/app.js")),
+    ("style.css", "text/css; charset=utf-8",
+     include_str!("../ui/style.css")),
+];
+
+fn create_ui_service() -> impl actix_web::dev::HttpServiceFactory {
+    actix_web::web::scope("/ui")
+        .route("", actix_web::web::get().to(redirect_to_ui))
+        .service(get_ui_asset)
+}
+
+async fn redirect_to_ui() -> actix_web::HttpResponse {
+    // Use a relative URL in order to support reverse proxies serving mirakc
+    // under a sub-path.
+    actix_web::HttpResponse::MovedPermanently()
+        .header("location", "ui/")
+        .finish()
+}
+
+#[actix_web::get("/{name:.*}")]
+async fn get_ui_asset(
+    path: actix_web::web::Path<UiAssetPath>,
+) -> actix_web::HttpResponse {
+    let name = match path.name.as_str() {
+        "" => "index.html",
+        name => name,
+    };
+    match UI_ASSETS.iter().find(|(asset, _, _)| *asset == name) {
+        Some((_, content_type, body)) => actix_web::HttpResponse::Ok()
+            .content_type(*content_type)
+            .body(*body),
+        None => actix_web::HttpResponse::NotFound().finish(),
+    }
+}
+
 // rest api
 
 type ApiResult = Result<actix_web::HttpResponse, Error>;
@@ -88,6 +210,39 @@ struct ErrorBody {
     pub errors: Vec<u8>,
 }
 
+#[derive(Serialize)]
+struct StatusBody {
+    pub epg: EpgStatusBody,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EpgStatusBody {
+    pub is_ready: bool,
+    pub stored_services: usize,
+    pub stored_events: usize,
+    // UNIX time in milliseconds.
+    pub services_updated_at: Option<i64>,
+    pub clocks_updated_at: Option<i64>,
+    pub schedules_updated_at: Option<i64>,
+}
+
+impl From<EpgStatus> for EpgStatusBody {
+    fn from(status: EpgStatus) -> Self {
+        EpgStatusBody {
+            is_ready: status.is_ready(),
+            stored_services: status.num_services,
+            stored_events: status.num_programs,
+            services_updated_at: status.services_updated_at
+                .map(|datetime| datetime.timestamp_millis()),
+            clocks_updated_at: status.clocks_updated_at
+                .map(|datetime| datetime.timestamp_millis()),
+            schedules_updated_at: status.schedules_updated_at
+                .map(|datetime| datetime.timestamp_millis()),
+        }
+    }
+}
+
 impl actix_web::ResponseError for Error {
     fn error_response(&self) -> actix_web::HttpResponse {
         match *self {
@@ -127,6 +282,52 @@ impl actix_web::ResponseError for Error {
                     reason: None,
                     errors: Vec::new(),
                 }),
+            Error::StreamingQuotaExceeded =>
+                actix_web::HttpResponse::TooManyRequests().json(ErrorBody {
+                    code: actix_web::http::StatusCode::TOO_MANY_REQUESTS
+                        .as_u16(),
+                    reason: Some("Streaming Quota Exceeded"),
+                    errors: Vec::new(),
+                }),
+            Error::InvalidConfig(_) =>
+                actix_web::HttpResponse::BadRequest().json(ErrorBody {
+                    code: actix_web::http::StatusCode::BAD_REQUEST.as_u16(),
+                    reason: Some("Invalid Config"),
+                    errors: Vec::new(),
+                }),
+            Error::InvalidQuery(_) =>
+                actix_web::HttpResponse::BadRequest().json(ErrorBody {
+                    code: actix_web::http::StatusCode::BAD_REQUEST.as_u16(),
+                    reason: Some("Invalid Query"),
+                    errors: Vec::new(),
+                }),
+            Error::AuthenticationRequired =>
+                actix_web::HttpResponse::Unauthorized()
+                .set_header("www-authenticate", r#"Basic realm="mirakc""#)
+                .json(ErrorBody {
+                    code: actix_web::http::StatusCode::UNAUTHORIZED.as_u16(),
+                    reason: None,
+                    errors: Vec::new(),
+                }),
+            Error::LogoNotFound =>
+                actix_web::HttpResponse::ServiceUnavailable().json(ErrorBody {
+                    code: actix_web::http::StatusCode::SERVICE_UNAVAILABLE
+                        .as_u16(),
+                    reason: Some("Logo Data Unavailable"),
+                    errors: Vec::new(),
+                }),
+            Error::JobNotFound | Error::TunerNotFound | Error::StreamNotFound =>
+                actix_web::HttpResponse::NotFound().json(ErrorBody {
+                    code: actix_web::http::StatusCode::NOT_FOUND.as_u16(),
+                    reason: None,
+                    errors: Vec::new(),
+                }),
+            Error::JobAlreadyRunning | Error::ChannelScanAlreadyRunning =>
+                actix_web::HttpResponse::Conflict().json(ErrorBody {
+                    code: actix_web::http::StatusCode::CONFLICT.as_u16(),
+                    reason: None,
+                    errors: Vec::new(),
+                }),
             _ =>
                 actix_web::HttpResponse::InternalServerError().json(ErrorBody {
                     code: actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
@@ -145,9 +346,26 @@ fn create_api_service() -> impl actix_web::dev::HttpServiceFactory {
         .service(get_channels)
         .service(get_services)
         .service(get_service)
+        .service(get_service_logo)
+        .service(get_service_programs)
         .service(get_programs)
+        // Must be registered before `get_program`.
+        .service(search_programs)
         .service(get_program)
         .service(get_tuners)
+        .service(delete_tuner)
+        .service(get_streams)
+        .service(delete_stream)
+        .service(get_config_server)
+        .service(put_config_server)
+        .service(get_config_channels)
+        .service(put_config_channels)
+        .service(scan_channels)
+        .service(get_config_tuners)
+        .service(put_config_tuners)
+        .service(get_jobs)
+        .service(run_job)
+        .service(cancel_job)
         .service(get_channel_stream)
         .service(get_channel_service_stream)
         .service(get_service_stream)
@@ -163,9 +381,13 @@ async fn get_version() -> ApiResult {
 }
 
 #[actix_web::get("/status")]
-async fn get_status() -> ApiResult {
-    Ok(actix_web::HttpResponse::Ok()
-       .content_type("application/json").body("{}"))
+async fn get_status(
+    epg: actix_web::web::Data<Addr<EpgActor>>,
+) -> ApiResult {
+    epg.send(QueryEpgStatusMessage).await?
+        .map(|status| actix_web::HttpResponse::Ok().json(StatusBody {
+            epg: EpgStatusBody::from(status),
+        }))
 }
 
 #[actix_web::get("/channels")]
@@ -199,11 +421,53 @@ async fn get_service(
         .map(|service| actix_web::HttpResponse::Ok().json(service))
 }
 
+#[actix_web::get("/services/{id}/logo")]
+async fn get_service_logo(
+    epg: actix_web::web::Data<Addr<EpgActor>>,
+    path: actix_web::web::Path<ServicePath>,
+) -> Result<actix_files::NamedFile, Error> {
+    let logo_path = epg.send(QueryLogoMessage {
+        nid: path.id.nid(),
+        sid: path.id.sid(),
+    }).await??;
+    Ok(actix_files::NamedFile::open(logo_path)?)
+}
+
+#[actix_web::get("/services/{id}/programs")]
+async fn get_service_programs(
+    epg: actix_web::web::Data<Addr<EpgActor>>,
+    path: actix_web::web::Path<ServicePath>,
+    query: actix_web::web::Query<ServiceProgramsQuery>,
+) -> ApiResult {
+    epg.send(QueryServiceProgramsMessage {
+        nid: path.id.nid(),
+        sid: path.id.sid(),
+        from: query.from.map(datetime_from_millis).transpose()?,
+        to: query.to.map(datetime_from_millis).transpose()?,
+    }).await?
+        .map(|programs| programs.into_iter()
+             .map(MirakurunProgram::from).collect::<Vec<MirakurunProgram>>())
+        .map(|programs| actix_web::HttpResponse::Ok().json(programs))
+}
+
 #[actix_web::get("/programs")]
 async fn get_programs(
     epg: actix_web::web::Data<Addr<EpgActor>>,
+    query: actix_web::web::Query<ProgramsQuery>,
+) -> ApiResult {
+    let filter = query.into_inner().into_filter()?;
+    epg.send(QueryProgramsWithFilterMessage { filter }).await?
+        .map(|programs| programs.into_iter()
+             .map(MirakurunProgram::from).collect::<Vec<MirakurunProgram>>())
+        .map(|programs| actix_web::HttpResponse::Ok().json(programs))
+}
+
+#[actix_web::get("/programs/search")]
+async fn search_programs(
+    epg: actix_web::web::Data<Addr<EpgActor>>,
+    query: actix_web::web::Query<SearchProgramsQuery>,
 ) -> ApiResult {
-    epg.send(QueryProgramsMessage).await?
+    epg.send(SearchProgramsMessage { query: query.into_inner().q }).await?
         .map(|programs| programs.into_iter()
              .map(MirakurunProgram::from).collect::<Vec<MirakurunProgram>>())
         .map(|programs| actix_web::HttpResponse::Ok().json(programs))
@@ -231,11 +495,234 @@ async fn get_tuners(
         .map(|tuners| actix_web::HttpResponse::Ok().json(tuners))
 }
 
+#[actix_web::delete("/tuners/{index}")]
+async fn delete_tuner(
+    tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
+    path: actix_web::web::Path<TunerPath>,
+) -> ApiResult {
+    tuner_manager.send(DeactivateTunerMessage { index: path.index }).await??;
+    Ok(actix_web::HttpResponse::NoContent().finish())
+}
+
+#[actix_web::get("/streams")]
+async fn get_streams(
+    tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>
+) -> ApiResult {
+    tuner_manager.send(QueryStreamsMessage).await?
+        .map(|streams| actix_web::HttpResponse::Ok().json(streams))
+}
+
+#[actix_web::delete("/streams/{id}")]
+async fn delete_stream(
+    tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
+    path: actix_web::web::Path<StreamPath>,
+) -> ApiResult {
+    let id: MpegTsStreamId = path.id.parse()?;
+    // The client receives EOS when the broadcaster closes the stream.
+    tuner_manager.send(KillStreamMessage { id }).await??;
+    Ok(actix_web::HttpResponse::NoContent().finish())
+}
+
+#[actix_web::get("/config/server")]
+async fn get_config_server(
+    config: actix_web::web::Data<Arc<Config>>,
+) -> ApiResult {
+    Ok(actix_web::HttpResponse::Ok()
+       .json(MirakurunConfigServer::from(&config.server)))
+}
+
+#[actix_web::put("/config/server")]
+async fn put_config_server(
+    config: actix_web::web::Data<Arc<Config>>,
+    restarter: actix_web::web::Data<Restarter>,
+    body: actix_web::web::Json<MirakurunConfigServer>,
+) -> ApiResult {
+    let addrs = body.apply_to_addrs(&config.server.addrs)?;
+    update_config(&config, &restarter, &["server", "addrs"], &addrs)?;
+    Ok(actix_web::HttpResponse::Ok().json(body.into_inner()))
+}
+
+#[actix_web::get("/config/channels")]
+async fn get_config_channels(
+    config: actix_web::web::Data<Arc<Config>>,
+) -> ApiResult {
+    let channels: Vec<MirakurunConfigChannel> = config.channels.iter()
+        .map(MirakurunConfigChannel::from)
+        .collect();
+    Ok(actix_web::HttpResponse::Ok().json(channels))
+}
+
+#[actix_web::put("/config/channels")]
+async fn put_config_channels(
+    config: actix_web::web::Data<Arc<Config>>,
+    restarter: actix_web::web::Data<Restarter>,
+    body: actix_web::web::Json<Vec<MirakurunConfigChannel>>,
+) -> ApiResult {
+    let channels: Vec<ChannelConfig> = body.into_inner().into_iter()
+        .map(MirakurunConfigChannel::into)
+        .collect();
+    update_config(&config, &restarter, &["channels"], &channels)?;
+    let channels: Vec<MirakurunConfigChannel> = channels.iter()
+        .map(MirakurunConfigChannel::from)
+        .collect();
+    Ok(actix_web::HttpResponse::Ok().json(channels))
+}
+
+#[actix_web::put("/config/channels/scan")]
+async fn scan_channels(
+    config: actix_web::web::Data<Arc<Config>>,
+    tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
+    restarter: actix_web::web::Data<Restarter>,
+    query: actix_web::web::Query<ChannelScanQuery>,
+) -> ApiResult {
+    let query = query.into_inner();
+    // Check it before scanning which takes a long time.  A dry run is also
+    // rejected because it occupies tuners.
+    check_writable(&config)?;
+    if !query.dry_run {
+        config_path(&config)?;
+    }
+    let candidates = ChannelScanner::candidates(
+        query.channel_type, query.min_ch, query.max_ch)?;
+    let lock = ChannelScanLock::acquire()?;
+
+    let scanner = ChannelScanner::new(
+        config.jobs.scan_services.command.clone(), query.channel_type,
+        candidates, tuner_manager.get_ref().clone().recipient(),
+        ChannelJobOptions::from(&config.jobs.scan_services));
+    let config = config.get_ref().clone();
+    let restarter = restarter.get_ref().clone();
+
+    let (mut sender, receiver) = mpsc::channel(16);
+    actix::spawn(async move {
+        let _lock = lock;
+        let channels = match scanner.scan(sender.clone()).await {
+            Some(channels) => channels,
+            None => {
+                log::info!("Canceled the channel scan");
+                return;
+            }
+        };
+        let mut msg = format!("{} channels found\n", channels.len());
+        if !query.dry_run {
+            let channels = merge_channels(
+                &config.channels, query.channel_type, channels, query.refresh);
+            match update_config(&config, &restarter, &["channels"], &channels) {
+                Ok(_) => msg.push_str("Updated the config\n"),
+                Err(err) => {
+                    let _ = writeln!(msg, "Failed to update the config: {}", err);
+                }
+            }
+        }
+        let _ = sender.send(msg).await;
+    });
+
+    Ok(actix_web::HttpResponse::Ok()
+       .content_type("text/plain; charset=utf-8")
+       .streaming(receiver.map(|msg| Ok::<_, io::Error>(Bytes::from(msg)))))
+}
+
+#[actix_web::get("/config/tuners")]
+async fn get_config_tuners(
+    config: actix_web::web::Data<Arc<Config>>,
+) -> ApiResult {
+    let tuners: Vec<MirakurunConfigTuner> = config.tuners.iter()
+        .map(MirakurunConfigTuner::from)
+        .collect();
+    Ok(actix_web::HttpResponse::Ok().json(tuners))
+}
+
+#[actix_web::put("/config/tuners")]
+async fn put_config_tuners(
+    config: actix_web::web::Data<Arc<Config>>,
+    restarter: actix_web::web::Data<Restarter>,
+    body: actix_web::web::Json<Vec<MirakurunConfigTuner>>,
+) -> ApiResult {
+    let tuners: Vec<TunerConfig> = body.into_inner().into_iter()
+        .map(MirakurunConfigTuner::into)
+        .collect();
+    update_config(&config, &restarter, &["tuners"], &tuners)?;
+    let tuners: Vec<MirakurunConfigTuner> = tuners.iter()
+        .map(MirakurunConfigTuner::from)
+        .collect();
+    Ok(actix_web::HttpResponse::Ok().json(tuners))
+}
+
+// Changes are applied by restarting the server.
+fn update_config<T: Serialize>(
+    config: &Config,
+    restarter: &Restarter,
+    keys: &[&str],
+    value: &T,
+) -> Result<(), Error> {
+    config::update(config_path(config)?, keys, value)?;
+    restarter.request();
+    Ok(())
+}
+
+fn check_writable(config: &Config) -> Result<(), Error> {
+    if !config.mirakurun.writable_config {
+        return Err(Error::AccessDenied);
+    }
+    Ok(())
+}
+
+fn config_path(config: &Config) -> Result<&str, Error> {
+    check_writable(config)?;
+    match config.path {
+        Some(ref path) => Ok(path),
+        None => Err(Error::InvalidConfig("No config file".to_string())),
+    }
+}
+
+#[actix_web::get("/jobs")]
+async fn get_jobs(
+    job_manager: actix_web::web::Data<Addr<JobManagerActor>>
+) -> ApiResult {
+    job_manager.send(QueryJobsMessage).await?
+        .map(|jobs| actix_web::HttpResponse::Ok().json(jobs))
+}
+
+#[actix_web::post("/jobs/{name}/run")]
+async fn run_job(
+    job_manager: actix_web::web::Data<Addr<JobManagerActor>>,
+    path: actix_web::web::Path<JobPath>,
+) -> ApiResult {
+    let result = match path.name.as_str() {
+        "scan-services" =>
+            job_manager.send(InvokeScanServicesMessage).await?,
+        "sync-clocks" =>
+            job_manager.send(InvokeSyncClocksMessage).await?,
+        "update-schedules" =>
+            job_manager.send(InvokeUpdateSchedulesMessage).await?,
+        "collect-logos" =>
+            job_manager.send(InvokeCollectLogosMessage).await?,
+        name =>
+            job_manager.send(InvokeCustomJobMessage {
+                name: name.to_string(),
+            }).await?,
+    };
+    result.map(|_| actix_web::HttpResponse::Accepted().finish())
+}
+
+#[actix_web::post("/jobs/{name}/cancel")]
+async fn cancel_job(
+    job_manager: actix_web::web::Data<Addr<JobManagerActor>>,
+    path: actix_web::web::Path<JobPath>,
+) -> ApiResult {
+    job_manager.send(CancelJobMessage {
+        name: path.name.clone(),
+    }).await?
+        .map(|_| actix_web::HttpResponse::Accepted().finish())
+}
+
 #[actix_web::get("/channels/{channel_type}/{channel}/stream")]
 async fn get_channel_stream(
     config: actix_web::web::Data<Arc<Config>>,
     tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
     epg: actix_web::web::Data<Addr<EpgActor>>,
+    quotas: actix_web::web::Data<Arc<StreamingQuotas>>,
+    quota_key: QuotaKey,
     path: actix_web::web::Path<ChannelPath>,
     user: TunerUser,
     filter_setting: FilterSetting,
@@ -261,11 +748,14 @@ async fn get_channel_stream(
         &config.post_filters, &filter_setting.post_filters)?;
     let (filters, content_type) = builder.build();
 
+    let mut permit = quotas.acquire(quota_key)?;
+
     let stream = tuner_manager.send(StartStreamingMessage {
-        channel, user
+        channel, user, filters: filters.clone(),
     }).await??;
+    permit.commit();
 
-    streaming(&config, stream, filters, content_type, None).await
+    streaming(&config, stream, filters, content_type, None, permit).await
 }
 
 #[actix_web::get("/channels/{channel_type}/{channel}/services/{sid}/stream")]
@@ -273,6 +763,8 @@ async fn get_channel_service_stream(
     config: actix_web::web::Data<Arc<Config>>,
     tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
     epg: actix_web::web::Data<Addr<EpgActor>>,
+    quotas: actix_web::web::Data<Arc<StreamingQuotas>>,
+    quota_key: QuotaKey,
     path: actix_web::web::Path<ChannelServicePath>,
     user: TunerUser,
     filter_setting: FilterSetting,
@@ -283,7 +775,8 @@ async fn get_channel_service_stream(
     }).await??;
 
     do_get_service_stream(
-        config, tuner_manager, channel, path.sid, user, filter_setting).await
+        config, tuner_manager, quotas, quota_key, channel, path.sid, user,
+        filter_setting).await
 }
 
 #[actix_web::get("/services/{id}/stream")]
@@ -291,6 +784,8 @@ async fn get_service_stream(
     config: actix_web::web::Data<Arc<Config>>,
     tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
     epg: actix_web::web::Data<Addr<EpgActor>>,
+    quotas: actix_web::web::Data<Arc<StreamingQuotas>>,
+    quota_key: QuotaKey,
     path: actix_web::web::Path<ServicePath>,
     user: TunerUser,
     filter_setting: FilterSetting,
@@ -301,8 +796,8 @@ async fn get_service_stream(
     }).await??;
 
     do_get_service_stream(
-        config, tuner_manager, service.channel, service.sid, user,
-        filter_setting).await
+        config, tuner_manager, quotas, quota_key, service.channel, service.sid,
+        user, filter_setting).await
 }
 
 #[actix_web::get("/programs/{id}/stream")]
@@ -310,6 +805,8 @@ async fn get_program_stream(
     config: actix_web::web::Data<Arc<Config>>,
     tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
     epg: actix_web::web::Data<Addr<EpgActor>>,
+    quotas: actix_web::web::Data<Arc<StreamingQuotas>>,
+    quota_key: QuotaKey,
     path: actix_web::web::Path<ProgramPath>,
     user: TunerUser,
     filter_setting: FilterSetting,
@@ -351,10 +848,14 @@ async fn get_program_stream(
         &config.post_filters, &filter_setting.post_filters)?;
     let (filters, content_type) = builder.build();
 
+    let mut permit = quotas.acquire(quota_key)?;
+
     let stream = tuner_manager.send(StartStreamingMessage {
         channel: service.channel.clone(),
-        user
+        user,
+        filters: filters.clone(),
     }).await??;
+    permit.commit();
 
     let stop_trigger = airtime_tracker::track_airtime(
         &config.recorder.track_airtime_command, &service.channel, &program,
@@ -362,7 +863,8 @@ async fn get_program_stream(
     ).await?;
 
     let result =
-        streaming(&config, stream, filters, content_type, stop_trigger).await;
+        streaming(&config, stream, filters, content_type, stop_trigger, permit)
+        .await;
 
     match result {
         Err(Error::ProgramNotFound) =>
@@ -382,7 +884,16 @@ async fn get_iptv_playlist(
     const INITIAL_BUFSIZE: usize = 8 * 1024;  // 8KB
 
     filter_setting.decode = true;  // always decode
-    let query = serde_qs::to_string(&filter_setting).expect("Never fails");
+    let mut query = serde_qs::to_string(&filter_setting).expect("Never fails");
+    // IPTV players can pass credentials only in URLs.
+    if let Some(token) = req.extensions().get::<AuthToken>() {
+        if !query.is_empty() {
+            query.push('&');
+        }
+        query.push_str(&serde_qs::to_string(&TokenQuery {
+            token: Some(token.0.clone()),
+        }).expect("Never fails"));
+    }
 
     let services = epg.send(QueryServicesMessage).await??;
 
@@ -417,6 +928,7 @@ async fn get_iptv_playlist(
 
 #[actix_web::get("/iptv/epg")]
 async fn get_iptv_epg(
+    config: actix_web::web::Data<Arc<Config>>,
     string_table: actix_web::web::Data<Arc<StringTable>>,
     epg: actix_web::web::Data<Addr<EpgActor>>,
     query: actix_web::web::Query<IptvEpgQuery>,
@@ -424,8 +936,9 @@ async fn get_iptv_epg(
     const INITIAL_BUFSIZE: usize = 8 * 1024 * 1024;  // 8MB
     const DATETIME_FORMAT: &'static str = "%Y%m%d%H%M%S %z";
 
-    let end_after = Jst::midnight();
-    let start_before = end_after + chrono::Duration::days(query.days as i64);
+    // Day boundaries and times depend on the time zone of each network.
+    let now = Jst::now();
+    let days = chrono::Duration::days(query.days as i64);
 
     let services = epg.send(QueryServicesMessage).await??;
     let programs = epg.send(QueryProgramsMessage).await??;
@@ -439,15 +952,17 @@ async fn get_iptv_epg(
         write!(buf, r#"<channel id="{}"><display-name lang="ja">{}</display-name></channel>"#,
                id.value(), escape(&sv.name))?;
     }
-    for pg in programs
-        .iter()
-        .filter(|pg| pg.name.is_some())
-        .filter(|pg| pg.start_at < start_before && pg.end_at() > end_after)
-    {
+    for pg in programs.iter().filter(|pg| pg.name.is_some()) {
+        let offset = config.time_zone.offset_for(pg.quad.nid());
+        let end_after = midnight_in(&offset, now);
+        let start_before = end_after + days;
+        if pg.start_at >= start_before || pg.end_at() <= end_after {
+            continue;
+        }
         let id = MirakurunServiceId::from(pg.quad);
         write!(buf, r#"<programme start="{}" stop="{}" channel="{}">"#,
-               pg.start_at.format(DATETIME_FORMAT),
-               pg.end_at().format(DATETIME_FORMAT),
+               pg.start_at.with_timezone(&offset).format(DATETIME_FORMAT),
+               pg.end_at().with_timezone(&offset).format(DATETIME_FORMAT),
                id.value())?;
         if let Some(name) = pg.name.as_ref() {
             write!(buf, r#"<title lang="ja">{}</title>"#, escape(&name))?;
@@ -502,6 +1017,8 @@ async fn get_docs(
 async fn do_get_service_stream(
     config: actix_web::web::Data<Arc<Config>>,
     tuner_manager: actix_web::web::Data<Addr<TunerManagerActor>>,
+    quotas: actix_web::web::Data<Arc<StreamingQuotas>>,
+    quota_key: QuotaKey,
     channel: EpgChannel,
     sid: ServiceId,
     user: TunerUser,
@@ -525,11 +1042,14 @@ async fn do_get_service_stream(
         &config.post_filters, &filter_setting.post_filters)?;
     let (filters, content_type) = builder.build();
 
+    let mut permit = quotas.acquire(quota_key)?;
+
     let stream = tuner_manager.send(StartStreamingMessage {
-        channel, user
+        channel, user, filters: filters.clone(),
     }).await??;
+    permit.commit();
 
-    streaming(&config, stream, filters, content_type, None).await
+    streaming(&config, stream, filters, content_type, None, permit).await
 }
 
 async fn streaming(
@@ -538,10 +1058,12 @@ async fn streaming(
     filters: Vec<String>,
     content_type: String,
     stop_trigger: Option<MpegTsStreamStopTrigger>,
+    permit: QuotaPermit,
 ) -> ApiResult {
     if filters.is_empty() {
         do_streaming(
-            stream, content_type, config.server.stream_time_limit).await
+            QuotaLimitedStream::new(stream, Some(permit)),
+            content_type, config.server.stream_time_limit).await
     } else {
         log::debug!("Streaming with filters: {:?}", filters);
 
@@ -596,7 +1118,10 @@ async fn streaming(
         });
 
         do_streaming(
-            MpegTsStreamTerminator::new(receiver, [stop_trigger, stop_trigger2]),
+            QuotaLimitedStream::new(
+                MpegTsStreamTerminator::new(
+                    receiver, [stop_trigger, stop_trigger2]),
+                Some(permit)),
             content_type, config.server.stream_time_limit).await
     }
 }
@@ -659,6 +1184,26 @@ struct ProgramPath {
     id: MirakurunProgramId,
 }
 
+#[derive(Deserialize)]
+struct TunerPath {
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct StreamPath {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct UiAssetPath {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct JobPath {
+    name: String,
+}
+
 // actix-web uses the serde_urlencoded crate for parsing the query in an URL.
 // Unfortunately, the Vec support is out of scope for the serde_urlencoded
 // crate and it's suggested to use the serde_qs crate.
@@ -755,6 +1300,28 @@ impl FromRequest for TunerUser {
     }
 }
 
+impl FromRequest for QuotaKey {
+    type Error = actix_web::Error;
+    type Future = futures::future::Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        _: &mut actix_web::dev::Payload
+    ) -> Self::Future {
+        if let Some(token) = req.extensions().get::<AuthToken>() {
+            return futures::future::ok(QuotaKey::Token(token.0.clone()));
+        }
+        // Use the client address determined by `AccessControl` if possible.
+        let addr = req.extensions().get::<ClientAddr>().map(|addr| addr.0)
+            .or_else(|| req.peer_addr().map(|socket| socket.ip()));
+        match addr {
+            Some(addr) => futures::future::ok(QuotaKey::Addr(addr)),
+            None => futures::future::ok(QuotaKey::Local),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct IptvEpgQuery {
     #[serde(default = "IptvEpgQuery::default_days")]
@@ -765,9 +1332,92 @@ impl IptvEpgQuery {
     fn default_days() -> u8 { 3 }
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ProgramsQuery {
+    network_id: Option<NetworkId>,
+    service_id: Option<ServiceId>,
+    event_id: Option<EventId>,
+    // UNIX time in milliseconds.
+    from: Option<i64>,
+    to: Option<i64>,
+    genre_lv1: Option<u8>,
+    genre_lv2: Option<u8>,
+    keyword: Option<String>,
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    #[serde(default)]
+    ended: bool,
+}
+
+#[derive(Deserialize)]
+struct ServiceProgramsQuery {
+    // UNIX time in milliseconds.
+    from: Option<i64>,
+    to: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct SearchProgramsQuery {
+    q: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelScanQuery {
+    #[serde(rename = "type", default = "ChannelScanQuery::default_type")]
+    channel_type: ChannelType,
+    min_ch: Option<u32>,
+    max_ch: Option<u32>,
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    refresh: bool,
+}
+
+impl ChannelScanQuery {
+    fn default_type() -> ChannelType {
+        ChannelType::GR
+    }
+}
+
+impl ProgramsQuery {
+    fn into_filter(self) -> Result<ProgramFilter, Error> {
+        Ok(ProgramFilter {
+            nid: self.network_id,
+            sid: self.service_id,
+            eid: self.event_id,
+            from: self.from.map(datetime_from_millis).transpose()?,
+            to: self.to.map(datetime_from_millis).transpose()?,
+            genre_lv1: self.genre_lv1,
+            genre_lv2: self.genre_lv2,
+            keyword: self.keyword.filter(|keyword| !keyword.is_empty()),
+            offset: self.offset,
+            limit: self.limit,
+            ended: self.ended,
+        })
+    }
+}
+
+// Converts UNIX time in milliseconds specified in a query parameter.
+fn datetime_from_millis(ms: i64) -> Result<chrono::DateTime<Jst>, Error> {
+    use chrono::TimeZone;
+    Jst.timestamp_millis_opt(ms).single().ok_or_else(|| {
+        Error::InvalidQuery(format!("Out of range: {}", ms))
+    })
+}
+
 // middleware
 
-struct AccessControl;
+#[derive(Clone)]
+struct AccessControl(Arc<AccessControlConfig>);
+
+impl AccessControl {
+    fn new(config: &AccessControlConfig) -> Self {
+        AccessControl(Arc::new(config.clone()))
+    }
+}
 
 impl<S, B> actix_service::Transform<S> for AccessControl
 where
@@ -786,11 +1436,11 @@ where
         futures::future::Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        futures::future::ok(AccessControlMiddleware(service))
+        futures::future::ok(AccessControlMiddleware(service, self.0.clone()))
     }
 }
 
-struct AccessControlMiddleware<S>(S);
+struct AccessControlMiddleware<S>(S, Arc<AccessControlConfig>);
 
 impl<S, B> actix_service::Service for AccessControlMiddleware<S>
 where
@@ -814,11 +1464,17 @@ where
     }
 
     fn call(&mut self, req: actix_web::dev::ServiceRequest) -> Self::Future {
-        // Take no account of HTTP Forwarded and X-Forwarded-For headers.
-        let ip = req.peer_addr().map(|socket| socket.ip());
-
-        let allowed = match ip {
-            Some(ip) => is_private_ip_addr(ip),
+        let allowed = match req.peer_addr() {
+            Some(socket) => {
+                let forwarded = forwarded_addrs(req.headers());
+                match client_ip_addr(&self.1, socket.ip(), forwarded) {
+                    Some(ip) => {
+                        req.extensions_mut().insert(ClientAddr(ip));
+                        is_allowed_ip_addr(&self.1, ip)
+                    }
+                    None => false,
+                }
+            }
             None => true,  // UNIX domain socket
         };
 
@@ -831,43 +1487,459 @@ where
     }
 }
 
-fn is_private_ip_addr(ip: IpAddr) -> bool {
-    // TODO: IpAddr::is_global() is a nightly-only API at this point.
-    match ip {
-        IpAddr::V4(ip) => is_private_ipv4_addr(ip),
-        IpAddr::V6(ip) => is_private_ipv6_addr(ip),
-    }
-}
-
-fn is_private_ipv4_addr(ip: Ipv4Addr) -> bool {
-    ip.is_loopback() || ip.is_private() || ip.is_link_local()
-}
+#[derive(Clone)]
+struct Authentication(Arc<AuthConfig>);
 
-fn is_private_ipv6_addr(ip: Ipv6Addr) -> bool {
-    // TODO: Support only IPv4-compatible and IPv4-mapped addresses at this
-    //       moment.
-    match ip.to_ipv4() {
-        Some(ip) => is_private_ipv4_addr(ip),
-        None => false,
+impl Authentication {
+    fn new(config: &AuthConfig) -> Self {
+        Authentication(Arc::new(config.clone()))
     }
 }
 
-// filters
+impl<S, B> actix_service::Transform<S> for Authentication
+where
+    S: actix_service::Service<Request = actix_web::dev::ServiceRequest,
+                              Response = actix_web::dev::ServiceResponse<B>,
+                              Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = actix_web::dev::ServiceRequest;
+    type Response = actix_web::dev::ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future =
+        futures::future::Ready<Result<Self::Transform, Self::InitError>>;
 
-struct FilterPipelineBuilder {
-    data: mustache::Data,
-    filters: Vec<String>,
-    content_type: String,
+    fn new_transform(&self, service: S) -> Self::Future {
+        futures::future::ok(AuthenticationMiddleware(service, self.0.clone()))
+    }
 }
 
-impl FilterPipelineBuilder {
-    fn new(data: mustache::Data) -> Self {
-        FilterPipelineBuilder {
-            data,
-            filters: Vec::new(),
-            content_type: "video/MP2T".to_string(),
-        }
-    }
+struct AuthenticationMiddleware<S>(S, Arc<AuthConfig>);
+
+impl<S, B> actix_service::Service for AuthenticationMiddleware<S>
+where
+    S: actix_service::Service<Request = actix_web::dev::ServiceRequest,
+                              Response = actix_web::dev::ServiceResponse<B>,
+                              Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = actix_web::dev::ServiceRequest;
+    type Response = actix_web::dev::ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn futures::future::Future<
+            Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: actix_web::dev::ServiceRequest) -> Self::Future {
+        if !self.1.is_enabled() {
+            return Box::pin(self.0.call(req));
+        }
+
+        let credential = match Credential::from_request(&req) {
+            Some(credential) => credential,
+            None => return Box::pin(futures::future::ok(
+                req.error_response(Error::AuthenticationRequired))),
+        };
+
+        let scopes = match credential.scopes(&self.1) {
+            Some(scopes) => scopes,
+            None => return Box::pin(futures::future::ok(
+                req.error_response(Error::AuthenticationRequired))),
+        };
+
+        let required = required_scope(&req);
+        if !scopes.contains(&AuthScope::Admin) && !scopes.contains(&required) {
+            return Box::pin(futures::future::ok(
+                req.error_response(Error::AccessDenied)));
+        }
+
+        if let Credential::Token(token) = credential {
+            req.extensions_mut().insert(AuthToken(token));
+        }
+        Box::pin(self.0.call(req))
+    }
+}
+
+// A token used for authenticating the request, which is propagated into URLs
+// generated by the server.
+struct AuthToken(String);
+
+#[derive(Deserialize, Serialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+#[derive(Debug)]
+enum Credential {
+    Token(String),
+    User(String, String),
+}
+
+impl Credential {
+    // The Authorization header takes precedence over the `token` query
+    // parameter.
+    fn from_request(req: &actix_web::dev::ServiceRequest) -> Option<Self> {
+        if let Some(value) = req.headers().get("authorization") {
+            return Self::from_authorization(value.to_str().ok()?);
+        }
+        serde_qs::from_str::<TokenQuery>(req.query_string())
+            .ok()
+            .and_then(|query| query.token)
+            .map(Credential::Token)
+    }
+
+    fn from_authorization(value: &str) -> Option<Self> {
+        let mut parts = value.trim().splitn(2, ' ');
+        let scheme = parts.next()?;
+        let param = parts.next()?.trim();
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return Some(Credential::Token(param.to_string()));
+        }
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(base64::decode(param).ok()?).ok()?;
+            let mut parts = decoded.splitn(2, ':');
+            let name = parts.next()?.to_string();
+            let password = parts.next()?.to_string();
+            return Some(Credential::User(name, password));
+        }
+        None
+    }
+
+    fn scopes<'a>(&self, config: &'a AuthConfig) -> Option<&'a [AuthScope]> {
+        match self {
+            Credential::Token(token) => config.tokens.iter()
+                .find(|config| constant_time_eq(&config.token, token))
+                .map(|config| config.scopes.as_slice()),
+            Credential::User(name, password) => config.users.iter()
+                .find(|config| {
+                    config.name == *name &&
+                        constant_time_eq(&config.password, password)
+                })
+                .map(|config| config.scopes.as_slice()),
+        }
+    }
+}
+
+fn required_scope(req: &actix_web::dev::ServiceRequest) -> AuthScope {
+    let method = req.method();
+    let path = req.path();
+    if (*method != actix_web::http::Method::GET &&
+        *method != actix_web::http::Method::HEAD) ||
+        path.starts_with("/api/jobs") || path.starts_with("/api/streams")
+    {
+        AuthScope::Admin
+    } else if path.ends_with("/stream") {
+        AuthScope::Stream
+    } else {
+        AuthScope::Read
+    }
+}
+
+// Compares secrets in a constant time in order to avoid timing attacks.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.bytes().zip(b.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+// Preflight requests are handled before authentication because browsers never
+// send credentials in preflight requests.
+#[derive(Clone)]
+struct Cors(Arc<CorsConfig>);
+
+impl Cors {
+    fn new(config: &CorsConfig) -> Self {
+        Cors(Arc::new(config.clone()))
+    }
+}
+
+impl<S, B> actix_service::Transform<S> for Cors
+where
+    S: actix_service::Service<Request = actix_web::dev::ServiceRequest,
+                              Response = actix_web::dev::ServiceResponse<B>,
+                              Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = actix_web::dev::ServiceRequest;
+    type Response = actix_web::dev::ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type InitError = ();
+    type Transform = CorsMiddleware<S>;
+    type Future =
+        futures::future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        futures::future::ok(CorsMiddleware(service, self.0.clone()))
+    }
+}
+
+struct CorsMiddleware<S>(S, Arc<CorsConfig>);
+
+impl<S, B> actix_service::Service for CorsMiddleware<S>
+where
+    S: actix_service::Service<Request = actix_web::dev::ServiceRequest,
+                              Response = actix_web::dev::ServiceResponse<B>,
+                              Error = actix_web::Error>,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = actix_web::dev::ServiceRequest;
+    type Response = actix_web::dev::ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn futures::future::Future<
+            Output = Result<Self::Response, Self::Error>>>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: actix_web::dev::ServiceRequest) -> Self::Future {
+        use actix_web::http::header;
+
+        if !self.1.is_enabled() {
+            return Box::pin(self.0.call(req));
+        }
+
+        let origin = match req.headers().get(header::ORIGIN)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(origin) => origin.to_string(),
+            None => return Box::pin(self.0.call(req)),  // not a CORS request
+        };
+
+        let is_preflight = *req.method() == actix_web::http::Method::OPTIONS &&
+            req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            let res = match preflight_response(&self.1, &origin, req.headers()) {
+                Some(res) => res,
+                None => actix_web::HttpResponse::Forbidden().finish(),
+            };
+            return Box::pin(futures::future::ok(
+                req.into_response(res.into_body())));
+        }
+
+        let allow_origin = allow_origin_value(&self.1, &origin);
+        let allow_credentials = self.1.allow_credentials;
+        let fut = self.0.call(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Some(allow_origin) = allow_origin {
+                let headers = res.headers_mut();
+                headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                               allow_origin.parse()?);
+                headers.append(header::VARY,
+                               header::HeaderValue::from_static("Origin"));
+                if allow_credentials {
+                    headers.insert(
+                        header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                        header::HeaderValue::from_static("true"));
+                }
+            }
+            Ok(res)
+        })
+    }
+}
+
+// Returns the value of the Access-Control-Allow-Origin header if the origin is
+// allowed.
+fn allow_origin_value(config: &CorsConfig, origin: &str) -> Option<String> {
+    if config.allowed_origins.iter().any(|allowed| allowed == origin) {
+        return Some(origin.to_string());
+    }
+    // The wildcard with credentials is rejected in the config validation.
+    if config.allowed_origins.iter().any(|allowed| allowed == "*") {
+        return Some("*".to_string());
+    }
+    None
+}
+
+// Returns `None` if the preflight request is not allowed.
+fn preflight_response(
+    config: &CorsConfig,
+    origin: &str,
+    headers: &actix_web::http::HeaderMap,
+) -> Option<actix_web::HttpResponse> {
+    use actix_web::http::header;
+
+    let allow_origin = allow_origin_value(config, origin)?;
+
+    let method = headers.get(header::ACCESS_CONTROL_REQUEST_METHOD)?
+        .to_str().ok()?;
+    if !config.allowed_methods.iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(method))
+    {
+        return None;
+    }
+
+    let requested_headers = headers
+        .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty());
+    for name in requested_headers {
+        if !config.allowed_headers.iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(name))
+        {
+            return None;
+        }
+    }
+
+    let mut builder = actix_web::HttpResponse::NoContent();
+    builder
+        .set_header(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin)
+        .set_header(header::ACCESS_CONTROL_ALLOW_METHODS,
+                    config.allowed_methods.join(", "))
+        .set_header(header::ACCESS_CONTROL_ALLOW_HEADERS,
+                    config.allowed_headers.join(", "))
+        .set_header(header::ACCESS_CONTROL_MAX_AGE, config.max_age.to_string())
+        .set_header(header::VARY, "Origin");
+    if config.allow_credentials {
+        builder.set_header(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, "true");
+    }
+    Some(builder.finish())
+}
+
+// The IP address of the client determined by `AccessControl`.
+struct ClientAddr(IpAddr);
+
+fn is_allowed_ip_addr(config: &AccessControlConfig, ip: IpAddr) -> bool {
+    if config.deny.iter().any(|net| net.contains(ip)) {
+        return false;
+    }
+    if config.allow.is_empty() {
+        return ip_net::is_private(ip);
+    }
+    config.allow.iter().any(|net| net.contains(ip))
+}
+
+// Returns the IP address of the client.
+//
+// Forwarded addresses are taken into account only when the request comes from
+// a trusted proxy.  Addresses are examined from the nearest one, and the first
+// address which is not a trusted proxy is the client.  `None` is returned if
+// an address of a hop cannot be determined.
+fn client_ip_addr(
+    config: &AccessControlConfig,
+    peer: IpAddr,
+    forwarded: Vec<Option<IpAddr>>,
+) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| {
+        config.trusted_proxies.iter().any(|net| net.contains(ip))
+    };
+
+    let mut client = peer;
+    for ip in forwarded.into_iter().rev() {
+        if !is_trusted(client) {
+            break;
+        }
+        client = ip?;
+    }
+    Some(client)
+}
+
+// Collects addresses of hops from the Forwarded header, or the
+// X-Forwarded-For header if the former doesn't exist.  Addresses are listed in
+// the order of the headers, and `None` is used for an unknown or obfuscated
+// address.
+fn forwarded_addrs(headers: &actix_web::http::HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded = header_lines(headers, actix_web::http::header::FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded.iter()
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element.split(';')
+                    .filter_map(|pair| {
+                        let mut kv = pair.trim().splitn(2, '=');
+                        match (kv.next(), kv.next()) {
+                            (Some(k), Some(v))
+                                if k.eq_ignore_ascii_case("for") => Some(v),
+                            _ => None,
+                        }
+                    })
+                    .next()
+                    .and_then(parse_forwarded_node)
+            })
+            .collect();
+    }
+
+    header_lines(
+        headers,
+        actix_web::http::header::HeaderName::from_static("x-forwarded-for"))
+        .iter()
+        .flat_map(|value| value.split(','))
+        .map(|addr| addr.trim().parse().ok())
+        .collect()
+}
+
+// Returns the values of a header field in the order they were received.
+//
+// `HeaderMap::get_all()` doesn't preserve the arrival order: when the second
+// line is appended, it's stored before the first one.  The remaining lines
+// follow in order.
+fn header_lines(
+    headers: &actix_web::http::HeaderMap,
+    name: actix_web::http::header::HeaderName,
+) -> Vec<&str> {
+    let mut lines: Vec<&str> = headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    if lines.len() > 1 {
+        lines.swap(0, 1);
+    }
+    lines
+}
+
+// Parses a node in the Forwarded header like `192.0.2.60`,
+// `"192.0.2.60:8080"` or `"[2001:db8::1]:8080"`.
+fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if node.starts_with('[') {
+        let end = node.find(']')?;
+        return node[1..end].parse().ok();
+    }
+    let host = match node.rfind(':') {
+        Some(pos) => &node[..pos],
+        None => node,
+    };
+    host.parse().ok()
+}
+
+// filters
+
+struct FilterPipelineBuilder {
+    data: mustache::Data,
+    filters: Vec<String>,
+    content_type: String,
+}
+
+impl FilterPipelineBuilder {
+    fn new(data: mustache::Data) -> Self {
+        FilterPipelineBuilder {
+            data,
+            filters: Vec::new(),
+            content_type: "video/MP2T".to_string(),
+        }
+    }
 
     fn build(self) -> (Vec<String>, String) {
         (self.filters, self.content_type)
@@ -1027,18 +2099,39 @@ impl<'a> std::fmt::Display for Escape<'a> {
 mod tests {
     use super::*;
     use std::net::SocketAddr;
+    use crate::config::{AuthTokenConfig, AuthUserConfig};
     use actix_http;
     use assert_matches::*;
     use crate::broadcaster::BroadcasterStream;
+    use crate::fs_util::test_util::TempDir;
 
     async fn request(req: actix_http::Request) -> actix_web::HttpResponse {
+        request_with_config(req, config_for_test()).await
+    }
+
+    async fn request_with_config(
+        req: actix_http::Request,
+        config: Arc<Config>,
+    ) -> actix_web::HttpResponse {
+        let access_control = AccessControl::new(&config.server.access_control);
+        let authentication = Authentication::new(&config.server.auth);
+        let quotas = Arc::new(StreamingQuotas::new(
+            config.server.quotas.clone(), config.time_zone.offset));
+        let cors = Cors::new(&config.server.cors);
+        let (restart_sender, _) = mpsc::channel(1);
         let mut app = actix_web::test::init_service(
             actix_web::App::new()
-                .data(config_for_test())
+                .data(config)
                 .data(tuner_manager_for_test())
                 .data(epg_for_test())
-                .wrap(AccessControl)
-                .service(create_api_service())).await;
+                .data(job_manager_for_test())
+                .data(quotas)
+                .data(Restarter(restart_sender))
+                .wrap(authentication)
+                .wrap(cors)
+                .wrap(access_control)
+                .service(create_api_service())
+                .service(create_ui_service())).await;
         actix_web::test::call_service(&mut app, req).await.into()
     }
 
@@ -1049,6 +2142,20 @@ mod tests {
         request(req).await
     }
 
+    async fn post(uri: &str) -> actix_web::HttpResponse {
+        let req = actix_web::test::TestRequest::with_uri(uri)
+            .method(actix_web::http::Method::POST)
+            .to_request();
+        request(req).await
+    }
+
+    async fn delete(uri: &str) -> actix_web::HttpResponse {
+        let req = actix_web::test::TestRequest::with_uri(uri)
+            .method(actix_web::http::Method::DELETE)
+            .to_request();
+        request(req).await
+    }
+
     async fn get_with_peer_addr(
         uri: &str,
         addr: SocketAddr
@@ -1099,10 +2206,63 @@ mod tests {
         assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
     }
 
+    #[actix_rt::test]
+    async fn test_get_service_logo() {
+        let res = get("/api/services/1/logo").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/services/2/logo").await;
+        assert!(res.status() ==
+                actix_web::http::StatusCode::SERVICE_UNAVAILABLE);
+
+        let res = get("/api/services/0/logo").await;
+        assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_service_programs() {
+        let res = get("/api/services/1/programs").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/services/1/programs?from=0&to=1").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res =
+            get("/api/services/1/programs?from=9223372036854775807").await;
+        assert!(res.status() == actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = get("/api/services/0/programs").await;
+        assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
+    }
+
     #[actix_rt::test]
     async fn test_get_programs() {
         let res = get("/api/programs").await;
         assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/programs?networkId=1&serviceId=2&from=0&to=1&genreLv1=1&keyword=test&offset=1&limit=10").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/programs?ended=true").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/programs?limit=x").await;
+        assert!(res.status() == actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = get("/api/programs?from=9223372036854775807").await;
+        assert!(res.status() == actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = get("/api/programs?to=-9223372036854775808").await;
+        assert!(res.status() == actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_search_programs() {
+        let res = get("/api/programs/search?q=test").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+
+        let res = get("/api/programs/search").await;
+        assert!(res.status() == actix_web::http::StatusCode::BAD_REQUEST);
     }
 
     #[actix_rt::test]
@@ -1120,6 +2280,206 @@ mod tests {
         assert!(res.status() == actix_web::http::StatusCode::OK);
     }
 
+    #[actix_rt::test]
+    async fn test_delete_tuner() {
+        let res = delete("/api/tuners/0").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NO_CONTENT);
+
+        let res = delete("/api/tuners/1").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_streams() {
+        let res = get("/api/streams").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_delete_stream() {
+        let res = delete("/api/streams/0.1").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NO_CONTENT);
+
+        let res = delete("/api/streams/0.2").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+
+        let res = delete("/api/streams/invalid").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_config() {
+        let res = get("/api/config/server").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let res = get("/api/config/channels").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let res = get("/api/config/tuners").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_put_config() {
+        async fn put(
+            uri: &str,
+            body: serde_json::Value,
+            config: Arc<Config>,
+        ) -> actix_web::HttpResponse {
+            let req = actix_web::test::TestRequest::with_uri(uri)
+                .method(actix_web::http::Method::PUT)
+                .set_json(&body)
+                .to_request();
+            request_with_config(req, config).await
+        }
+
+        let dir = TempDir::new("web");
+        let path = dir.path().join("config.yml");
+        std::fs::write(&path, "{}").unwrap();
+
+        let tuners = serde_json::json!([
+            {"name": "tuner", "types": ["GR"], "command": "cmd"},
+        ]);
+
+        // read-only by default
+        let res = put("/api/config/tuners", tuners.clone(),
+                      config_for_test()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let mut config = (*config_for_test()).clone();
+        config.path = Some(path.to_str().unwrap().to_string());
+        config.mirakurun.writable_config = true;
+        let config = Arc::new(config);
+
+        let res = put("/api/config/tuners", tuners.clone(),
+                      config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(config::load(path.to_str().unwrap()).tuners.len(), 1);
+
+        let res = put("/api/config/channels", serde_json::json!([
+            {"name": "", "type": "GR", "channel": "27"},
+        ]), config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = put("/api/config/server", serde_json::json!({
+            "port": 40772,
+        }), config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(config::load(path.to_str().unwrap()).server.addrs,
+                   vec![ServerAddr::Http("0.0.0.0:40772".to_string())]);
+
+        let res = put("/api/config/server", serde_json::json!({}),
+                      config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_rt::test]
+    async fn test_scan_channels() {
+        async fn put(
+            uri: &str,
+            config: Arc<Config>,
+        ) -> actix_web::HttpResponse {
+            let req = actix_web::test::TestRequest::with_uri(uri)
+                .method(actix_web::http::Method::PUT)
+                .to_request();
+            request_with_config(req, config).await
+        }
+
+        // read-only by default
+        let res = put("/api/config/channels/scan", config_for_test()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+        let res = put(
+            "/api/config/channels/scan?minCh=27&maxCh=27&dryRun=true",
+            config_for_test()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let mut config = (*config_for_test()).clone();
+        config.mirakurun.writable_config = true;
+        let config = Arc::new(config);
+
+        let res = put("/api/config/channels/scan?type=SKY&dryRun=true",
+                      config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = put(
+            "/api/config/channels/scan?minCh=30&maxCh=20&dryRun=true",
+            config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::BAD_REQUEST);
+
+        let res = put(
+            "/api/config/channels/scan?minCh=27&maxCh=27&dryRun=true",
+            config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(res.headers().get("content-type")
+                   .and_then(|v| v.to_str().ok()),
+                   Some("text/plain; charset=utf-8"));
+    }
+
+    #[actix_rt::test]
+    async fn test_get_ui() {
+        let res = get("/ui").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers().get("location")
+                   .and_then(|v| v.to_str().ok()), Some("ui/"));
+
+        let res = get("/ui/").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(res.headers().get("content-type")
+                   .and_then(|v| v.to_str().ok()),
+                   Some("text/html; charset=utf-8"));
+
+        let res = get("/ui/app.js").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+        assert_eq!(res.headers().get("content-type")
+                   .and_then(|v| v.to_str().ok()),
+                   Some("application/javascript; charset=utf-8"));
+
+        let res = get("/ui/style.css").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let res = get("/ui/not-found").await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_get_jobs() {
+        let res = get("/api/jobs").await;
+        assert!(res.status() == actix_web::http::StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_run_job() {
+        let res = post("/api/jobs/scan-services/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::ACCEPTED);
+
+        let res = post("/api/jobs/sync-clocks/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::CONFLICT);
+
+        let res = post("/api/jobs/update-schedules/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::ACCEPTED);
+
+        let res = post("/api/jobs/collect-logos/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::ACCEPTED);
+
+        let res = post("/api/jobs/custom/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::ACCEPTED);
+
+        let res = post("/api/jobs/unknown/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
+
+        let res = get("/api/jobs/scan-services/run").await;
+        assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
+    }
+
+    #[actix_rt::test]
+    async fn test_cancel_job() {
+        let res = post("/api/jobs/scan-services/cancel").await;
+        assert!(res.status() == actix_web::http::StatusCode::ACCEPTED);
+
+        let res = post("/api/jobs/unknown/cancel").await;
+        assert!(res.status() == actix_web::http::StatusCode::NOT_FOUND);
+    }
+
     #[actix_rt::test]
     async fn test_get_channel_stream() {
         let res = get("/api/channels/GR/ch/stream").await;
@@ -1246,6 +2606,34 @@ mod tests {
         }
     }
 
+    #[actix_rt::test]
+    async fn test_streaming_quotas() {
+        let mut config = (*config_for_test()).clone();
+        config.server.quotas.per_client.max_concurrent_streams = Some(0);
+        let config = Arc::new(config);
+
+        let req = actix_web::test::TestRequest::with_uri(
+            "/api/programs/100001/stream")
+            .method(actix_web::http::Method::GET)
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+
+        let req = actix_web::test::TestRequest::with_uri(
+            "/api/services/1/stream")
+            .method(actix_web::http::Method::GET)
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::TOO_MANY_REQUESTS);
+
+        // Other endpoints are not limited.
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(actix_web::http::Method::GET)
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    }
+
     #[actix_rt::test]
     async fn test_get_iptv_playlist() {
         let res = get("/api/iptv/playlist").await;
@@ -1269,13 +2657,327 @@ mod tests {
         assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
     }
 
+    #[actix_rt::test]
+    async fn test_access_control_with_trusted_proxies() {
+        let mut config = (*config_for_test()).clone();
+        config.server.access_control.trusted_proxies =
+            vec!["127.0.0.1".parse().unwrap()];
+        let config = Arc::new(config);
+
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(actix_web::http::Method::GET)
+            .peer_addr("127.0.0.1:10000".parse().unwrap())
+            .header("X-Forwarded-For", "192.168.0.1")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(actix_web::http::Method::GET)
+            .peer_addr("127.0.0.1:10000".parse().unwrap())
+            .header("X-Forwarded-For", "192.168.0.1, 8.8.8.8")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(actix_web::http::Method::GET)
+            .peer_addr("127.0.0.1:10000".parse().unwrap())
+            .header("Forwarded", "for=8.8.8.8")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+        // Forwarded headers from untrusted peers are ignored.
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(actix_web::http::Method::GET)
+            .peer_addr("192.168.0.2:10000".parse().unwrap())
+            .header("X-Forwarded-For", "8.8.8.8")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), actix_web::http::StatusCode::OK);
+    }
+
+    #[actix_rt::test]
+    async fn test_authentication() {
+        async fn do_request(
+            method: actix_web::http::Method,
+            uri: &str,
+            authorization: Option<&str>,
+        ) -> actix_web::HttpResponse {
+            let mut config = (*config_for_test()).clone();
+            config.server.auth = AuthConfig {
+                tokens: vec![
+                    AuthTokenConfig {
+                        token: "secret".to_string(),
+                        scopes: vec![AuthScope::Read, AuthScope::Stream],
+                    },
+                    AuthTokenConfig {
+                        token: "admin".to_string(),
+                        scopes: vec![AuthScope::Admin],
+                    },
+                ],
+                users: vec![
+                    AuthUserConfig {
+                        name: "user".to_string(),
+                        password: "pass".to_string(),
+                        scopes: vec![AuthScope::Read],
+                    },
+                ],
+            };
+            let mut req = actix_web::test::TestRequest::with_uri(uri)
+                .method(method);
+            if let Some(authorization) = authorization {
+                req = req.header("authorization", authorization);
+            }
+            request_with_config(req.to_request(), Arc::new(config)).await
+        }
+
+        use actix_web::http::Method;
+        use actix_web::http::StatusCode;
+
+        let res = do_request(Method::GET, "/api/version", None).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        assert!(res.headers().contains_key("www-authenticate"));
+
+        let res = do_request(
+            Method::GET, "/api/version", Some("Bearer secret")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = do_request(
+            Method::GET, "/api/version", Some("Bearer wrong")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        let res = do_request(Method::GET, "/api/version?token=secret", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = do_request(
+            Method::GET, "/api/programs/100001/stream?token=secret", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = do_request(Method::GET, "/api/jobs?token=secret", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = do_request(Method::GET, "/api/jobs?token=admin", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = do_request(Method::GET, "/api/streams?token=secret", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let res = do_request(Method::GET, "/api/streams?token=admin", None)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // user:pass
+        let res = do_request(
+            Method::GET, "/api/version", Some("Basic dXNlcjpwYXNz")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = do_request(
+            Method::GET, "/api/programs/100001/stream",
+            Some("Basic dXNlcjpwYXNz")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // user:wrong
+        let res = do_request(
+            Method::GET, "/api/version", Some("Basic dXNlcjp3cm9uZw==")).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_rt::test]
+    async fn test_cors() {
+        use actix_web::http::Method;
+        use actix_web::http::StatusCode;
+        use actix_web::http::header;
+
+        let mut config = (*config_for_test()).clone();
+        config.server.cors.allowed_origins =
+            vec!["http://localhost:8080".to_string()];
+        config.server.auth.tokens = vec![
+            AuthTokenConfig {
+                token: "secret".to_string(),
+                scopes: vec![AuthScope::Read],
+            },
+        ];
+        let config = Arc::new(config);
+
+        // preflight
+        let req = actix_web::test::TestRequest::with_uri("/api/programs")
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "Authorization")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                   .and_then(|value| value.to_str().ok()),
+                   Some("http://localhost:8080"));
+        assert!(res.headers().contains_key(header::ACCESS_CONTROL_MAX_AGE));
+
+        let req = actix_web::test::TestRequest::with_uri("/api/programs")
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "DELETE")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = actix_web::test::TestRequest::with_uri("/api/programs")
+            .method(Method::OPTIONS)
+            .header(header::ORIGIN, "http://example.com")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        // actual requests
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(Method::GET)
+            .header(header::ORIGIN, "http://localhost:8080")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                   .and_then(|value| value.to_str().ok()),
+                   Some("http://localhost:8080"));
+
+        let req = actix_web::test::TestRequest::with_uri("/api/version")
+            .method(Method::GET)
+            .header(header::ORIGIN, "http://example.com")
+            .header(header::AUTHORIZATION, "Bearer secret")
+            .to_request();
+        let res = request_with_config(req, config.clone()).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+
+    #[test]
+    fn test_allow_origin_value() {
+        let mut config = CorsConfig {
+            allowed_origins: vec!["http://localhost:8080".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(allow_origin_value(&config, "http://localhost:8080"),
+                   Some("http://localhost:8080".to_string()));
+        assert_eq!(allow_origin_value(&config, "http://example.com"), None);
+
+        config.allowed_origins = vec!["*".to_string()];
+        assert_eq!(allow_origin_value(&config, "http://example.com"),
+                   Some("*".to_string()));
+    }
+
+    #[test]
+    fn test_credential_from_authorization() {
+        assert_matches!(
+            Credential::from_authorization("Bearer secret"),
+            Some(Credential::Token(token)) if token == "secret");
+        assert_matches!(
+            Credential::from_authorization("basic dXNlcjpwYXNz"),
+            Some(Credential::User(name, password))
+                if name == "user" && password == "pass");
+        assert_matches!(Credential::from_authorization("Basic !!!"), None);
+        assert_matches!(Credential::from_authorization("Digest xxx"), None);
+        assert_matches!(Credential::from_authorization("Bearer"), None);
+    }
+
+    #[test]
+    fn test_is_allowed_ip_addr() {
+        let config = AccessControlConfig::default();
+        assert!(is_allowed_ip_addr(&config, "127.0.0.1".parse().unwrap()));
+        assert!(is_allowed_ip_addr(&config, "192.168.0.1".parse().unwrap()));
+        assert!(is_allowed_ip_addr(&config, "fd00::1".parse().unwrap()));
+        assert!(!is_allowed_ip_addr(&config, "8.8.8.8".parse().unwrap()));
+
+        let config = AccessControlConfig {
+            allow: vec!["8.8.8.0/24".parse().unwrap()],
+            deny: vec!["8.8.8.8".parse().unwrap()],
+            trusted_proxies: vec![],
+        };
+        assert!(!is_allowed_ip_addr(&config, "127.0.0.1".parse().unwrap()));
+        assert!(is_allowed_ip_addr(&config, "8.8.8.1".parse().unwrap()));
+        assert!(!is_allowed_ip_addr(&config, "8.8.8.8".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_client_ip_addr() {
+        let config = AccessControlConfig {
+            allow: vec![],
+            deny: vec![],
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        };
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert_eq!(client_ip_addr(&config, ip("10.0.0.1"), vec![]),
+                   Some(ip("10.0.0.1")));
+        assert_eq!(
+            client_ip_addr(&config, ip("10.0.0.1"),
+                           vec![Some(ip("8.8.8.8")), Some(ip("10.0.0.2"))]),
+            Some(ip("8.8.8.8")));
+        assert_eq!(
+            client_ip_addr(&config, ip("10.0.0.1"),
+                           vec![Some(ip("10.0.0.3")), Some(ip("8.8.8.8"))]),
+            Some(ip("8.8.8.8")));
+        assert_eq!(
+            client_ip_addr(&config, ip("192.168.0.1"),
+                           vec![Some(ip("8.8.8.8"))]),
+            Some(ip("192.168.0.1")));
+        assert_eq!(client_ip_addr(&config, ip("10.0.0.1"), vec![None]), None);
+    }
+
     #[test]
-    fn test_is_private_ip_addr() {
-        assert!(is_private_ip_addr("127.0.0.1".parse().unwrap()));
-        assert!(is_private_ip_addr("10.0.0.1".parse().unwrap()));
-        assert!(is_private_ip_addr("172.16.0.1".parse().unwrap()));
-        assert!(is_private_ip_addr("192.168.0.1".parse().unwrap()));
-        assert!(!is_private_ip_addr("8.8.8.8".parse().unwrap()));
+    fn test_forwarded_addrs() {
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+        let mut headers = actix_web::http::HeaderMap::new();
+        headers.append(
+            actix_web::http::header::FORWARDED,
+            "for=192.0.2.60;proto=http, For=\"[2001:db8::1]:4711\""
+                .parse().unwrap());
+        headers.append(
+            actix_web::http::header::FORWARDED,
+            "by=203.0.113.43;for=\"198.51.100.17:8080\", for=_hidden"
+                .parse().unwrap());
+        headers.append(
+            actix_web::http::header::HeaderName::from_static(
+                "x-forwarded-for"),
+            "8.8.8.8".parse().unwrap());
+        assert_eq!(forwarded_addrs(&headers), vec![
+            ip("192.0.2.60"),
+            ip("2001:db8::1"),
+            ip("198.51.100.17"),
+            None,
+        ]);
+
+        let mut headers = actix_web::http::HeaderMap::new();
+        headers.append(
+            actix_web::http::header::HeaderName::from_static(
+                "x-forwarded-for"),
+            "192.0.2.60, 2001:db8::1".parse().unwrap());
+        assert_eq!(forwarded_addrs(&headers), vec![
+            ip("192.0.2.60"),
+            ip("2001:db8::1"),
+        ]);
+
+        let mut headers = actix_web::http::HeaderMap::new();
+        for addr in &["192.0.2.1", "192.0.2.2", "192.0.2.3"] {
+            headers.append(
+                actix_web::http::header::HeaderName::from_static(
+                    "x-forwarded-for"),
+                addr.parse().unwrap());
+        }
+        assert_eq!(forwarded_addrs(&headers), vec![
+            ip("192.0.2.1"),
+            ip("192.0.2.2"),
+            ip("192.0.2.3"),
+        ]);
     }
 
     #[actix_rt::test]
@@ -1428,6 +3130,26 @@ mod tests {
             if let Some(_) = msg.downcast_ref::<QueryTunersMessage>() {
                 Box::<Option<Result<Vec<MirakurunTuner>, Error>>>::new(
                     Some(Ok(Vec::new())))
+            } else if let Some(_) = msg.downcast_ref::<QueryStreamsMessage>() {
+                let stream = StreamInfo {
+                    id: "0.1".parse().unwrap(),
+                    tuner_index: 0,
+                    channel_type: ChannelType::GR,
+                    channel: "ch".to_string(),
+                    user: TunerUserInfo::Web { remote: None, agent: None },
+                    priority: 0.into(),
+                    started_at: Jst::now(),
+                    bytes_sent: 0,
+                    filters: Vec::new(),
+                };
+                Box::<Option<Result<Vec<StreamInfo>, Error>>>::new(
+                    Some(Ok(vec![stream])))
+            } else if let Some(msg) = msg.downcast_ref::<DeactivateTunerMessage>() {
+                let result = match msg.index {
+                    0 => Ok(()),
+                    _ => Err(Error::TunerNotFound),
+                };
+                Box::<Option<Result<(), Error>>>::new(Some(result))
             } else if let Some(msg) = msg.downcast_ref::<StartStreamingMessage>() {
                 if msg.channel.channel == "ch" {
                     let (mut tx, stream) = BroadcasterStream::new_for_test();
@@ -1443,6 +3165,13 @@ mod tests {
                 }
             } else if let Some(_) = msg.downcast_ref::<StopStreamingMessage>() {
                 Box::<Option<()>>::new(Some(()))
+            } else if let Some(msg) = msg.downcast_ref::<KillStreamMessage>() {
+                let result = if msg.id == "0.1".parse().unwrap() {
+                    Ok(())
+                } else {
+                    Err(Error::StreamNotFound)
+                };
+                Box::<Option<Result<(), Error>>>::new(Some(result))
             } else {
                 unimplemented!();
             }
@@ -1451,7 +3180,10 @@ mod tests {
 
     fn epg_for_test() -> Addr<EpgActor> {
         EpgActor::mock(Box::new(|msg, _| {
-            if let Some(_) = msg.downcast_ref::<QueryChannelsMessage>() {
+            if let Some(_) = msg.downcast_ref::<QueryEpgStatusMessage>() {
+                Box::<Option<Result<EpgStatus, Error>>>::new(
+                    Some(Ok(Default::default())))
+            } else if let Some(_) = msg.downcast_ref::<QueryChannelsMessage>() {
                 Box::<Option<Result<Vec<MirakurunChannel>, Error>>>::new(
                     Some(Ok(Vec::new())))
             } else if let Some(msg) = msg.downcast_ref::<QueryChannelMessage>() {
@@ -1498,11 +3230,20 @@ mod tests {
                                     services: Vec::new(),
                                     excluded_services: Vec::new(),
                                 },
+                                has_logo_data: false,
                             })
                         }
                     }
                 };
                 Box::<Option<Result<EpgService, Error>>>::new(Some(result))
+            } else if let Some(msg) = msg.downcast_ref::<QueryLogoMessage>() {
+                let result = match msg.sid.value() {
+                    0 => Err(Error::ServiceNotFound),
+                    1 => Ok(std::path::PathBuf::from("/dev/null")),
+                    _ => Err(Error::LogoNotFound),
+                };
+                Box::<Option<Result<std::path::PathBuf, Error>>>::new(
+                    Some(result))
             } else if let Some(msg) = msg.downcast_ref::<QueryClockMessage>() {
                 let result = match msg.triple.sid().value() {
                     0 => Err(Error::ClockNotSynced),
@@ -1512,6 +3253,21 @@ mod tests {
             } else if let Some(_) = msg.downcast_ref::<QueryProgramsMessage>() {
                 Box::<Option<Result<Vec<EpgProgram>, Error>>>::new(
                     Some(Ok(Vec::new())))
+            } else if let Some(_) =
+                msg.downcast_ref::<QueryProgramsWithFilterMessage>() {
+                Box::<Option<Result<Vec<EpgProgram>, Error>>>::new(
+                    Some(Ok(Vec::new())))
+            } else if let Some(msg) =
+                msg.downcast_ref::<QueryServiceProgramsMessage>() {
+                let result = match msg.sid.value() {
+                    0 => Err(Error::ServiceNotFound),
+                    _ => Ok(Vec::new()),
+                };
+                Box::<Option<Result<Vec<EpgProgram>, Error>>>::new(
+                    Some(result))
+            } else if let Some(_) = msg.downcast_ref::<SearchProgramsMessage>() {
+                Box::<Option<Result<Vec<EpgProgram>, Error>>>::new(
+                    Some(Ok(Vec::new())))
             } else if let Some(msg) = msg.downcast_ref::<QueryProgramMessage>() {
                 let result = match msg {
                     QueryProgramMessage::ByNidSidEid { nid, sid, eid } => {
@@ -1531,4 +3287,43 @@ mod tests {
             }
         })).start()
     }
+
+    fn job_manager_for_test() -> Addr<JobManagerActor> {
+        JobManagerActor::mock(Box::new(|msg, _| {
+            if let Some(_) = msg.downcast_ref::<QueryJobsMessage>() {
+                Box::<Option<Result<Vec<JobInfo>, Error>>>::new(
+                    Some(Ok(Vec::new())))
+            } else if let Some(_) =
+                msg.downcast_ref::<InvokeScanServicesMessage>() {
+                Box::<Option<Result<(), Error>>>::new(Some(Ok(())))
+            } else if let Some(_) =
+                msg.downcast_ref::<InvokeSyncClocksMessage>() {
+                Box::<Option<Result<(), Error>>>::new(
+                    Some(Err(Error::JobAlreadyRunning)))
+            } else if let Some(_) =
+                msg.downcast_ref::<InvokeUpdateSchedulesMessage>() {
+                Box::<Option<Result<(), Error>>>::new(Some(Ok(())))
+            } else if let Some(_) =
+                msg.downcast_ref::<InvokeCollectLogosMessage>() {
+                Box::<Option<Result<(), Error>>>::new(Some(Ok(())))
+            } else if let Some(msg) =
+                msg.downcast_ref::<InvokeCustomJobMessage>() {
+                let result = if msg.name == "custom" {
+                    Ok(())
+                } else {
+                    Err(Error::JobNotFound)
+                };
+                Box::<Option<Result<(), Error>>>::new(Some(result))
+            } else if let Some(msg) = msg.downcast_ref::<CancelJobMessage>() {
+                let result = if msg.name == "unknown" {
+                    Err(Error::JobNotFound)
+                } else {
+                    Ok(())
+                };
+                Box::<Option<Result<(), Error>>>::new(Some(result))
+            } else {
+                unimplemented!();
+            }
+        })).start()
+    }
 }
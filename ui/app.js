@@ -0,0 +1,307 @@
+'use strict';
+
+// The admin UI uses only the Web API of mirakc.
+//
+// A token for the authentication can be specified in the `token` query
+// parameter of the URL of this page.  The browser handles the HTTP Basic
+// authentication.
+
+const REFRESH_INTERVAL = 5000;  // ms
+const GUIDE_HOURS = 6;
+const HOUR_WIDTH = 240;  // px
+const HOUR = 3600 * 1000;  // ms
+
+const token = new URLSearchParams(location.search).get('token');
+
+let guideStart = startOfHour(Date.now());
+
+function apiUrl(path, params = {}) {
+  const url = new URL('../api' + path, location.href);
+  for (const [name, value] of Object.entries(params)) {
+    url.searchParams.set(name, value);
+  }
+  if (token) {
+    url.searchParams.set('token', token);
+  }
+  return url;
+}
+
+async function api(path, { method = 'GET', params } = {}) {
+  const res = await fetch(apiUrl(path, params), { method });
+  if (!res.ok) {
+    throw new Error(`${method} ${path}: ${res.status} ${res.statusText}`);
+  }
+  if (res.status === 204 || method !== 'GET') {
+    return null;
+  }
+  return res.json();
+}
+
+function el(tag, props = {}, ...children) {
+  const elem = document.createElement(tag);
+  Object.assign(elem, props);
+  for (const child of children) {
+    elem.append(child);
+  }
+  return elem;
+}
+
+function row(...cells) {
+  return el('tr', {}, ...cells.map((cell) => el('td', {}, cell)));
+}
+
+function showError(err) {
+  const elem = document.getElementById('error');
+  if (err) {
+    elem.textContent = err.message;
+    elem.hidden = false;
+  } else {
+    elem.hidden = true;
+  }
+}
+
+function startOfHour(ms) {
+  return Math.floor(ms / HOUR) * HOUR;
+}
+
+function formatTime(ms) {
+  return new Date(ms).toLocaleTimeString([], {
+    hour: '2-digit',
+    minute: '2-digit',
+  });
+}
+
+function formatDateTime(ms) {
+  return new Date(ms).toLocaleString();
+}
+
+function formatDuration(ms) {
+  return `${(ms / 1000).toFixed(1)}s`;
+}
+
+function tunerState(tuner) {
+  if (tuner.isFault) {
+    return 'fault';
+  }
+  if (tuner.isUsing) {
+    return 'using';
+  }
+  if (tuner.isFree) {
+    return 'free';
+  }
+  return 'unavailable';
+}
+
+function userLabel(user) {
+  return user.agent ? `${user.id} (${user.agent})` : user.id;
+}
+
+function streamUserLabel(info) {
+  switch (info.type) {
+  case 'job':
+    return `Job(${info.name})`;
+  case 'tracker':
+    return `Tracker(${info.streamId})`;
+  default:
+    return [info.remote, info.agent].filter((v) => v).join(' ') || 'Web';
+  }
+}
+
+function formatBytes(bytes) {
+  const units = ['B', 'KiB', 'MiB', 'GiB', 'TiB'];
+  let i = 0;
+  while (bytes >= 1024 && i < units.length - 1) {
+    bytes /= 1024;
+    i++;
+  }
+  return `${bytes.toFixed(i === 0 ? 0 : 1)} ${units[i]}`;
+}
+
+async function killStream(stream) {
+  if (!confirm(`Stop the stream ${stream.id}?`)) {
+    return;
+  }
+  try {
+    await api(`/streams/${stream.id}`, { method: 'DELETE' });
+    await refresh();
+  } catch (err) {
+    showError(err);
+  }
+}
+
+async function killTuner(tuner) {
+  if (!confirm(`Stop all streams using ${tuner.name}?`)) {
+    return;
+  }
+  try {
+    await api(`/tuners/${tuner.index}`, { method: 'DELETE' });
+    await refresh();
+  } catch (err) {
+    showError(err);
+  }
+}
+
+function renderTuners(tuners) {
+  const tunersBody = document.querySelector('#tuners tbody');
+  tunersBody.replaceChildren();
+
+  for (const tuner of tuners) {
+    const state = tunerState(tuner);
+    const session = tuner.session;
+    const users = tuner.users
+      .map((user) => `${userLabel(user)} [${user.priority}]`)
+      .join(', ');
+    const kill = el('button', {
+      type: 'button',
+      textContent: 'Kill',
+      disabled: !tuner.isUsing,
+      onclick: () => killTuner(tuner),
+    });
+    tunersBody.append(row(
+      String(tuner.index),
+      tuner.name,
+      tuner.types.join(', '),
+      el('span', { className: `state-${state}`, textContent: state }),
+      tuner.pid ? String(tuner.pid) : '',
+      session ? `${session.channelType}/${session.channel}` : '',
+      session ? `${formatBytes(session.bytesPerSecond)}/s` : '',
+      session ? String(session.droppedChunks) : '',
+      users,
+      kill));
+  }
+}
+
+function renderStreams(streams) {
+  const body = document.querySelector('#streams tbody');
+  body.replaceChildren();
+  for (const stream of streams) {
+    const kill = el('button', {
+      type: 'button',
+      textContent: 'Kill',
+      onclick: () => killStream(stream),
+    });
+    body.append(row(
+      stream.id,
+      `${stream.channelType}/${stream.channel}`,
+      streamUserLabel(stream.user),
+      String(stream.priority),
+      formatDateTime(stream.startedAt),
+      formatBytes(stream.bytesSent),
+      stream.filters.join(' | '),
+      kill));
+  }
+}
+
+function renderJobs(jobs) {
+  const body = document.querySelector('#jobs tbody');
+  body.replaceChildren();
+  for (const job of jobs) {
+    body.append(row(
+      job.name,
+      job.schedule,
+      job.nextScheduledAt ? formatDateTime(job.nextScheduledAt) : '',
+      job.isRunning ? 'running' : 'idle',
+      job.lastDuration !== null ? formatDuration(job.lastDuration) : '',
+      job.lastError || ''));
+  }
+}
+
+async function refresh() {
+  try {
+    renderTuners(await api('/tuners'));
+    renderStreams(await api('/streams'));
+    renderJobs(await api('/jobs'));
+    showError(null);
+  } catch (err) {
+    showError(err);
+  }
+}
+
+async function renderGuide() {
+  const start = guideStart;
+  const end = start + GUIDE_HOURS * HOUR;
+  const width = GUIDE_HOURS * HOUR_WIDTH;
+  const x = (ms) => (ms - start) / HOUR * HOUR_WIDTH;
+
+  document.getElementById('guide-range').textContent =
+    `${formatDateTime(start)} - ${formatTime(end)}`;
+
+  const grid = document.querySelector('#guide .grid');
+  grid.replaceChildren();
+
+  const header = el('div', { className: 'row header' },
+                    el('div', { className: 'service' }));
+  const timeline = el('div', { className: 'timeline' });
+  timeline.style.width = `${width}px`;
+  for (let ms = start; ms < end; ms += HOUR) {
+    const label = el('span', { textContent: formatTime(ms) });
+    label.style.left = `${x(ms)}px`;
+    timeline.append(label);
+  }
+  header.append(timeline);
+  grid.append(header);
+
+  try {
+    const services = await api('/services');
+    for (const service of services) {
+      const programs = await api(`/services/${service.id}/programs`, {
+        params: { from: start, to: end },
+      });
+      grid.append(renderGuideRow(service, programs, start, end, width, x));
+    }
+    showError(null);
+  } catch (err) {
+    showError(err);
+  }
+}
+
+function renderGuideRow(service, programs, start, end, width, x) {
+  const now = Date.now();
+  const timeline = el('div', { className: 'timeline' });
+  timeline.style.width = `${width}px`;
+  for (const program of programs) {
+    const programEnd = program.startAt + program.duration;
+    if (programEnd <= start || program.startAt >= end) {
+      continue;
+    }
+    const left = Math.max(x(program.startAt), 0);
+    const right = Math.min(x(programEnd), width);
+    const onAir = program.startAt <= now && now < programEnd;
+    const name = program.name || '';
+    const elem = el('div', {
+      className: onAir ? 'program on-air' : 'program',
+      textContent: `${formatTime(program.startAt)} ${name}`,
+      title: [name, program.description || ''].join('\n'),
+    });
+    elem.style.left = `${left}px`;
+    elem.style.width = `${right - left}px`;
+    timeline.append(elem);
+  }
+  return el('div', { className: 'row' },
+            el('div', { className: 'service', textContent: service.name }),
+            timeline);
+}
+
+document.getElementById('guide-prev').onclick = () => {
+  guideStart -= GUIDE_HOURS * HOUR;
+  renderGuide();
+};
+
+document.getElementById('guide-next').onclick = () => {
+  guideStart += GUIDE_HOURS * HOUR;
+  renderGuide();
+};
+
+setInterval(() => {
+  if (document.getElementById('auto-refresh').checked) {
+    refresh();
+  }
+}, REFRESH_INTERVAL);
+
+api('/version')
+  .then((version) => {
+    document.getElementById('version').textContent = version;
+  })
+  .catch(showError);
+refresh();
+renderGuide();